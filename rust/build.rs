@@ -0,0 +1,26 @@
+use std::process::Command;
+
+/// Embeds the git commit the binary is built from, recorded in each run's manifest.json.
+fn main() {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    if let Some(commit) = git(&["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+    }
+    if let Some(status) = git(&["status", "--porcelain", "--untracked-files=no"]) {
+        println!("cargo:rustc-env=GIT_DIRTY={}", !status.is_empty());
+    }
+    // Rebuild when the checked out commit or the staged files change
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+        println!("cargo:rerun-if-changed={}/refs/heads", git_dir);
+    }
+    println!("cargo:rerun-if-changed=src");
+}
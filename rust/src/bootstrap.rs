@@ -0,0 +1,80 @@
+use crate::console;
+use crate::preprocessing;
+use crate::sampling::{Sampler, Sampling, SamplingConfig};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Draws `count` samples of each size from the .txt files under `source_dir`
+/// into `output_dir/N_{size}/sample_{j}`, the layout the benchmark reads.
+///
+/// Files within a sample are drawn without replacement, stratified or in whole
+/// groups as `sampling` asks. With `index_only`,
+/// each sample is written as `sample_{j}.list` referencing the original files
+/// instead of copying them.
+pub fn run(source_dir: &str, sizes: &[usize], count: usize, output_dir: &str, seed: u64, index_only: bool, sampling: &SamplingConfig) -> Result<(), Box<dyn Error>> {
+    let mut files = preprocessing::input_files(source_dir)?;
+    // Walk order is platform dependent; sort so a seed always gives the same samples
+    files.sort();
+    progress!("Found {} text files in {}", files.len(), source_dir);
+    let sampler = Sampler::new(&files, Path::new(source_dir), sampling)?;
+    if sampling.sampling != Sampling::Simple {
+        progress!("Sampling {}", sampler.describe());
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for &size in sizes {
+        if size > files.len() {
+            return Err(format!("Sample size {} exceeds the {} available files", size, files.len()).into());
+        }
+
+        let size_dir = Path::new(output_dir).join(format!("N_{}", size));
+        fs::create_dir_all(&size_dir)?;
+
+        for j in 1..=count {
+            let sample: Vec<&PathBuf> = sampler.draw(size, &mut rng).into_iter().map(|idx| &files[idx]).collect();
+            if sample.len() < size {
+                progress!("Sample {} of size {} has {} documents: no remaining group fits", j, size, sample.len());
+            }
+            if index_only {
+                write_index(&size_dir.join(format!("sample_{}.list", j)), &sample)?;
+            } else {
+                copy_sample(&size_dir.join(format!("sample_{}", j)), &sample)?;
+            }
+        }
+        progress!("Created {} samples of {} documents in {}", count, size, size_dir.display());
+        console::artifact(&format!("N_{}", size), &size_dir);
+    }
+
+    Ok(())
+}
+
+fn write_index(path: &Path, sample: &[&PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for file in sample {
+        writeln!(writer, "{}", file.canonicalize()?.display())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn copy_sample(sample_dir: &Path, sample: &[&PathBuf]) -> Result<(), Box<dyn Error>> {
+    if sample_dir.exists() {
+        fs::remove_dir_all(sample_dir)?;
+    }
+    fs::create_dir_all(sample_dir)?;
+
+    for (copied, file) in sample.iter().enumerate() {
+        let name = file.file_name().ok_or("File path has no name")?;
+        let mut dest = sample_dir.join(name);
+        // Different source folders can contain files with the same name
+        if dest.exists() {
+            dest = sample_dir.join(format!("{}_{}", copied, name.to_string_lossy()));
+        }
+        fs::copy(file, dest)?;
+    }
+    Ok(())
+}
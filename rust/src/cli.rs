@@ -0,0 +1,913 @@
+use preproccess::compression::Compression;
+use preproccess::cooccurrence::CooccurrenceWeighting;
+use preproccess::dynamic::TimeSlice;
+use preproccess::generate::CorpusFormat;
+use preproccess::modeling::{Background, Convergence, Idf, Solver};
+use preproccess::preprocessing::{StemmerKind, StopwordStage};
+use preproccess::sampling::{SampleField, Sampling, SamplingConfig};
+use preproccess::stability::Similarity;
+use preproccess::tokenizer::{CharNgrams, HyphenMode, NumberMode};
+use preproccess::tune::{SearchSpace, TuneMetric, TuneOptions};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Debug, Parser)]
+#[command(about = "NMF topic modeling pipeline and benchmark harness")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Fraction of each dataset held out of the fit to report out-of-sample error
+    #[arg(long, default_value_t = 0.0)]
+    pub holdout: f32,
+
+    /// Write per-topic cluster membership files after modeling
+    #[arg(long)]
+    pub clusters: bool,
+
+    /// Write each topic's N highest weighted documents, with file paths and text
+    /// snippets, to topic_examples/topic_<i>.csv
+    #[arg(long)]
+    pub topic_examples: Option<usize>,
+
+    /// NMF update rule [default: mu]
+    #[arg(long, value_enum, global = true)]
+    pub solver: Option<Solver>,
+
+    /// Model hyperparameters (k, min_df, max_df, lambda, solver) from a TOML file such as
+    /// the pipeline.toml written by `tune`; options given on the command line take precedence
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Stop NMF when the error on this share of matrix entries, hidden from the fit,
+    /// stops improving (instead of the training error tolerance)
+    #[arg(long, global = true, value_parser = parse_fraction)]
+    pub validation_fraction: Option<f32>,
+
+    /// Iterations without validation improvement before stopping
+    #[arg(long, default_value_t = 5, global = true)]
+    pub patience: usize,
+
+    /// Error the NMF improvement per iteration is measured against for the tolerance
+    #[arg(long, value_enum, default_value_t = Convergence::Initial, global = true)]
+    pub convergence: Convergence,
+
+    /// Stop NMF once the relative reconstruction error ‖V − WH‖ / ‖V‖ falls to this
+    #[arg(long, global = true)]
+    pub error_floor: Option<f32>,
+
+    /// Stop NMF once the projected gradient norm falls to this share of its initial norm
+    #[arg(long, global = true)]
+    pub gradient_tol: Option<f32>,
+
+    /// Stop NMF after this many seconds
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub time_budget: Option<f64>,
+
+    /// Save W and H to nmf_checkpoint.json in the workdir every this many NMF iterations
+    #[arg(long)]
+    pub checkpoint_every: Option<usize>,
+
+    /// Start NMF from a checkpoint, or from the topics of a saved model such as one fit
+    /// on another bootstrap sample
+    #[arg(long)]
+    pub warm_start: Option<String>,
+
+    /// CSV of document weights in the NMF objective: a key column (file path, file name
+    /// or corpus row id) and a `weight` column; unlisted documents weigh 1
+    /// (multiplicative updates only)
+    #[arg(long)]
+    pub document_weights: Option<String>,
+
+    /// Keep the document-term matrix in a memory-mapped file in the workdir instead of
+    /// memory, for corpora too large for RAM (multiplicative updates only)
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// Most MiB the dense document-term matrix may take. A larger matrix is kept in a
+    /// memory-mapped file as with --mmap where the fit allows it, otherwise the run
+    /// fails suggesting a min_df (see --config) that fits
+    #[arg(long, value_parser = parse_mib)]
+    pub max_memory: Option<f64>,
+
+    /// Write the topic-word matrix H to topic_word_matrix.csv in the workdir
+    #[arg(long)]
+    pub topic_words: bool,
+
+    /// Export only each topic's N highest weighted terms, one row per topic and term
+    #[arg(long, requires = "topic_words")]
+    pub topic_words_top: Option<usize>,
+
+    /// IDF formula of the TF-IDF weights; standard and smooth match scikit-learn's
+    /// TfidfTransformer with smooth_idf=False and True
+    #[arg(long, value_enum, default_value_t = Idf::Smooth, global = true)]
+    pub idf: Idf,
+
+    /// Dedicate the last topic to the corpus-wide term weights so common words gather
+    /// there, updating it like the others (free) or holding it fixed
+    #[arg(long, value_enum)]
+    pub background: Option<Background>,
+
+    /// Write each document's N highest weighted TF-IDF terms to keywords.csv in the workdir
+    #[arg(long)]
+    pub keywords: Option<usize>,
+
+    /// Also write each document's N highest weighted topics to document_top_topics.csv
+    /// in the workdir, one (document, topic, weight) row per topic
+    #[arg(long)]
+    pub top_topics: Option<usize>,
+
+    /// Scale each document's topic weights to sum to 1 in the written distributions
+    #[arg(long)]
+    pub normalize_topics: bool,
+
+    /// Words listed per topic in printed topics, metrics and reports
+    #[arg(long, default_value_t = 10, global = true)]
+    pub top_words: usize,
+
+    /// Leave words weighted at most this much in H out of the listed topic words
+    #[arg(long, default_value_t = 0.001, global = true)]
+    pub min_word_weight: f32,
+
+    /// Split every topic into this many subtopics and write the tree to topic_tree.json
+    #[arg(long)]
+    pub subtopics: Option<usize>,
+
+    /// TOML file with seed word lists for the leading topics
+    #[arg(long)]
+    pub seed_topics: Option<String>,
+
+    /// Strength of the seed word prior
+    #[arg(long, default_value_t = 1.0)]
+    pub seed_strength: f32,
+
+    /// Treat terms occurring in more than this share of documents as stopwords
+    /// and list them in auto_stopwords.txt
+    #[arg(long)]
+    pub auto_stopwords: Option<f32>,
+
+    /// Drop vocabulary terms matching this regex, e.g. '^[a-z]{1,2}$'; may be repeated.
+    /// Terms are matched after stemming and the dropped ones listed in pruned_terms.txt
+    #[arg(long = "exclude-pattern", value_name = "REGEX")]
+    pub exclude_patterns: Vec<String>,
+
+    /// Drop vocabulary terms with a character repeated this many times in a row
+    #[arg(long, value_name = "N")]
+    pub max_repeat: Option<usize>,
+
+    /// File of vocabulary terms to drop, one per line
+    #[arg(long)]
+    pub vocab_blocklist: Option<String>,
+
+    /// File of the only vocabulary terms to keep, one per line
+    #[arg(long)]
+    pub vocab_allowlist: Option<String>,
+
+    /// Vocabulary file shared by all datasets; built from the first dataset if missing
+    #[arg(long)]
+    pub vocab: Option<String>,
+
+    /// Text column (CSV) or field (JSON Lines) when the input is a corpus file
+    #[arg(long, default_value = "text", global = true)]
+    pub text_column: String,
+
+    /// Id column or field for corpus files; defaults to the row number
+    #[arg(long, global = true)]
+    pub id_column: Option<String>,
+
+    /// Leave documents without vocabulary terms out of the factorization
+    #[arg(long)]
+    pub exclude_empty: bool,
+
+    /// Directory intermediate files (tokens, distributions, model) are written to
+    #[arg(long, default_value = ".", global = true)]
+    pub workdir: String,
+
+    /// Write intermediate files to a fresh directory under the system temp directory
+    #[arg(long, global = true, conflicts_with = "workdir")]
+    pub temp_workdir: bool,
+
+    /// CSV of document dates: a file path, file name or row id column and a `date`
+    /// column (YYYY-MM-DD); files not listed get their modification date
+    #[arg(long, global = true)]
+    pub dates: Option<String>,
+
+    /// Compress tokens, topic distributions and metrics CSVs
+    #[arg(long, value_enum, default_value_t = Compression::None, global = true)]
+    pub compress: Compression,
+
+    /// Regex matching whole tokens in the lowercased text, replacing the default cleaning
+    #[arg(long, global = true)]
+    pub token_pattern: Option<String>,
+
+    /// Regex of characters replaced by spaces before splitting into tokens
+    #[arg(long, global = true, conflicts_with = "token_pattern")]
+    pub strip_pattern: Option<String>,
+
+    /// Keep numbers and alphanumeric codes such as "iso27001" instead of stripping digits
+    #[arg(long, global = true)]
+    pub keep_digits: bool,
+
+    /// Handling of purely numeric tokens when digits are kept
+    #[arg(long, value_enum, default_value_t = NumberMode::Keep, global = true, requires = "keep_digits")]
+    pub numbers: NumberMode,
+
+    /// How hyphenated words are tokenized
+    #[arg(long, value_enum, default_value_t = HyphenMode::Split, global = true)]
+    pub hyphens: HyphenMode,
+
+    /// Remove URLs before tokenizing
+    #[arg(long, global = true)]
+    pub strip_urls: bool,
+
+    /// Remove email addresses before tokenizing
+    #[arg(long, global = true)]
+    pub strip_emails: bool,
+
+    /// Apply Unicode NFKC normalization (ligatures, full-width letters) before tokenizing
+    #[arg(long, global = true)]
+    pub nfkc: bool,
+
+    /// Strip accents before tokenizing, so "coöperate" and "cooperate" are one token
+    #[arg(long, global = true)]
+    pub fold_accents: bool,
+
+    /// Word list (one word per line, optionally with a count) misspelled tokens are
+    /// folded into before stemming, e.g. the vocabulary of a clean corpus
+    #[arg(long, global = true)]
+    pub spelling_words: Option<String>,
+
+    /// Most edits between a token and the listed word it is folded into
+    #[arg(long, default_value_t = 1, global = true, requires = "spelling_words")]
+    pub spelling_distance: usize,
+
+    /// Vectorize by character n-grams of these lengths (e.g. 3-5) within each word instead
+    /// of by words, without stopword removal or stemming; for morphologically rich
+    /// languages or noisy OCR text
+    #[arg(long, global = true, conflicts_with = "phrases")]
+    pub char_ngrams: Option<CharNgrams>,
+
+    /// Additional stopword file, one word per line; may be repeated
+    #[arg(long = "stopwords", global = true)]
+    pub stopword_files: Vec<String>,
+
+    /// Additional stopwords, comma separated
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub extra_stopwords: Vec<String>,
+
+    /// Don't include the built-in English stopword list
+    #[arg(long, global = true)]
+    pub no_default_stopwords: bool,
+
+    /// Filter stopwords before stemming, after it (matching stemmed stopwords, so
+    /// inflected forms and stopwords given as stems are caught), or both
+    #[arg(long, value_enum, default_value_t = StopwordStage::Before, global = true)]
+    pub stopword_stage: StopwordStage,
+
+    /// Drop tokens shorter than this many characters, before stemming
+    #[arg(long, value_name = "N", global = true)]
+    pub min_token_length: Option<usize>,
+
+    /// Drop tokens longer than this many characters, before stemming
+    #[arg(long, value_name = "N", global = true)]
+    pub max_token_length: Option<usize>,
+
+    /// Drop tokens matching this regex before stemming, e.g. '^x+$'; may be repeated.
+    /// Unlike --exclude-pattern, the tokens never reach tokens.csv
+    #[arg(long = "exclude-token-pattern", value_name = "REGEX", global = true)]
+    pub token_exclude_patterns: Vec<String>,
+
+    /// Stemming algorithm applied to tokens
+    #[arg(long, value_enum, default_value_t = StemmerKind::Snowball, global = true)]
+    pub stemmer: StemmerKind,
+
+    /// Language of the Snowball stemmer
+    #[arg(long, default_value = "english", global = true)]
+    pub stem_language: String,
+
+    /// Merge frequent token pairs ("new york" -> "new_york") before building the vocabulary
+    #[arg(long, global = true)]
+    pub phrases: bool,
+
+    /// Minimum number of occurrences for a pair to be merged
+    #[arg(long, default_value_t = 5, global = true)]
+    pub phrase_min_count: usize,
+
+    /// Minimum collocation score for a pair to be merged
+    #[arg(long, default_value_t = 10.0, global = true)]
+    pub phrase_threshold: f32,
+
+    /// Keep only words with these part-of-speech tag prefixes (e.g. NN,JJ)
+    #[cfg(feature = "pos")]
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub pos_tags: Option<Vec<String>>,
+
+    /// nlprule tokenizer binary used for part-of-speech tagging
+    #[cfg(feature = "pos")]
+    #[arg(long, default_value = "en_tokenizer.bin", global = true)]
+    pub pos_model: String,
+
+    /// Also write tokens, files and topic distributions as Arrow IPC (Feather) files
+    #[cfg(feature = "arrow")]
+    #[arg(long, global = true)]
+    pub arrow: bool,
+
+    /// Directory s3:// and https:// inputs are downloaded to and reused from
+    #[cfg(feature = "remote")]
+    #[arg(long, default_value = preproccess::remote::DEFAULT_CACHE_DIR, global = true)]
+    pub remote_cache: String,
+
+    /// Number of benchmark datasets run concurrently, each in its own child process
+    #[arg(long, default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Number of benchmark datasets fitted concurrently on threads of this process,
+    /// each fit single-threaded; only CPU time is measured per dataset, memory and
+    /// disk I/O are the whole process's
+    #[arg(long, default_value_t = 1, conflicts_with = "jobs")]
+    pub threads: usize,
+
+    /// Name of the benchmark's results directory under rust_metrics; defaults to the
+    /// current UTC time (YYYYMMDD-HHMMSS)
+    #[arg(long)]
+    pub run_id: Option<String>,
+
+    /// Keep every fitted model under rust_metrics/<run_id>/models for the stability command
+    #[arg(long)]
+    pub keep_models: bool,
+
+    /// Passes over each sample's datasets run before the measured iterations and left
+    /// out of the metrics, so cold file caches don't inflate the first iteration
+    #[arg(long, default_value_t = 0)]
+    pub warmup: usize,
+
+    /// Flag step times more than this many median absolute deviations from the median
+    /// of their sample, k and step as outliers in summary.csv and outliers.csv
+    #[arg(long, default_value_t = 3.0)]
+    pub outlier_mads: f64,
+
+    /// Check the benchmark's inputs and outputs and print the planned runs without running them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Continue an interrupted benchmark run, skipping the datasets it completed
+    #[arg(long, conflicts_with = "run_id")]
+    pub resume: Option<String>,
+
+    /// Metrics outputs written by the benchmark, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [MetricsFormat::Csv])]
+    pub metrics_format: Vec<MetricsFormat>,
+
+    /// Topic counts to run the benchmark grid with, e.g. 5,10,20,50, recording k in the
+    /// metrics; by default every dataset is modeled with the model's k only
+    #[arg(long, value_delimiter = ',')]
+    pub k_values: Option<Vec<usize>>,
+
+    /// Seed for random sampling (bootstrap samples, synthetic corpora, held-out split)
+    #[arg(long, default_value_t = 42, global = true)]
+    pub seed: u64,
+
+    /// Make outputs bit-identical across runs with the same --seed and thread count:
+    /// NMF initializations are drawn from --seed, each benchmark cell from a seed of its
+    /// own, and parallel cells are recorded in grid order rather than as they finish
+    #[arg(long, global = true, conflicts_with = "time_budget")]
+    pub deterministic: bool,
+
+    /// Print nothing to stdout but a JSON summary at the end: the command, whether it
+    /// succeeded, the artifacts written, key metrics and the topics. Errors still go to stderr
+    #[arg(long, global = true)]
+    pub porcelain: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MetricsFormat {
+    /// Flat N{sample}_metrics.csv
+    Csv,
+    /// N{sample}_metrics.jsonl with parameters and nested timings
+    Jsonl,
+    /// N{sample}_metrics.arrow with the CSV columns, typed
+    #[cfg(feature = "arrow")]
+    Arrow,
+    /// results.sqlite with steps, topics and documents' dominant topics of all sample sizes
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl MetricsFormat {
+    /// Whether the format stores every document's dominant topic.
+    pub fn stores_assignments(&self) -> bool {
+        match self {
+            MetricsFormat::Csv | MetricsFormat::Jsonl => false,
+            #[cfg(feature = "arrow")]
+            MetricsFormat::Arrow => false,
+            #[cfg(feature = "sqlite")]
+            MetricsFormat::Sqlite => true,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fit a model on a directory and keep processing documents added to it
+    Watch(WatchArgs),
+    /// Serve topic inference for a saved model over HTTP, with Prometheus metrics on /metrics
+    Serve(ServeArgs),
+    /// Label every document of a large directory with a saved model's topics, streaming
+    /// it in batches of bounded size
+    Score(ScoreArgs),
+    /// Generate bootstrap sample directories from a source corpus
+    Bootstrap(BootstrapArgs),
+    /// Generate a synthetic corpus from planted topics, with its ground truth
+    Generate(GenerateArgs),
+    /// List the documents most similar to a document in topic space
+    Similar(SimilarArgs),
+    /// Compare the saved model against a reference (e.g. scikit-learn) factorization
+    Validate(ValidateArgs),
+    /// Preprocess a dataset and track topic prevalence over its documents' dates
+    Dynamic(DynamicArgs),
+    /// Search model hyperparameters by cross-validation and write the best as pipeline.toml
+    Tune(TuneArgs),
+    /// Cluster the vocabulary by factorizing the transposed term-document matrix,
+    /// writing each word's cluster memberships and a lexicon per cluster
+    WordClusters(WordClustersArgs),
+    /// Count how often vocabulary terms occur near each other and export the
+    /// co-occurrence or PPMI matrix, e.g. for NPMI coherence or word graphs
+    Cooccurrence(CooccurrenceArgs),
+    /// Match the topics of two saved models by cosine similarity
+    CompareModels(CompareModelsArgs),
+    /// Record how the topics of a refit model descend from those of the previous model:
+    /// matched, split, merged, new or retired
+    TopicLineage(TopicLineageArgs),
+    /// Compare two preprocessing outputs: vocabulary added and removed, and how each
+    /// document's tokens changed
+    DiffCorpus(DiffCorpusArgs),
+    /// Measure how consistently topics reappear across models fit on bootstrap datasets
+    Stability(StabilityArgs),
+    /// Line up this crate's benchmark metrics with another implementation's by sample,
+    /// dataset and step, and summarize the speedup and memory ratio of each step
+    ReportCompare(ReportCompareArgs),
+    /// Fit a model on a precomputed document-term matrix, skipping tokenization and TF-IDF
+    FitMatrix(FitMatrixArgs),
+    /// Browse the saved model's topics, words and top documents in a terminal UI
+    #[cfg(feature = "tui")]
+    Explore(ExploreArgs),
+    /// Run one benchmark dataset and write its metrics as JSON (used by --jobs)
+    #[command(hide = true)]
+    Cell(CellArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Directory of .txt documents to monitor
+    pub input_dir: String,
+
+    /// Refit the model, warm-started from its topics, every time this many documents
+    /// have been added, and record the topics' lineage in topic_lineage.csv
+    #[arg(long)]
+    pub refit_every: Option<usize>,
+
+    /// Add the terms of new documents that reach min_df to the model's vocabulary as
+    /// they arrive, keeping the columns of known terms, and refit over the grown
+    /// vocabulary (written to watch_vocabulary.txt) instead of rebuilding it
+    #[arg(long)]
+    pub grow_vocabulary: bool,
+
+    /// Serve Prometheus metrics (documents processed, fit duration, reconstruction
+    /// error, memory) on /metrics at this address, e.g. 127.0.0.1:9090
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ScoreArgs {
+    /// Directory of documents, or an index file listing one path per line
+    pub input: String,
+
+    /// Saved model written by the modeling step; defaults to nmf_model.json in the working directory
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Dominant topic and topic distribution of each file; .gz or .zst compresses it
+    #[arg(long, default_value = "scores.csv")]
+    pub output: String,
+
+    /// Documents held in memory, projected and written at a time
+    #[arg(long, default_value_t = 1000)]
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Saved model written by the modeling step; defaults to nmf_model.json in the working directory
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub addr: String,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Args)]
+pub struct ExploreArgs {
+    /// Saved model to explore; defaults to nmf_model.json in the working directory
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Highest weighted documents listed per topic
+    #[arg(long, default_value_t = 20)]
+    pub documents: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct BootstrapArgs {
+    /// Directory containing the source .txt corpus
+    pub source_dir: String,
+
+    /// Sample sizes to generate
+    #[arg(long, value_delimiter = ',', default_values_t = [100, 250, 500, 750, 1000])]
+    pub sizes: Vec<usize>,
+
+    /// Number of samples per size
+    #[arg(long, default_value_t = 100)]
+    pub count: usize,
+
+    /// Directory the N_{size}/sample_{j} layout is written to
+    #[arg(long, default_value = "../bootstrap_samples")]
+    pub output: String,
+
+    /// Write sample_{j}.list files referencing the originals instead of copying
+    #[arg(long)]
+    pub index: bool,
+
+    /// Keep each value's share of the files in every sample: "subdir" (top-level
+    /// subdirectory), "year" (from --metadata dates or modification times), or a
+    /// --metadata column
+    #[arg(long, value_name = "FIELD", conflicts_with = "group_by")]
+    pub stratify_by: Option<SampleField>,
+
+    /// Draw whole groups of files sharing a value of FIELD (as for --stratify-by), so
+    /// a group is never split across samples
+    #[arg(long, value_name = "FIELD")]
+    pub group_by: Option<SampleField>,
+
+    /// CSV of file metadata: a key column (file path or file name) followed by
+    /// columns such as `date` that --stratify-by and --group-by can name
+    #[arg(long)]
+    pub metadata: Option<String>,
+}
+
+impl BootstrapArgs {
+    pub fn sampling(&self) -> SamplingConfig {
+        let sampling = match (&self.stratify_by, &self.group_by) {
+            (Some(field), _) => Sampling::Stratified(field.clone()),
+            (None, Some(field)) => Sampling::Grouped(field.clone()),
+            (None, None) => Sampling::Simple,
+        };
+        SamplingConfig { sampling, metadata: self.metadata.clone() }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /// Directory the documents and the true_topic_words.csv and true_document_topics.csv
+    /// ground truth are written to
+    pub output: String,
+
+    /// Number of documents
+    #[arg(long, default_value_t = 1000)]
+    pub documents: usize,
+
+    /// Number of planted topics
+    #[arg(long, default_value_t = 5)]
+    pub topics: usize,
+
+    /// Number of distinct words, split evenly between the topics
+    #[arg(long, default_value_t = 500)]
+    pub vocabulary: usize,
+
+    /// Mean number of words per document
+    #[arg(long, default_value_t = 100.0)]
+    pub mean_length: f64,
+
+    /// Dirichlet concentration of the documents' topic mixtures; lower values give
+    /// documents dominated by a single topic
+    #[arg(long, default_value_t = 0.1)]
+    pub alpha: f64,
+
+    /// Dirichlet concentration of the weights of each topic's words
+    #[arg(long, default_value_t = 0.5)]
+    pub beta: f64,
+
+    /// Share of words drawn uniformly from the whole vocabulary instead of a topic
+    #[arg(long, default_value_t = 0.05)]
+    pub noise: f64,
+
+    /// Write documents/doc_*.txt (for bootstrap), or a single corpus.csv whose row
+    /// order matches true_document_topics.csv (for validate --reference-w)
+    #[arg(long, value_enum, default_value_t = CorpusFormat::Txt)]
+    pub format: CorpusFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct SimilarArgs {
+    /// Index of a document in files.csv to use as the query
+    #[arg(long, conflicts_with = "file", required_unless_present = "file")]
+    pub doc: Option<usize>,
+
+    /// External text file to project onto the topics and use as the query
+    #[arg(long)]
+    pub file: Option<String>,
+
+    /// Number of similar documents to list
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// Saved model used to project an external file; defaults to nmf_model.json in the working directory
+    #[arg(long)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Reference topic-word matrix: CSV with a term header row and leading index
+    /// column (pandas `to_csv`), or .npy with --reference-terms
+    #[arg(long)]
+    pub reference_h: String,
+
+    /// Reference document-topic matrix for the same documents (CSV or .npy)
+    #[arg(long)]
+    pub reference_w: Option<String>,
+
+    /// Terms of the reference topic-word columns, one per line
+    #[arg(long)]
+    pub reference_terms: Option<String>,
+
+    /// Saved model to validate; defaults to nmf_model.json in the working directory
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Number of top words compared per topic
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// Per-topic alignment output
+    #[arg(long, default_value = "validation.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct FitMatrixArgs {
+    /// Document-term matrix: MatrixMarket (.mtx), scipy.sparse.save_npz (.npz) or dense NumPy (.npy)
+    pub input: String,
+
+    /// Terms of the matrix columns, one per line
+    #[arg(long)]
+    pub terms: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DynamicArgs {
+    /// Dataset to preprocess and model
+    pub input: String,
+
+    /// Width of the time slices
+    #[arg(long, value_enum, default_value_t = TimeSlice::Month)]
+    pub slice: TimeSlice,
+}
+
+#[derive(Debug, Args)]
+pub struct WordClustersArgs {
+    /// Dataset to preprocess first; the workdir's tokens are clustered as they are when omitted
+    pub input: Option<String>,
+
+    /// Number of word clusters
+    #[arg(long, default_value_t = 10)]
+    pub k: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct CooccurrenceArgs {
+    /// Dataset to preprocess first; the workdir's tokens are counted as they are when omitted
+    pub input: Option<String>,
+
+    /// Terms within this many consecutive vocabulary terms co-occur; 0 pairs all the
+    /// terms of a document, once per document
+    #[arg(long, default_value_t = 10)]
+    pub window: usize,
+
+    /// Value written for each pair
+    #[arg(long, value_enum, default_value_t = CooccurrenceWeighting::Count)]
+    pub weighting: CooccurrenceWeighting,
+
+    /// Leave out pairs seen fewer times
+    #[arg(long, default_value_t = 1)]
+    pub min_count: usize,
+
+    /// CSV list of term pairs, or a MatrixMarket matrix with a terms file beside it
+    /// when the name ends in .mtx
+    #[arg(long, default_value = "cooccurrence.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TuneArgs {
+    /// Dataset the configurations are cross-validated on
+    pub input: String,
+
+    /// Topic counts to try
+    #[arg(long, value_delimiter = ',', default_values_t = [5, 10, 15])]
+    pub k: Vec<usize>,
+
+    /// Minimum document frequencies of vocabulary terms to try
+    #[arg(long, value_delimiter = ',', default_values_t = [3])]
+    pub min_df: Vec<usize>,
+
+    /// Maximum document frequency shares to try, as for --auto-stopwords; every term
+    /// is kept when omitted
+    #[arg(long, value_delimiter = ',')]
+    pub max_df: Vec<f32>,
+
+    /// Regularization strengths to try
+    #[arg(long, value_delimiter = ',', default_values_t = [0.01])]
+    pub lambda: Vec<f32>,
+
+    /// NMF update rules to try
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Solver::Mu])]
+    pub solvers: Vec<Solver>,
+
+    /// Number of cross-validation folds
+    #[arg(long, default_value_t = 3)]
+    pub folds: usize,
+
+    /// Try this many random combinations instead of the whole grid
+    #[arg(long)]
+    pub trials: Option<usize>,
+
+    /// Score the best configuration is chosen by
+    #[arg(long, value_enum, default_value_t = TuneMetric::Error)]
+    pub metric: TuneMetric,
+
+    /// Where the best configuration is written, for use with --config
+    #[arg(long, default_value = "pipeline.toml")]
+    pub output: String,
+}
+
+impl TuneArgs {
+    pub fn options(&self) -> TuneOptions {
+        let max_df = if self.max_df.is_empty() { vec![None] } else { self.max_df.iter().copied().map(Some).collect() };
+        TuneOptions {
+            space: SearchSpace {
+                k: self.k.clone(),
+                min_df: self.min_df.clone(),
+                max_df,
+                lambda: self.lambda.clone(),
+                solver: self.solvers.clone(),
+            },
+            folds: self.folds,
+            trials: self.trials,
+            metric: self.metric,
+            output: self.output.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CompareModelsArgs {
+    /// First saved model; its topics are the rows of the matrix
+    pub model_a: String,
+
+    /// Second saved model, possibly with a different vocabulary
+    pub model_b: String,
+
+    /// Topic-by-topic cosine similarity matrix
+    #[arg(long, default_value = "similarity_matrix.csv")]
+    pub matrix: String,
+
+    /// One-to-one best matching of the topics
+    #[arg(long, default_value = "topic_matches.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TopicLineageArgs {
+    /// Saved model before the update
+    pub previous: String,
+
+    /// Saved model after the update, possibly with a different vocabulary
+    pub current: String,
+
+    /// Lineage file the update is appended to as its next generation
+    #[arg(long, default_value = "topic_lineage.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffCorpusArgs {
+    /// Workdir or tokens file of the first preprocessing run
+    pub before: String,
+
+    /// Workdir or tokens file of the second run; documents are matched by file path
+    pub after: String,
+
+    /// Terms only one of the corpora has, with their document frequency
+    #[arg(long, default_value = "vocabulary_diff.csv")]
+    pub vocabulary: String,
+
+    /// Token changes of every document in both corpora, most affected first
+    #[arg(long, default_value = "document_diff.csv")]
+    pub output: String,
+
+    /// Most affected documents to print
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct StabilityArgs {
+    /// Saved models, or directories of them (e.g. rust_metrics/<run_id>/models/N100);
+    /// topics are aligned with those of the first
+    #[arg(required = true)]
+    pub models: Vec<String>,
+
+    /// Topic similarity used for matching
+    #[arg(long, value_enum, default_value_t = Similarity::Correlation)]
+    pub similarity: Similarity,
+
+    /// Number of top words compared by --similarity jaccard
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// Per-topic stability output
+    #[arg(long, default_value = "stability.csv")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportCompareArgs {
+    /// Benchmark run directory (e.g. rust_metrics/<run_id>) or one N{sample}_metrics.csv
+    pub rust: String,
+
+    /// The other implementation's metrics: a CSV file or a directory of them
+    pub other: String,
+
+    /// TOML file naming the other implementation's sample, iteration, dataset, k, step,
+    /// time and memory columns, their time_scale and memory_scale, and a [steps] table
+    /// renaming its steps to this crate's; unset keys take this crate's column names
+    #[arg(long)]
+    pub mapping: Option<String>,
+
+    /// Name of the other implementation in the column headers
+    #[arg(long, default_value = "Python")]
+    pub label: String,
+
+    /// Times and memory of each matched step side by side
+    #[arg(long, default_value = "comparison.csv")]
+    pub output: String,
+
+    /// Speedup and memory ratio per step, for each sample size and over all of them
+    #[arg(long, default_value = "comparison_summary.csv")]
+    pub summary: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CellArgs {
+    /// Dataset to preprocess and model
+    #[arg(long)]
+    pub input: String,
+
+    /// File the step metrics are written to
+    #[arg(long)]
+    pub output: String,
+
+    /// Copy the fitted model to this file
+    #[arg(long)]
+    pub keep_model: Option<String>,
+
+    /// Number of topics, for cells of a --k-values sweep
+    #[arg(long)]
+    pub k: Option<usize>,
+
+    /// Seed of the cell's NMF initialization, derived by the parent from --seed
+    #[arg(long, hide = true)]
+    pub cell_seed: Option<u64>,
+}
+
+/// A fraction strictly between 0 and 1.
+fn parse_fraction(value: &str) -> Result<f32, String> {
+    let fraction: f32 = value.parse().map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if fraction > 0.0 && fraction < 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("{} is not between 0 and 1", value))
+    }
+}
+
+/// A size in MiB, at least zero.
+fn parse_mib(value: &str) -> Result<f64, String> {
+    let mib: f64 = value.parse().map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if mib >= 0.0 {
+        Ok(mib)
+    } else {
+        Err(format!("{} is not a size in MiB", value))
+    }
+}
@@ -0,0 +1,107 @@
+use crate::readers;
+use csv::Writer;
+use ndarray::{Array2, ArrayView1};
+use std::error::Error;
+use std::path::Path;
+
+pub struct ClusterSummary {
+    pub topic: usize,
+    pub size: usize,
+    /// Mean share of the cluster's topic in its members' normalized topic rows
+    pub mean_probability: f32,
+}
+
+pub fn dominant_topic(row: ArrayView1<f32>) -> (usize, f32) {
+    let total = row.sum();
+    let (topic, &weight) = row.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap_or((0, &0.0));
+    let probability = if total > 0.0 { weight / total } else { 0.0 };
+    (topic, probability)
+}
+
+/// Assigns every document to its highest-weighted topic, writes one membership
+/// file per topic plus `summary.csv` into `output_dir`, and returns the per-topic summaries.
+pub fn write_clusters(w: &Array2<f32>, paths: &[String], output_dir: &str) -> Result<Vec<ClusterSummary>, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+
+    let mut writers = Vec::new();
+    for topic in 0..w.ncols() {
+        let mut writer = Writer::from_path(Path::new(output_dir).join(format!("topic_{}.csv", topic)))?;
+        writer.write_record(["Document", "File", "Probability"])?;
+        writers.push(writer);
+    }
+
+    let mut sizes = vec![0; w.ncols()];
+    let mut probability_sums = vec![0.0f32; w.ncols()];
+    for (doc_idx, row) in w.rows().into_iter().enumerate() {
+        let (topic, probability) = dominant_topic(row);
+        let path = paths.get(doc_idx).map_or("", String::as_str);
+        writers[topic].serialize((doc_idx, path, probability))?;
+        sizes[topic] += 1;
+        probability_sums[topic] += probability;
+    }
+    for writer in &mut writers {
+        writer.flush()?;
+    }
+
+    let summaries: Vec<ClusterSummary> = (0..w.ncols())
+        .map(|topic| ClusterSummary {
+            topic,
+            size: sizes[topic],
+            mean_probability: if sizes[topic] > 0 { probability_sums[topic] / sizes[topic] as f32 } else { 0.0 },
+        })
+        .collect();
+
+    let mut summary_writer = Writer::from_path(Path::new(output_dir).join("summary.csv"))?;
+    summary_writer.write_record(["Topic", "Size", "Mean Probability"])?;
+    for summary in &summaries {
+        summary_writer.serialize((summary.topic, summary.size, summary.mean_probability))?;
+    }
+    summary_writer.flush()?;
+
+    Ok(summaries)
+}
+
+/// Characters of a document's text shown with it as a topic example
+const SNIPPET_CHARS: usize = 200;
+
+/// Opening text of the document at `path` on one line, empty when it can't be read
+/// (e.g. a corpus row id in place of a path).
+fn snippet(path: &str) -> String {
+    let Ok((text, _)) = readers::read_document(Path::new(path)) else { return String::new() };
+    let mut snippet = String::new();
+    for word in text.split_whitespace() {
+        if snippet.chars().count() + word.chars().count() > SNIPPET_CHARS {
+            snippet.push_str(" …");
+            break;
+        }
+        if !snippet.is_empty() {
+            snippet.push(' ');
+        }
+        snippet.push_str(word);
+    }
+    snippet
+}
+
+/// Writes the `top` documents with the highest weight in each topic to
+/// `topic_<i>.csv` in `output_dir`, with their file, the weight, the topic's share of
+/// the document and the opening of its text.
+pub fn write_topic_examples(w: &Array2<f32>, paths: &[String], top: usize, output_dir: &str) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    for (topic, column) in w.columns().into_iter().enumerate() {
+        let mut order: Vec<usize> = (0..column.len()).filter(|&doc_idx| column[doc_idx] > 0.0).collect();
+        order.sort_unstable_by(|&a, &b| column[b].total_cmp(&column[a]));
+
+        let mut writer = Writer::from_path(Path::new(output_dir).join(format!("topic_{}.csv", topic)))?;
+        writer.write_record(["Rank", "Document", "File", "Weight", "Share", "Snippet"])?;
+        for (rank, doc_idx) in order.into_iter().take(top).enumerate() {
+            let total = w.row(doc_idx).sum();
+            let path = paths.get(doc_idx).map_or("", String::as_str);
+            writer.serialize((rank + 1, doc_idx, path, column[doc_idx], column[doc_idx] / total, snippet(path)))?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use crate::console;
+use crate::modeling::{NmfModel, TopWords, Topics};
+use crate::similar::cosine_similarity;
+use crate::validate::match_topics;
+use ndarray::{Array2, Axis};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Best match in the second model for a topic of the first.
+#[derive(Debug, serde::Serialize)]
+pub struct ModelMatch {
+    pub topic_a: usize,
+    pub topic_b: usize,
+    pub similarity: f32,
+    pub top_words_a: String,
+    pub top_words_b: String,
+}
+
+/// Columns of the terms both models know, in the first model's vocabulary order.
+fn shared_columns(a: &NmfModel, b: &NmfModel) -> (Vec<usize>, Vec<usize>) {
+    let index_b: HashMap<&str, usize> = b.vocab.terms().into_iter().enumerate().map(|(i, term)| (term, i)).collect();
+    a.vocab
+        .terms()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, term)| index_b.get(term).map(|&j| (i, j)))
+        .unzip()
+}
+
+/// Top words of a topic, space separated.
+fn words(topics: &Topics, topic: usize) -> String {
+    topics.get(topic).map_or(String::new(), |t| t.terms().join(" "))
+}
+
+/// Cosine similarity of every topic of `a` (rows) to every topic of `b` (columns),
+/// over the terms the two vocabularies share.
+pub fn similarity_matrix(a: &NmfModel, b: &NmfModel) -> Result<Array2<f32>, Box<dyn Error>> {
+    let (columns_a, columns_b) = shared_columns(a, b);
+    if columns_a.is_empty() {
+        return Err("The models have no terms in common".into());
+    }
+    let h_a = a.h.select(Axis(1), &columns_a);
+    let h_b = b.h.select(Axis(1), &columns_b);
+    let mut similarities = Array2::<f32>::zeros((h_a.nrows(), h_b.nrows()));
+    for ((i, j), similarity) in similarities.indexed_iter_mut() {
+        *similarity = cosine_similarity(h_a.row(i), h_b.row(j));
+    }
+    Ok(similarities)
+}
+
+/// Compares two saved models topic by topic, writing the full similarity matrix to
+/// `matrix_output` and the one-to-one best matching, with the `top` words of each
+/// topic, to `matches_output`.
+pub fn run(model_a: &str, model_b: &str, matrix_output: &str, matches_output: &str, top: TopWords) -> Result<(), Box<dyn Error>> {
+    let a = NmfModel::load(model_a)?;
+    let b = NmfModel::load(model_b)?;
+    let (shared, _) = shared_columns(&a, &b);
+    progress!("{} of {} and {} of {} terms shared", shared.len(), a.vocab.len(), shared.len(), b.vocab.len());
+    let similarities = similarity_matrix(&a, &b)?;
+
+    let mut wtr = csv::Writer::from_path(matrix_output)?;
+    let mut header = vec!["topic".to_string()];
+    header.extend((0..similarities.ncols()).map(|j| format!("b{}", j)));
+    wtr.write_record(&header)?;
+    for (i, row) in similarities.rows().into_iter().enumerate() {
+        let mut record = vec![format!("a{}", i)];
+        record.extend(row.iter().map(|s| s.to_string()));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+
+    let (topics_a, topics_b) = (a.topics(top), b.topics(top));
+    let mut matches = Vec::new();
+    for (topic_a, topic_b) in match_topics(&similarities)?.into_iter().enumerate() {
+        let Some(topic_b) = topic_b else {
+            progress!("Topic {} of {} has no counterpart", topic_a, model_a);
+            continue;
+        };
+        matches.push(ModelMatch {
+            topic_a,
+            topic_b,
+            similarity: similarities[[topic_a, topic_b]],
+            top_words_a: words(&topics_a, topic_a),
+            top_words_b: words(&topics_b, topic_b),
+        });
+    }
+    let mut wtr = csv::Writer::from_path(matches_output)?;
+    for m in &matches {
+        wtr.serialize(m)?;
+    }
+    wtr.flush()?;
+
+    for m in &matches {
+        progress!("Topic {} ~ {}: cosine {:.3} ({} | {})", m.topic_a, m.topic_b, m.similarity, m.top_words_a, m.top_words_b);
+    }
+    progress!("Similarity matrix written to {}, matching to {}", matrix_output, matches_output);
+    console::artifact("similarity_matrix", matrix_output);
+    console::artifact("topic_matches", matches_output);
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Codec for the pipeline's tabular outputs. Readers pick the codec from the
+/// file extension, so compressed inputs need no configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Output file name for `base` under this codec, e.g. `tokens.csv.gz`.
+    pub fn path(self, base: &str) -> String {
+        format!("{}{}", base, self.extension())
+    }
+}
+
+fn codec(path: &Path) -> Compression {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Compression::Gzip,
+        Some("zst") => Compression::Zstd,
+        _ => Compression::None,
+    }
+}
+
+/// Extension of the file inside any compression layer: `txt` for `a.txt.gz`.
+pub fn inner_extension(path: &Path) -> Option<String> {
+    let path = match codec(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem()?),
+    };
+    path.extension().map(|ext| ext.to_string_lossy().into_owned())
+}
+
+/// Writer of a file from `create` or `append`, compressed according to its extension.
+///
+/// Call `finish` once everything is written: it ends the gzip member or zstd frame and
+/// flushes the file, reporting any error. Dropping an unfinished writer finishes it too
+/// but has to ignore errors, so a full disk would leave a truncated file unnoticed.
+pub struct Encoder(Codec);
+
+enum Codec {
+    None(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(file: File, compression: Compression) -> io::Result<Encoder> {
+        let writer = BufWriter::new(file);
+        Ok(Encoder(match compression {
+            Compression::None => Codec::None(writer),
+            Compression::Gzip => Codec::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::Zstd => Codec::Zstd(zstd::Encoder::new(writer, 0)?),
+        }))
+    }
+
+    /// Writes the codec's trailer and flushes the file. Nothing may be written after.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Codec::None(writer) => writer.flush(),
+            Codec::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut().flush()
+            }
+            Codec::Zstd(encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Codec::None(writer) => writer.write(buf),
+            Codec::Gzip(encoder) => encoder.write(buf),
+            Codec::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Codec::None(writer) => writer.flush(),
+            Codec::Gzip(encoder) => encoder.flush(),
+            Codec::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Creates `path`, compressing according to its extension.
+pub fn create(path: impl AsRef<Path>) -> io::Result<Encoder> {
+    let path = path.as_ref();
+    Encoder::new(File::create(path)?, codec(path))
+}
+
+/// Opens `path` for appending. Compressed files get a new gzip member or zstd
+/// frame, which decoders read back as one continuous stream.
+pub fn append(path: impl AsRef<Path>) -> io::Result<Encoder> {
+    let path = path.as_ref();
+    Encoder::new(OpenOptions::new().append(true).open(path)?, codec(path))
+}
+
+/// Flushes a CSV writer over an `Encoder` and finishes the file.
+pub fn finish_csv(writer: csv::Writer<Encoder>) -> io::Result<()> {
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+/// Opens `path` for reading, decompressing according to its extension.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn Read>> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+    Ok(match codec(path) {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+}
+
+/// Removes `base` and its compressed variants so stale outputs are not picked up.
+pub fn remove_variants(base: &str) -> io::Result<()> {
+    for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+        let path = compression.path(base);
+        if Path::new(&path).exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+static REPORT: Mutex<Report> = Mutex::new(Report { artifacts: Vec::new(), metrics: Vec::new(), topics: None });
+
+/// What the run produced, collected as it goes for the final porcelain summary.
+struct Report {
+    artifacts: Vec<(String, Value)>,
+    metrics: Vec<(String, Value)>,
+    topics: Option<Value>,
+}
+
+/// Replaces the value of `name`, or adds it at the end.
+fn set<T>(entries: &mut Vec<(String, T)>, name: &str, value: T) {
+    match entries.iter_mut().find(|(entry, _)| entry == name) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((name.to_string(), value)),
+    }
+}
+
+/// Turns the porcelain mode on: progress output is dropped and stdout carries only the
+/// JSON summary printed by `summary` at the end.
+pub fn set_porcelain(porcelain: bool) {
+    PORCELAIN.store(porcelain, Ordering::Relaxed);
+}
+
+pub fn porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+/// Records a file or directory the run wrote, under a name like "model" or "tokens".
+/// A later path of the same name replaces the earlier one.
+pub fn artifact(name: &str, path: impl AsRef<std::path::Path>) {
+    let path = path.as_ref().to_string_lossy().into_owned();
+    set(&mut REPORT.lock().unwrap().artifacts, name, path.into());
+}
+
+/// Records a key result of the run, e.g. the explained variance of the fitted model.
+pub fn metric(name: &str, value: impl Into<Value>) {
+    set(&mut REPORT.lock().unwrap().metrics, name, value.into());
+}
+
+/// Records the topics of the fitted model.
+pub fn topics(topics: &impl Serialize) {
+    REPORT.lock().unwrap().topics = serde_json::to_value(topics).ok();
+}
+
+/// The JSON summary of the run of `command`: whether it succeeded, with the error
+/// otherwise, and the artifacts, metrics and topics recorded along the way.
+pub fn summary(command: &str, error: Option<&str>) -> Value {
+    let report = REPORT.lock().unwrap();
+    let object = |entries: &[(String, Value)]| Value::Object(entries.iter().cloned().collect());
+    serde_json::json!({
+        "command": command,
+        "status": if error.is_some() { "error" } else { "ok" },
+        "error": error,
+        "artifacts": object(&report.artifacts),
+        "metrics": object(&report.metrics),
+        "topics": report.topics,
+    })
+}
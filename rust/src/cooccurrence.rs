@@ -0,0 +1,154 @@
+use crate::console;
+use crate::modeling::{self, ModelConfig};
+use crate::vocabulary::Vocabulary;
+use crate::workdir::TOKENS_FILE;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Value written for each pair of terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CooccurrenceWeighting {
+    /// Number of times the terms occur together
+    #[default]
+    Count,
+    /// Positive pointwise mutual information, max(0, ln(p(a, b) / (p(a) p(b)))), with the
+    /// probabilities taken from the counts
+    Ppmi,
+}
+
+/// Symmetric co-occurrence counts of the terms of a vocabulary.
+pub struct Cooccurrence {
+    /// Count of each pair of distinct terms, keyed by (lower, higher) term index
+    pub pairs: HashMap<(usize, usize), f64>,
+    /// Row sums of the symmetric matrix: the pairs each term takes part in
+    pub marginals: Vec<f64>,
+}
+
+impl Cooccurrence {
+    /// Counts how often two vocabulary terms occur within `window` terms of each other,
+    /// over the documents' vocabulary terms only, or with `None` in the same document,
+    /// counted once per document. A term is not paired with itself.
+    pub fn count(documents: &[Vec<String>], vocab: &Vocabulary, window: Option<usize>) -> Cooccurrence {
+        let mut pairs = HashMap::new();
+        let mut add = |a: usize, b: usize| {
+            if a != b {
+                *pairs.entry((a.min(b), a.max(b))).or_insert(0.0) += 1.0;
+            }
+        };
+        for document in documents {
+            let mut terms: Vec<usize> = document.iter().filter_map(|token| vocab.get(token)).collect();
+            match window {
+                Some(window) => {
+                    for (i, &a) in terms.iter().enumerate() {
+                        for &b in terms.iter().skip(i + 1).take(window.saturating_sub(1)) {
+                            add(a, b);
+                        }
+                    }
+                }
+                None => {
+                    terms.sort_unstable();
+                    terms.dedup();
+                    for (i, &a) in terms.iter().enumerate() {
+                        for &b in &terms[i + 1..] {
+                            add(a, b);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut marginals = vec![0.0; vocab.len()];
+        for (&(a, b), &count) in &pairs {
+            marginals[a] += count;
+            marginals[b] += count;
+        }
+        Cooccurrence { pairs, marginals }
+    }
+
+    /// Pointwise mutual information of a pair with the given count.
+    pub fn pmi(&self, (a, b): (usize, usize), count: f64) -> f64 {
+        // Every pair is counted in both halves of the symmetric matrix
+        let total: f64 = self.marginals.iter().sum();
+        (count * total / (self.marginals[a] * self.marginals[b])).ln()
+    }
+
+    /// Pairs seen at least `min_count` times with their value under `weighting`, pairs
+    /// of zero value left out, highest first.
+    pub fn weighted(&self, weighting: CooccurrenceWeighting, min_count: usize) -> Vec<((usize, usize), f64)> {
+        let mut weighted: Vec<((usize, usize), f64)> = self.pairs
+            .iter()
+            .filter(|&(_, &count)| count >= min_count as f64)
+            .map(|(&pair, &count)| match weighting {
+                CooccurrenceWeighting::Count => (pair, count),
+                CooccurrenceWeighting::Ppmi => (pair, self.pmi(pair, count).max(0.0)),
+            })
+            .filter(|&(_, value)| value > 0.0)
+            .collect();
+        weighted.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        weighted
+    }
+}
+
+/// Writes the pairs as a symmetric MatrixMarket coordinate matrix, one entry per pair
+/// in the lower triangle, with rows and columns in vocabulary order.
+fn write_mtx(path: &str, size: usize, pairs: &[((usize, usize), f64)]) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    writeln!(writer, "%%MatrixMarket matrix coordinate real symmetric")?;
+    writeln!(writer, "{} {} {}", size, size, pairs.len())?;
+    for &((a, b), value) in pairs {
+        writeln!(writer, "{} {} {}", b + 1, a + 1, value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Counts the co-occurrences of the vocabulary terms of the workdir's tokens, built as
+/// for fitting (`--min-df`, `--vocab`, the vocabulary filters), within `window` terms
+/// or, with `None`, the whole document. Writes the pairs seen at least `min_count`
+/// times weighted by `weighting` to `output`: a MatrixMarket file with the terms one
+/// per line beside it when it ends in .mtx, otherwise a CSV list of term pairs.
+pub fn run(config: &ModelConfig, window: Option<usize>, weighting: CooccurrenceWeighting, min_count: usize, output: &str) -> Result<(), Box<dyn Error>> {
+    if window.is_some_and(|window| window < 2) {
+        return Err("A co-occurrence window must span at least 2 terms".into());
+    }
+    let documents = modeling::load_documents(&config.workdir.path(&config.compression.path(TOKENS_FILE)))?;
+    let vocab = modeling::load_vocabulary(&documents, config)?;
+    if vocab.is_empty() {
+        return Err("The vocabulary is empty, no terms to pair".into());
+    }
+    let cooccurrence = Cooccurrence::count(&documents, &vocab, window);
+    let pairs = cooccurrence.weighted(weighting, min_count);
+    let terms = vocab.terms();
+
+    let path = Path::new(output);
+    if path.extension().is_some_and(|ext| ext == "mtx") {
+        write_mtx(output, vocab.len(), &pairs)?;
+        let terms_file = path.with_extension("terms.txt").to_string_lossy().into_owned();
+        vocab.save(&terms_file)?;
+        progress!("Terms of the rows and columns written to {}", terms_file);
+        console::artifact("cooccurrence_terms", &terms_file);
+    } else {
+        let mut wtr = csv::Writer::from_path(output)?;
+        let value = match weighting {
+            CooccurrenceWeighting::Count => "Count",
+            CooccurrenceWeighting::Ppmi => "PPMI",
+        };
+        wtr.write_record(["Term A", "Term B", value])?;
+        for &((a, b), value) in &pairs {
+            wtr.serialize((terms[a], terms[b], value))?;
+        }
+        wtr.flush()?;
+    }
+
+    let span = window.map_or("documents".to_string(), |window| format!("windows of {} terms", window));
+    progress!("{} pairs of {} terms co-occur in {}, {} kept, written to {}", cooccurrence.pairs.len(), vocab.len(), span, pairs.len(), output);
+    console::artifact("cooccurrence", output);
+    console::metric("pairs", pairs.len());
+    for &((a, b), value) in pairs.iter().take(config.top_words.count) {
+        progress!("  {} {}: {}", terms[a], terms[b], value);
+    }
+    Ok(())
+}
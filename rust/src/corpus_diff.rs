@@ -0,0 +1,153 @@
+use crate::compression::Compression;
+use crate::console;
+use crate::modeling;
+use crate::preprocessing;
+use crate::vocabulary;
+use crate::workdir::{FILES_FILE, TOKENS_FILE};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// A preprocessed corpus: the documents' tokens and the files they came from.
+struct Corpus {
+    documents: Vec<Vec<String>>,
+    /// File path of each document, or its index when there is no files.csv next to the tokens
+    keys: Vec<String>,
+}
+
+impl Corpus {
+    /// Loads a workdir, finding its tokens under any compression, or a tokens file
+    /// with files.csv beside it.
+    fn load(path: &str) -> Result<Corpus, Box<dyn Error>> {
+        let path = Path::new(path);
+        let (tokens, dir) = if path.is_dir() {
+            let tokens = [Compression::None, Compression::Gzip, Compression::Zstd]
+                .into_iter()
+                .map(|compression| path.join(compression.path(TOKENS_FILE)))
+                .find(|tokens| tokens.exists())
+                .ok_or_else(|| format!("{} has no {}", path.display(), TOKENS_FILE))?;
+            (tokens, path)
+        } else {
+            (path.to_path_buf(), path.parent().unwrap_or(Path::new(".")))
+        };
+        let documents = modeling::load_documents(&tokens.to_string_lossy())?;
+        let files_csv = dir.join(FILES_FILE);
+        let keys = if files_csv.exists() {
+            preprocessing::load_file_paths(&files_csv.to_string_lossy())?
+        } else {
+            (0..documents.len()).map(|idx| idx.to_string()).collect()
+        };
+        if keys.len() != documents.len() {
+            return Err(format!("{} lists {} documents but the tokens have {}", files_csv.display(), keys.len(), documents.len()).into());
+        }
+        Ok(Corpus { documents, keys })
+    }
+}
+
+/// A term in only one of the corpora.
+#[derive(Debug, serde::Serialize)]
+pub struct TermChange {
+    pub term: String,
+    /// "added" or "removed"
+    pub status: &'static str,
+    /// Documents containing the term in the corpus that has it
+    pub documents: usize,
+}
+
+/// How the tokens of a document present in both corpora changed.
+#[derive(Debug, serde::Serialize)]
+pub struct DocumentChange {
+    pub document: String,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Tokens in the second corpus but not the first, counted with multiplicity
+    pub tokens_added: usize,
+    /// Tokens in the first corpus but not the second, counted with multiplicity
+    pub tokens_removed: usize,
+    /// Added and removed tokens over the larger of the two token counts
+    pub changed_share: f32,
+}
+
+/// Added and removed tokens of `after` against `before`, counted with multiplicity.
+fn token_changes(before: &[String], after: &[String]) -> (usize, usize) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for token in after {
+        *counts.entry(token).or_default() += 1;
+    }
+    for token in before {
+        *counts.entry(token).or_default() -= 1;
+    }
+    counts.values().fold((0, 0), |(added, removed), &count| (added + count.max(0) as usize, removed + (-count).max(0) as usize))
+}
+
+/// Compares two preprocessing outputs (workdirs or tokens files), e.g. before and
+/// after changing the stopwords or the tokenizer. Documents are matched by file path.
+/// Writes the terms only one corpus has to `vocabulary_output`, and the token changes of
+/// every matched document, most affected first, to `documents_output`; prints a summary
+/// with the `top` most affected documents.
+pub fn run(before: &str, after: &str, vocabulary_output: &str, documents_output: &str, top: usize) -> Result<(), Box<dyn Error>> {
+    let before_corpus = Corpus::load(before)?;
+    let after_corpus = Corpus::load(after)?;
+
+    let df_before = vocabulary::document_frequencies(&before_corpus.documents);
+    let df_after = vocabulary::document_frequencies(&after_corpus.documents);
+    let mut terms: Vec<TermChange> = df_after
+        .iter()
+        .filter(|(term, _)| !df_before.contains_key(*term))
+        .map(|(term, &documents)| TermChange { term: term.clone(), status: "added", documents })
+        .chain(df_before.iter().filter(|(term, _)| !df_after.contains_key(*term)).map(|(term, &documents)| TermChange { term: term.clone(), status: "removed", documents }))
+        .collect();
+    terms.sort_by(|a, b| a.status.cmp(b.status).then(b.documents.cmp(&a.documents)).then_with(|| a.term.cmp(&b.term)));
+    let added = terms.iter().filter(|term| term.status == "added").count();
+
+    let mut wtr = csv::Writer::from_path(vocabulary_output)?;
+    for term in &terms {
+        wtr.serialize(term)?;
+    }
+    wtr.flush()?;
+
+    let after_index: HashMap<&str, usize> = after_corpus.keys.iter().enumerate().map(|(idx, key)| (key.as_str(), idx)).collect();
+    let mut changes = Vec::new();
+    for (key, tokens_before) in before_corpus.keys.iter().zip(&before_corpus.documents) {
+        let Some(&idx) = after_index.get(key.as_str()) else { continue };
+        let tokens_after = &after_corpus.documents[idx];
+        let (tokens_added, tokens_removed) = token_changes(tokens_before, tokens_after);
+        let larger = tokens_before.len().max(tokens_after.len());
+        changes.push(DocumentChange {
+            document: key.clone(),
+            tokens_before: tokens_before.len(),
+            tokens_after: tokens_after.len(),
+            tokens_added,
+            tokens_removed,
+            changed_share: if larger > 0 { (tokens_added + tokens_removed) as f32 / larger as f32 } else { 0.0 },
+        });
+    }
+    changes.sort_by(|a, b| b.changed_share.total_cmp(&a.changed_share).then_with(|| a.document.cmp(&b.document)));
+
+    let mut wtr = csv::Writer::from_path(documents_output)?;
+    for change in &changes {
+        wtr.serialize(change)?;
+    }
+    wtr.flush()?;
+
+    let only_before = before_corpus.documents.len() - changes.len();
+    let only_after = after_corpus.keys.len() - changes.len();
+    let tokens = |corpus: &Corpus| corpus.documents.iter().map(Vec::len).sum::<usize>();
+    progress!("Documents: {} before, {} after, {} in both, {} only before, {} only after", before_corpus.documents.len(), after_corpus.documents.len(), changes.len(), only_before, only_after);
+    progress!("Tokens: {} before, {} after", tokens(&before_corpus), tokens(&after_corpus));
+    progress!("Vocabulary: {} terms before, {} after, {} added, {} removed", df_before.len(), df_after.len(), added, terms.len() - added);
+    if !changes.is_empty() {
+        let shift = changes.iter().map(|change| change.tokens_after as f64 - change.tokens_before as f64).sum::<f64>() / changes.len() as f64;
+        let unchanged = changes.iter().filter(|change| change.tokens_added + change.tokens_removed == 0).count();
+        progress!("Mean token count shift per document {:+.1}, {} documents unchanged", shift, unchanged);
+    }
+    for change in changes.iter().take(top).filter(|change| change.changed_share > 0.0) {
+        progress!("  {:.1}% changed ({} → {} tokens, +{} −{}): {}", change.changed_share * 100.0, change.tokens_before, change.tokens_after, change.tokens_added, change.tokens_removed, change.document);
+    }
+    progress!("Vocabulary changes written to {}, document changes to {}", vocabulary_output, documents_output);
+    console::artifact("vocabulary_diff", vocabulary_output);
+    console::artifact("document_diff", documents_output);
+    console::metric("terms_added", added);
+    console::metric("terms_removed", terms.len() - added);
+    Ok(())
+}
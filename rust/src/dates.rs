@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use time::OffsetDateTime;
+
+/// Document dates (YYYY-MM-DD) from a metadata CSV with a key column (file path,
+/// file name or corpus row id) followed by a `date` column, falling back to file
+/// modification times.
+#[derive(Debug, Default)]
+pub struct DocumentDates {
+    by_key: HashMap<String, String>,
+}
+
+/// The YYYY-MM-DD prefix of a date or timestamp, if it has one.
+fn parse_date(value: &str) -> Option<String> {
+    let date = value.trim().get(..10)?;
+    let bytes = date.as_bytes();
+    let digits = |range: std::ops::Range<usize>| bytes[range].iter().all(u8::is_ascii_digit);
+    (digits(0..4) && bytes[4] == b'-' && digits(5..7) && bytes[7] == b'-' && digits(8..10)).then(|| date.to_string())
+}
+
+impl DocumentDates {
+    pub fn load(path: &str) -> Result<DocumentDates, Box<dyn Error>> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let date_idx = rdr.headers()?
+            .iter()
+            .position(|h| h == "date")
+            .ok_or_else(|| format!("{} has no 'date' column", path))?;
+        let mut by_key = HashMap::new();
+        for (row, result) in rdr.records().enumerate() {
+            let record = result?;
+            let value = record.get(date_idx).unwrap_or_default();
+            let date = parse_date(value).ok_or_else(|| format!("Row {} of {}: '{}' is not a YYYY-MM-DD date", row + 1, path, value))?;
+            by_key.insert(record.get(0).unwrap_or_default().to_string(), date);
+        }
+        Ok(DocumentDates { by_key })
+    }
+
+    /// Date of a corpus row, empty when the metadata doesn't list it.
+    pub fn of_row(&self, id: &str) -> String {
+        self.by_key.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Date of a document file: its metadata entry by path or file name, otherwise
+    /// the day it was last modified (UTC).
+    pub fn of_file(&self, path: &Path) -> String {
+        let by_name = || path.file_name().and_then(|name| self.by_key.get(name.to_string_lossy().as_ref()));
+        if let Some(date) = self.by_key.get(path.to_string_lossy().as_ref()).or_else(by_name) {
+            return date.clone();
+        }
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| {
+                let date = OffsetDateTime::from(modified).date();
+                format!("{:04}-{:02}-{:02}", date.year(), date.month() as u8, date.day())
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,103 @@
+use crate::console;
+use crate::modeling::{self, create_tfidf_matrix, nmf, Fit, FitResult, ModelConfig, NmfOptions, Topics};
+use crate::preprocessing;
+use crate::workdir::{FILES_FILE, TOKENS_FILE};
+use clap::ValueEnum;
+use ndarray::Axis;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+pub const PREVALENCE_FILE: &str = "topic_prevalence.csv";
+pub const SLICE_TOPICS_FILE: &str = "slice_topics.csv";
+
+/// Width of the time slices documents are bucketed into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimeSlice {
+    Year,
+    #[default]
+    Month,
+    Day,
+}
+
+impl TimeSlice {
+    /// Slice of a YYYY-MM-DD date, e.g. "2024-03" by month.
+    fn key(self, date: &str) -> &str {
+        let len = match self {
+            TimeSlice::Year => 4,
+            TimeSlice::Month => 7,
+            TimeSlice::Day => 10,
+        };
+        date.get(..len).unwrap_or(date)
+    }
+}
+
+/// Fits topics on the whole preprocessed corpus, then refits them slice by slice in
+/// date order, each slice warm-started from the previous slice's H so topic ids stay
+/// comparable. Writes the mean topic share of each slice to `PREVALENCE_FILE` and
+/// the slices' topic words to `SLICE_TOPICS_FILE`.
+pub fn run(config: &ModelConfig, slice: TimeSlice) -> Result<(), Box<dyn Error>> {
+    let workdir = &config.workdir;
+    let documents = modeling::load_documents(&workdir.path(&config.compression.path(TOKENS_FILE)))?;
+    let dates = preprocessing::load_file_dates(&workdir.path(FILES_FILE))?;
+    if dates.len() != documents.len() {
+        return Err(format!("{} lists {} documents but the tokens have {}", FILES_FILE, dates.len(), documents.len()).into());
+    }
+
+    let mut slices: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (doc_idx, date) in dates.iter().enumerate().filter(|(_, date)| !date.is_empty()) {
+        slices.entry(slice.key(date)).or_default().push(doc_idx);
+    }
+    let undated = documents.len() - slices.values().map(Vec::len).sum::<usize>();
+    if undated > 0 {
+        progress!("{} documents have no date and are left out of the time slices", undated);
+    }
+    if slices.is_empty() {
+        return Err("No dated documents to slice".into());
+    }
+
+    let Fit { model, .. } = modeling::fit(&documents, config)?;
+    modeling::check_memory_budget(&documents, &model.vocab, config, Err("the time slices need the matrix in memory"))?;
+    let tfidf = create_tfidf_matrix(&documents, &model.vocab, &model.idf);
+
+    let prevalence_csv = workdir.path(PREVALENCE_FILE);
+    let topics_csv = workdir.path(SLICE_TOPICS_FILE);
+    let mut prevalence_writer = csv::Writer::from_path(&prevalence_csv)?;
+    let mut header = vec!["slice".to_string(), "documents".to_string()];
+    header.extend((0..config.k).map(|topic| format!("topic_{}", topic)));
+    prevalence_writer.write_record(&header)?;
+    let mut topics_writer = csv::Writer::from_path(&topics_csv)?;
+    topics_writer.write_record(["slice", "topic", "words"])?;
+
+    let mut h = model.h;
+    for (slice_idx, (key, doc_indices)) in slices.iter().enumerate() {
+        let seed = config.init_seed().map(|seed| modeling::child_seed(seed, slice_idx as u64));
+        let FitResult { w, h: h_slice, .. } = nmf(&tfidf.select(Axis(0), doc_indices), NmfOptions { h_init: Some(&h), seed, ..NmfOptions::new(config) });
+
+        // Mean over the slice's documents of each topic's share of the document
+        let mut prevalence = vec![0.0f32; config.k];
+        for row in w.rows() {
+            let total = row.sum();
+            if total > 0.0 {
+                for (share, &weight) in prevalence.iter_mut().zip(row) {
+                    *share += weight / total;
+                }
+            }
+        }
+        let mut record = vec![key.to_string(), doc_indices.len().to_string()];
+        record.extend(prevalence.iter().map(|share| (share / doc_indices.len() as f32).to_string()));
+        prevalence_writer.write_record(&record)?;
+
+        for topic in &Topics::new(&h_slice, &model.vocab, config.top_words) {
+            topics_writer.write_record([key.to_string(), topic.index.to_string(), topic.terms().join(" ")])?;
+        }
+        progress!("  {}: {} documents", key, doc_indices.len());
+        h = h_slice;
+    }
+    prevalence_writer.flush()?;
+    topics_writer.flush()?;
+
+    progress!("Topic prevalence over {} slices written to {}, slice topics to {}", slices.len(), prevalence_csv, topics_csv);
+    console::artifact("topic_prevalence", &prevalence_csv);
+    console::artifact("slice_topics", &topics_csv);
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use sysinfo::{Components, CpuRefreshKind, RefreshKind, System};
+
+/// CPU clock and temperature at one moment, as far as the platform reports them.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub struct Thermal {
+    /// Mean current frequency over the logical cores
+    pub mean_mhz: Option<f64>,
+    pub min_mhz: Option<u64>,
+    pub max_mhz: Option<u64>,
+    /// Hottest temperature sensor, e.g. a CPU package or core
+    pub max_celsius: Option<f32>,
+}
+
+/// Reads the current core frequencies and temperatures.
+pub fn thermal() -> Thermal {
+    let system = System::new_with_specifics(RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing().with_frequency()));
+    // Reported as 0 where the frequency can't be read, e.g. in many virtual machines
+    let frequencies: Vec<u64> = system.cpus().iter().map(|cpu| cpu.frequency()).filter(|&mhz| mhz > 0).collect();
+    let mean_mhz = (!frequencies.is_empty()).then(|| frequencies.iter().sum::<u64>() as f64 / frequencies.len() as f64);
+    let max_celsius = Components::new_with_refreshed_list()
+        .iter()
+        .filter_map(|component| component.temperature())
+        .filter(|celsius| celsius.is_finite())
+        .reduce(f32::max);
+    Thermal { mean_mhz, min_mhz: frequencies.iter().min().copied(), max_mhz: frequencies.iter().max().copied(), max_celsius }
+}
+
+/// Clock and temperature around a step, telling a step slowed by throttling or one
+/// sped up by boost from one that did more work.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub struct StepThermal {
+    pub before: Thermal,
+    pub after: Thermal,
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|value| value.trim().to_string())
+}
+
+/// Frequency scaling governors in use, e.g. "powersave" or "performance", over all cores.
+#[cfg(target_os = "linux")]
+fn governors() -> Vec<String> {
+    let mut governors: Vec<String> = std::fs::read_dir("/sys/devices/system/cpu")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| read_trimmed(entry.ok()?.path().join("cpufreq/scaling_governor")))
+        .collect();
+    governors.sort();
+    governors.dedup();
+    governors
+}
+
+#[cfg(not(target_os = "linux"))]
+fn governors() -> Vec<String> {
+    Vec::new()
+}
+
+/// Whether turbo boost is enabled, from the acpi-cpufreq or intel_pstate driver.
+#[cfg(target_os = "linux")]
+fn boost() -> Option<bool> {
+    read_trimmed("/sys/devices/system/cpu/cpufreq/boost")
+        .map(|boost| boost == "1")
+        .or_else(|| read_trimmed("/sys/devices/system/cpu/intel_pstate/no_turbo").map(|no_turbo| no_turbo == "0"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn boost() -> Option<bool> {
+    None
+}
+
+/// Whether the machine runs on battery, and the battery's charge in percent, each
+/// `None` when unknown (e.g. a desktop without a battery reports no charge).
+#[cfg(target_os = "linux")]
+fn power() -> (Option<bool>, Option<u8>) {
+    let supplies: Vec<_> = std::fs::read_dir("/sys/class/power_supply").into_iter().flatten().filter_map(|entry| Some(entry.ok()?.path())).collect();
+    let of_type = |kind: &'static str| supplies.iter().filter(move |supply| read_trimmed(supply.join("type")).as_deref() == Some(kind));
+    let mains_online = of_type("Mains").any(|supply| read_trimmed(supply.join("online")).as_deref() == Some("1"));
+    let battery = of_type("Battery").next();
+    let charge = battery.and_then(|battery| read_trimmed(battery.join("capacity"))?.parse().ok());
+    let on_battery = match battery {
+        Some(battery) => Some(!mains_online && read_trimmed(battery.join("status")).as_deref() == Some("Discharging")),
+        None if mains_online => Some(false),
+        None => None,
+    };
+    (on_battery, charge)
+}
+
+#[cfg(windows)]
+fn power() -> (Option<bool>, Option<u8>) {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return (None, None);
+    }
+    // 255 stands for unknown in both fields
+    let on_battery = match status.ACLineStatus {
+        0 => Some(true),
+        1 => Some(false),
+        _ => None,
+    };
+    (on_battery, (status.BatteryLifePercent <= 100).then_some(status.BatteryLifePercent))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn power() -> (Option<bool>, Option<u8>) {
+    (None, None)
+}
+
+/// Frequency scaling, boost, power source and the clock and temperature when the run
+/// starts, for the manifest. The CPU model is part of `provenance::host`.
+pub fn context() -> serde_json::Value {
+    let (on_battery, battery_percent) = power();
+    serde_json::json!({
+        "governors": governors(),
+        "boost": boost(),
+        "on_battery": on_battery,
+        "battery_percent": battery_percent,
+        "thermal": thermal(),
+    })
+}
@@ -0,0 +1,228 @@
+use crate::modeling::{self, ModelConfig, NmfModel, Topics};
+use crate::preprocessing::{self, PreprocessConfig, Preprocessor};
+use crate::workdir::{DISTRIBUTIONS_FILE, FILES_FILE};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::error::Error;
+
+/// A word looked up with `/`: the vocabulary terms it preprocesses to and the
+/// topics they load on, strongest first.
+#[derive(Default)]
+struct Search {
+    query: String,
+    /// Still being typed
+    editing: bool,
+    terms: Vec<String>,
+    hits: Vec<(usize, f32)>,
+}
+
+/// State of the `explore` UI.
+struct Explorer {
+    model: NmfModel,
+    topics: Topics,
+    /// Highest weighted documents of each topic as (document, weight)
+    documents: Vec<Vec<(usize, f32)>>,
+    /// Path or corpus row id of each document, from files.csv
+    paths: Vec<String>,
+    preprocessor: Preprocessor,
+    selected: ListState,
+    search: Option<Search>,
+}
+
+/// The `top` rows of each column of `w` by weight.
+fn top_documents(w: &ndarray::Array2<f32>, top: usize) -> Vec<Vec<(usize, f32)>> {
+    w.columns()
+        .into_iter()
+        .map(|column| {
+            let mut ranked: Vec<(usize, f32)> = column.iter().copied().enumerate().filter(|&(_, weight)| weight > 0.0).collect();
+            ranked.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+            ranked.truncate(top);
+            ranked
+        })
+        .collect()
+}
+
+impl Explorer {
+    fn selected_topic(&self) -> usize {
+        self.selected.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, topic: usize) {
+        self.selected.select(Some(topic.min(self.topics.len().saturating_sub(1))));
+    }
+
+    /// Preprocesses the query like the corpus and ranks the topics by the summed
+    /// weight of its terms in H.
+    fn run_search(&mut self) {
+        let Some(search) = &mut self.search else { return };
+        search.editing = false;
+        let tokens = self.preprocessor.process(&search.query);
+        let indices: Vec<usize> = tokens.iter().filter_map(|token| self.model.vocab.get(token)).collect();
+        search.terms = tokens.into_iter().filter(|token| self.model.vocab.contains(token)).collect();
+        search.hits = self.model.h
+            .rows()
+            .into_iter()
+            .enumerate()
+            .map(|(topic, row)| (topic, indices.iter().map(|&idx| row[idx]).sum::<f32>()))
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+        search.hits.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        if let Some(&(topic, _)) = search.hits.first() {
+            self.select(topic);
+        }
+    }
+
+    /// Handles a key press, returning false to quit.
+    fn handle(&mut self, key: KeyCode) -> bool {
+        if let Some(search) = self.search.as_mut().filter(|search| search.editing) {
+            match key {
+                KeyCode::Char(c) => search.query.push(c),
+                KeyCode::Backspace => {
+                    search.query.pop();
+                }
+                KeyCode::Enter => self.run_search(),
+                KeyCode::Esc => self.search = None,
+                _ => {}
+            }
+            return true;
+        }
+        match key {
+            KeyCode::Char('q') => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected_topic() + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected_topic().saturating_sub(1)),
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(self.topics.len()),
+            KeyCode::Char('/') => self.search = Some(Search { editing: true, ..Search::default() }),
+            // Cycle through the topics the searched word loads on
+            KeyCode::Char('n') => {
+                if let Some(search) = self.search.as_ref().filter(|search| !search.hits.is_empty()) {
+                    let current = search.hits.iter().position(|&(topic, _)| topic == self.selected_topic());
+                    let next = current.map_or(0, |position| (position + 1) % search.hits.len());
+                    self.select(search.hits[next].0);
+                }
+            }
+            KeyCode::Esc => self.search = None,
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+        let [words, documents] = Layout::vertical([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(right);
+        let (topics, search) = match &self.search {
+            Some(search) if !search.editing => {
+                let [topics, search] = Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(left);
+                (topics, Some(search))
+            }
+            _ => (left, None),
+        };
+
+        self.draw_topics(frame, topics);
+        self.draw_words(frame, words);
+        self.draw_documents(frame, documents);
+        if let Some(area) = search {
+            self.draw_search(frame, area);
+        }
+
+        let status = match &self.search {
+            Some(search) if search.editing => Line::from(format!("/{}", search.query)),
+            _ => Line::from(" ↑/↓ topic   / search   n next hit   Esc clear   q quit").dim(),
+        };
+        frame.render_widget(Paragraph::new(status), footer);
+    }
+
+    fn draw_topics(&mut self, frame: &mut Frame, area: Rect) {
+        let items = self.topics.iter().map(|topic| format!("{:>3}  {}", topic.index, topic.terms().join(" ")));
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" Topics ({}) ", self.topics.len())))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.selected);
+    }
+
+    fn draw_words(&self, frame: &mut Frame, area: Rect) {
+        let topic = self.selected_topic();
+        let rows = self.topics.get(topic).into_iter().flat_map(|topic| &topic.words).map(|word| {
+            let searched = self.search.as_ref().is_some_and(|search| search.terms.contains(&word.word));
+            let row = Row::new([word.word.clone(), format!("{:.4}", word.weight)]);
+            if searched { row.bold() } else { row }
+        });
+        let table = Table::new(rows, [Constraint::Min(20), Constraint::Length(10)])
+            .header(Row::new(["Word", "Weight"]).underlined())
+            .block(Block::bordered().title(format!(" Topic {} words ", topic)));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_documents(&self, frame: &mut Frame, area: Rect) {
+        let topic = self.selected_topic();
+        let rows = self.documents.get(topic).into_iter().flatten().map(|&(doc, weight)| {
+            let path = self.paths.get(doc).map_or("?", String::as_str);
+            Row::new([format!("{:.4}", weight), doc.to_string(), path.to_string()])
+        });
+        let table = Table::new(rows, [Constraint::Length(8), Constraint::Length(8), Constraint::Min(20)])
+            .header(Row::new(["Weight", "Document", "File"]).underlined())
+            .block(Block::bordered().title(format!(" Topic {} documents ", topic)));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_search(&self, frame: &mut Frame, area: Rect) {
+        let Some(search) = &self.search else { return };
+        let title = format!(" '{}' ", search.query);
+        if search.hits.is_empty() {
+            let message = if search.terms.is_empty() { "Not in the vocabulary (or removed by preprocessing)" } else { "Loads on no topic" };
+            frame.render_widget(Paragraph::new(message).block(Block::bordered().title(title)), area);
+            return;
+        }
+        let total: f32 = search.hits.iter().map(|&(_, weight)| weight).sum();
+        let rows = search.hits.iter().map(|&(topic, weight)| {
+            let row = Row::new([topic.to_string(), format!("{:.4}", weight), format!("{:.0}%", weight / total * 100.0)]);
+            if topic == self.selected_topic() { row.reversed() } else { row }
+        });
+        let table = Table::new(rows, [Constraint::Length(6), Constraint::Length(10), Constraint::Length(6)])
+            .header(Row::new(["Topic", "Weight", "Share"]).underlined())
+            .block(Block::bordered().title(format!("{}as {} ", title, search.terms.join(" "))));
+        frame.render_widget(table, area);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle(key.code)
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Browses a saved model in the terminal: its topics, each topic's words and weights
+/// and its `documents` highest weighted documents from the workdir, and the topics a
+/// searched word loads on.
+pub fn run(model_path: &str, documents: usize, preprocess_config: &PreprocessConfig, config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let model = NmfModel::load(model_path)?;
+    let w = modeling::load_topic_distributions(&config.workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)))?;
+    if w.ncols() != model.h.nrows() {
+        return Err(format!("{} has {} topics but the model has {}", DISTRIBUTIONS_FILE, w.ncols(), model.h.nrows()).into());
+    }
+    let mut explorer = Explorer {
+        topics: model.topics(config.top_words),
+        documents: top_documents(&w, documents),
+        paths: preprocessing::load_file_paths(&config.workdir.path(FILES_FILE))?,
+        preprocessor: Preprocessor::new(preprocess_config)?,
+        model,
+        selected: ListState::default().with_selected(Some(0)),
+        search: None,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = explorer.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,93 @@
+use crate::compression;
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, Float32Array, Float64Array, Int64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{Field, Schema};
+use ndarray::Array2;
+use std::error::Error;
+use std::fs::File;
+use std::sync::Arc;
+
+pub const TOKENS_ARROW: &str = "tokens.arrow";
+pub const FILES_ARROW: &str = "files.arrow";
+pub const DISTRIBUTIONS_ARROW: &str = "document_topic_distributions.arrow";
+
+fn write_batch(path: &str, columns: Vec<(String, ArrayRef)>) -> Result<(), Box<dyn Error>> {
+    let fields: Vec<Field> = columns.iter().map(|(name, array)| Field::new(name, array.data_type().clone(), array.null_count() > 0)).collect();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns.into_iter().map(|(_, array)| array).collect())?;
+    let mut writer = FileWriter::try_new(File::create(path)?, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes the tokens of each document as a list column, so readers don't have to
+/// parse the JSON strings of tokens.csv.
+pub fn write_tokens(documents: &[Vec<String>], path: &str) -> Result<(), Box<dyn Error>> {
+    let mut tokens = ListBuilder::new(StringBuilder::new());
+    for document in documents {
+        for token in document {
+            tokens.values().append_value(token);
+        }
+        tokens.append(true);
+    }
+    let index: ArrayRef = Arc::new(UInt32Array::from_iter_values(0..documents.len() as u32));
+    let tokens: ArrayRef = Arc::new(tokens.finish());
+    write_batch(path, vec![("index".to_string(), index), ("tokens".to_string(), tokens)])
+}
+
+/// Writes a document-topic matrix with the columns of `save_topic_distributions`.
+pub fn write_distributions(w: &Array2<f32>, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut columns: Vec<(String, ArrayRef)> = vec![("Document".to_string(), Arc::new(Int64Array::from_iter_values(0..w.nrows() as i64)))];
+    for (topic, weights) in w.columns().into_iter().enumerate() {
+        columns.push((format!("Topic{}", topic), Arc::new(Float32Array::from_iter_values(weights.iter().copied()))));
+    }
+    write_batch(path, columns)
+}
+
+/// Type a column of string cells is written as. Empty cells become nulls.
+#[derive(Debug, Clone, Copy)]
+pub enum Column {
+    Int,
+    Float,
+    Text,
+}
+
+impl Column {
+    fn array(self, cells: &[&str]) -> ArrayRef {
+        match self {
+            Column::Int => Arc::new(cells.iter().map(|cell| cell.parse::<i64>().ok()).collect::<Int64Array>()),
+            Column::Float => Arc::new(cells.iter().map(|cell| cell.parse::<f64>().ok()).collect::<Float64Array>()),
+            Column::Text => Arc::new(StringArray::from(cells.to_vec())),
+        }
+    }
+}
+
+/// Writes rows of string cells as a table with the given column names and types.
+pub fn write_table(columns: &[(&str, Column)], rows: &[Vec<String>], path: &str) -> Result<(), Box<dyn Error>> {
+    let columns = columns
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, column))| {
+            let cells: Vec<&str> = rows.iter().map(|row| row.get(i).map_or("", String::as_str)).collect();
+            (name.to_string(), column.array(&cells))
+        })
+        .collect();
+    write_batch(path, columns)
+}
+
+/// Converts a (possibly compressed) CSV file with a header row, such as files.csv,
+/// whose columns have the given types.
+pub fn convert_csv(csv_path: &str, types: &[Column], path: &str) -> Result<(), Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_reader(compression::open(csv_path)?);
+    let header: Vec<String> = rdr.headers()?.iter().map(str::to_string).collect();
+    if header.len() != types.len() {
+        return Err(format!("{} has {} columns, expected {}", csv_path, header.len(), types.len()).into());
+    }
+    let mut rows = Vec::new();
+    for result in rdr.records() {
+        rows.push(result?.iter().map(str::to_string).collect());
+    }
+    let columns: Vec<(&str, Column)> = header.iter().map(String::as_str).zip(types.iter().copied()).collect();
+    write_table(&columns, &rows, path)
+}
@@ -0,0 +1,68 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// Keeps, rewrites or drops a token between tokenizing and stemming.
+///
+/// Implement this to plug a custom filter into a `Preprocessor`, see
+/// `Preprocessor::with_filter`. Filters run in the order they were added, each on the
+/// output of the one before.
+pub trait TokenFilter: Send + Sync {
+    /// The token to keep, borrowed as it is or rewritten, or `None` to drop it.
+    fn filter<'a>(&self, token: &'a str) -> Option<Cow<'a, str>>;
+}
+
+/// Runs `token` through `filters` in order, stopping at the first that drops it.
+pub fn apply<'f>(filters: impl IntoIterator<Item = &'f dyn TokenFilter>, mut token: String) -> Option<String> {
+    for filter in filters {
+        match filter.filter(&token)? {
+            Cow::Borrowed(_) => {}
+            Cow::Owned(rewritten) => token = rewritten,
+        }
+    }
+    Some(token)
+}
+
+/// Drops the listed words.
+#[derive(Default)]
+pub struct StopwordFilter {
+    words: HashSet<String>,
+}
+
+impl StopwordFilter {
+    pub fn new(words: HashSet<String>) -> StopwordFilter {
+        StopwordFilter { words }
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &String> {
+        self.words.iter()
+    }
+}
+
+impl TokenFilter for StopwordFilter {
+    fn filter<'a>(&self, token: &'a str) -> Option<Cow<'a, str>> {
+        (!self.words.contains(token)).then_some(Cow::Borrowed(token))
+    }
+}
+
+/// Drops tokens shorter than `min` or longer than `max` characters.
+pub struct LengthFilter {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl TokenFilter for LengthFilter {
+    fn filter<'a>(&self, token: &'a str) -> Option<Cow<'a, str>> {
+        let length = token.chars().count();
+        (length >= self.min && self.max.is_none_or(|max| length <= max)).then_some(Cow::Borrowed(token))
+    }
+}
+
+/// Drops tokens the regex matches anywhere in; anchor it to match whole tokens.
+pub struct RegexFilter(pub Regex);
+
+impl TokenFilter for RegexFilter {
+    fn filter<'a>(&self, token: &'a str) -> Option<Cow<'a, str>> {
+        (!self.0.is_match(token)).then_some(Cow::Borrowed(token))
+    }
+}
@@ -0,0 +1,204 @@
+use crate::console;
+use clap::ValueEnum;
+use ndarray::Array2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Dirichlet, Distribution, Poisson, WeightedIndex};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Ground-truth topic-word distributions, in the pandas `to_csv` layout `validate` reads
+pub const TRUE_TOPIC_WORDS_FILE: &str = "true_topic_words.csv";
+/// Ground-truth topic mixture of each document, in the same layout
+pub const TRUE_DOCUMENT_TOPICS_FILE: &str = "true_document_topics.csv";
+/// Directory the documents are written to in the `Txt` format
+pub const DOCUMENTS_DIR: &str = "documents";
+/// Corpus file written in the `Csv` format, with `id` and `text` columns
+pub const CORPUS_FILE: &str = "corpus.csv";
+
+/// Letters synthetic words are made of. Words alternate consonants and vowels and end
+/// in a consonant, without the letters of common English suffixes (e, s, y, l, n, c),
+/// so they pass stopword removal and stemming unchanged.
+const CONSONANTS: &[u8] = b"bdfgkmptvz";
+const VOWELS: &[u8] = b"aiou";
+
+/// How the generated documents are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CorpusFormat {
+    /// One .txt file per document under `DOCUMENTS_DIR`, as `bootstrap` reads
+    #[default]
+    Txt,
+    /// A single `CORPUS_FILE`, keeping the document order of the ground truth
+    Csv,
+}
+
+/// Shape of a synthetic corpus, see `run`.
+#[derive(Debug, Clone)]
+pub struct SyntheticConfig {
+    pub documents: usize,
+    pub topics: usize,
+    pub vocabulary: usize,
+    /// Mean number of words per document (Poisson distributed)
+    pub mean_length: f64,
+    /// Dirichlet concentration of the documents' topic mixtures; small values give
+    /// documents dominated by one topic
+    pub alpha: f64,
+    /// Dirichlet concentration of the weights of a topic's words
+    pub beta: f64,
+    /// Share of each document's words drawn uniformly from the whole vocabulary
+    pub noise: f64,
+    pub seed: u64,
+    pub format: CorpusFormat,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        SyntheticConfig {
+            documents: 1000,
+            topics: 5,
+            vocabulary: 500,
+            mean_length: 100.0,
+            alpha: 0.1,
+            beta: 0.5,
+            noise: 0.05,
+            seed: 42,
+            format: CorpusFormat::Txt,
+        }
+    }
+}
+
+/// The `index`th synthetic word, unique for indices below 16000.
+fn word(index: usize) -> String {
+    let (c, v) = (CONSONANTS.len(), VOWELS.len());
+    let letters = [
+        CONSONANTS[index % c],
+        VOWELS[index / c % v],
+        CONSONANTS[index / (c * v) % c],
+        VOWELS[index / (c * v * c) % v],
+        CONSONANTS[index / (c * v * c * v) % c],
+    ];
+    String::from_utf8(letters.to_vec()).expect("ASCII letters")
+}
+
+/// Draws from a symmetric Dirichlet over `len` outcomes.
+fn dirichlet(rng: &mut StdRng, concentration: f64, len: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    if len == 1 {
+        return Ok(vec![1.0]);
+    }
+    Ok(Dirichlet::new(&vec![concentration; len])?.sample(rng))
+}
+
+/// Topic-word distributions: each topic puts all its weight on its own block of the
+/// vocabulary, so every topic is identifiable.
+fn planted_topics(rng: &mut StdRng, config: &SyntheticConfig) -> Result<Array2<f64>, Box<dyn Error>> {
+    let block = config.vocabulary / config.topics;
+    let mut topics = Array2::<f64>::zeros((config.topics, config.vocabulary));
+    for (topic, mut row) in topics.rows_mut().into_iter().enumerate() {
+        let weights = dirichlet(rng, config.beta, block)?;
+        for (offset, weight) in weights.into_iter().enumerate() {
+            row[topic * block + offset] = weight;
+        }
+    }
+    Ok(topics)
+}
+
+fn write_matrix(path: &Path, index: &[String], columns: &[String], values: &Array2<f64>) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    let mut header = vec![String::new()];
+    header.extend(columns.iter().cloned());
+    wtr.write_record(&header)?;
+    for (name, row) in index.iter().zip(values.rows()) {
+        let mut record = vec![name.clone()];
+        record.extend(row.iter().map(|x| x.to_string()));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Generates a corpus from planted topics (LDA's generative process: each document
+/// draws a topic mixture, then each word a topic and a word of that topic) into
+/// `output_dir`, with the true topic-word distributions in `TRUE_TOPIC_WORDS_FILE` and
+/// document mixtures in `TRUE_DOCUMENT_TOPICS_FILE` for `validate` to compare against.
+/// The same seed always gives the same corpus.
+pub fn run(output_dir: &str, config: &SyntheticConfig) -> Result<(), Box<dyn Error>> {
+    let max_vocabulary = CONSONANTS.len().pow(3) * VOWELS.len().pow(2);
+    if config.topics == 0 || config.vocabulary < config.topics || config.vocabulary > max_vocabulary {
+        return Err(format!("Need at least one topic and between {} and {} vocabulary words", config.topics.max(1), max_vocabulary).into());
+    }
+    if !(0.0..=1.0).contains(&config.noise) {
+        return Err(format!("Noise must be a share between 0 and 1, got {}", config.noise).into());
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let vocabulary: Vec<String> = (0..config.vocabulary).map(word).collect();
+    let topics = planted_topics(&mut rng, config)?;
+    let topic_words = topics
+        .rows()
+        .into_iter()
+        .map(|row| WeightedIndex::new(row.iter().copied()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let length = Poisson::new(config.mean_length)?;
+
+    let output = Path::new(output_dir);
+    fs::create_dir_all(output)?;
+    let mut corpus = match config.format {
+        CorpusFormat::Txt => {
+            let documents_dir = output.join(DOCUMENTS_DIR);
+            if documents_dir.exists() {
+                fs::remove_dir_all(&documents_dir)?;
+            }
+            fs::create_dir_all(&documents_dir)?;
+            None
+        }
+        CorpusFormat::Csv => {
+            let mut wtr = csv::Writer::from_path(output.join(CORPUS_FILE))?;
+            wtr.write_record(["id", "text"])?;
+            Some(wtr)
+        }
+    };
+
+    let width = config.documents.saturating_sub(1).to_string().len();
+    let mut names = Vec::with_capacity(config.documents);
+    let mut mixtures = Array2::<f64>::zeros((config.documents, config.topics));
+    for doc in 0..config.documents {
+        let mixture = dirichlet(&mut rng, config.alpha, config.topics)?;
+        let topic_of_word = WeightedIndex::new(&mixture)?;
+        let words = (length.sample(&mut rng) as usize).max(1);
+        let text = (0..words)
+            .map(|_| {
+                let index = if rng.gen_bool(config.noise) {
+                    rng.gen_range(0..config.vocabulary)
+                } else {
+                    topic_words[topic_of_word.sample(&mut rng)].sample(&mut rng)
+                };
+                vocabulary[index].as_str()
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let name = format!("doc_{:0width$}", doc, width = width);
+        match &mut corpus {
+            None => {
+                let mut file = BufWriter::new(File::create(output.join(DOCUMENTS_DIR).join(format!("{}.txt", name)))?);
+                writeln!(file, "{}", text)?;
+                file.flush()?;
+            }
+            Some(wtr) => wtr.write_record([name.as_str(), text.as_str()])?,
+        }
+        mixtures.row_mut(doc).assign(&ndarray::Array1::from(mixture));
+        names.push(name);
+    }
+    if let Some(mut wtr) = corpus {
+        wtr.flush()?;
+    }
+
+    let topic_names: Vec<String> = (0..config.topics).map(|topic| topic.to_string()).collect();
+    write_matrix(&output.join(TRUE_TOPIC_WORDS_FILE), &topic_names, &vocabulary, &topics)?;
+    write_matrix(&output.join(TRUE_DOCUMENT_TOPICS_FILE), &names, &topic_names, &mixtures)?;
+    progress!("Generated {} documents from {} planted topics over {} words in {}", config.documents, config.topics, config.vocabulary, output.display());
+    console::artifact("corpus", output);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::cluster::dominant_topic;
+use crate::modeling::{child_seed, nmf, FitResult, NmfOptions, TopWords, Topics};
+use crate::vocabulary::Vocabulary;
+use ndarray::{Array2, Axis};
+use serde::Serialize;
+
+pub const TOPIC_TREE_FILE: &str = "topic_tree.json";
+
+/// A topic with the documents it dominates and, on the first level, the subtopics
+/// found by factorizing those documents again.
+#[derive(Debug, Serialize)]
+pub struct TopicNode {
+    pub topic: usize,
+    pub words: String,
+    pub documents: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subtopics: Vec<TopicNode>,
+}
+
+/// Builds a two-level topic tree: each topic of `h` gets `options.k` subtopics fit on
+/// the TF-IDF rows of the documents whose dominant topic it is. Topics with fewer than
+/// `options.k` documents stay leaves. Nodes list the `top` words of their topic.
+pub(crate) fn topic_tree(v: &Array2<f32>, w: &Array2<f32>, h: &Array2<f32>, vocab: &Vocabulary, options: NmfOptions, top: TopWords) -> Vec<TopicNode> {
+    let k = options.k;
+    let mut members = vec![Vec::new(); h.nrows()];
+    for (doc_idx, row) in w.rows().into_iter().enumerate() {
+        if row.sum() > 0.0 {
+            members[dominant_topic(row).0].push(doc_idx);
+        }
+    }
+
+    Topics::new(h, vocab, top)
+        .iter()
+        .zip(members)
+        .map(|(topic, documents)| {
+            let subtopics = if documents.len() >= k {
+                // Each topic's fit draws from its own stream, whatever order the topics run in
+                let seed = options.seed.map(|seed| child_seed(seed, topic.index as u64));
+                let FitResult { w: w_sub, h: h_sub, .. } = nmf(&v.select(Axis(0), &documents), NmfOptions { seed, ..options });
+                let mut sizes = vec![0; k];
+                for row in w_sub.rows() {
+                    if row.sum() > 0.0 {
+                        sizes[dominant_topic(row).0] += 1;
+                    }
+                }
+                Topics::new(&h_sub, vocab, top)
+                    .iter()
+                    .zip(sizes)
+                    .map(|(subtopic, size)| TopicNode { topic: subtopic.index, words: subtopic.terms().join(" "), documents: size, subtopics: Vec::new() })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            TopicNode { topic: topic.index, words: topic.terms().join(" "), documents: documents.len(), subtopics }
+        })
+        .collect()
+}
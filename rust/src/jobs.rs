@@ -0,0 +1,151 @@
+use crate::CellOutcome;
+use preproccess::console;
+use preproccess::shutdown;
+use preproccess::workdir::Workdir;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Flags dropped from the arguments handed to children, which get their own `--workdir`.
+const PARENT_FLAGS: [&str; 3] = ["--jobs", "--workdir", "--temp-workdir"];
+
+/// The benchmark's own arguments minus `--jobs` and working directory flags.
+fn child_args() -> Vec<String> {
+    let mut args = Vec::new();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--jobs" || arg == "--workdir" {
+            iter.next();
+        } else if !PARENT_FLAGS.iter().any(|flag| arg == *flag || arg.starts_with(&format!("{}=", flag))) {
+            args.push(arg);
+        }
+    }
+    args
+}
+
+/// One (k, iteration, dataset) cell of the benchmark grid.
+pub struct CellJob {
+    /// Number of topics
+    pub k: usize,
+    pub iteration: usize,
+    pub dataset: usize,
+    pub input: String,
+    /// Where to keep a copy of the fitted model
+    pub keep_model: Option<PathBuf>,
+    /// Seed of the cell's NMF initialization
+    pub seed: u64,
+    /// Run only to warm the caches, its outcome is not recorded
+    pub warmup: bool,
+}
+
+fn run_child(exe: &Path, args: &[String], dir: &Path, cell: &CellJob) -> Result<CellOutcome, Box<dyn Error>> {
+    let output = dir.join("cell_result.json");
+    let mut command = Command::new(exe);
+    command
+        .args(args)
+        .arg("--workdir")
+        .arg(dir)
+        .arg("cell")
+        .arg("--input")
+        .arg(&cell.input)
+        .arg("--output")
+        .arg(&output)
+        .arg("--k")
+        .arg(cell.k.to_string())
+        .arg("--cell-seed")
+        .arg(cell.seed.to_string());
+    if let Some(keep_model) = &cell.keep_model {
+        command.arg("--keep-model").arg(keep_model);
+    }
+    if console::porcelain() {
+        // Children would otherwise print their own summaries to the shared stdout
+        command.stdout(Stdio::null());
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(format!("Benchmark cell for {} failed with {}", cell.input, status).into());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(&output)?)?)
+}
+
+/// Runs the given cells in up to `jobs` child processes,
+/// one per cell so each measures only its own CPU and memory. Outcomes are handed to
+/// `on_outcome` on the calling thread as cells finish, in completion order, or in the
+/// order of `cells` when `ordered`.
+pub fn run_cells<F>(cells: Vec<CellJob>, jobs: usize, ordered: bool, on_outcome: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&CellJob, CellOutcome) -> Result<(), Box<dyn Error>>,
+{
+    let exe = std::env::current_exe()?;
+    let args = child_args();
+    schedule(cells, jobs, ordered, |dir, cell| run_child(&exe, &args, dir, cell).map_err(|e| e.to_string()), on_outcome)
+}
+
+/// Runs the given cells on up to `threads` threads of this process, each cell with
+/// `run` in its own working directory. Cheaper than child processes, but the cells
+/// share the process: only their CPU time can be told apart. Outcomes are handed to
+/// `on_outcome` as with `run_cells`.
+pub fn run_cells_in_threads<R, F>(cells: Vec<CellJob>, threads: usize, ordered: bool, run: R, on_outcome: F) -> Result<(), Box<dyn Error>>
+where
+    R: Fn(&Workdir, &CellJob) -> Result<CellOutcome, Box<dyn Error>> + Sync,
+    F: FnMut(&CellJob, CellOutcome) -> Result<(), Box<dyn Error>>,
+{
+    schedule(cells, threads, ordered, |dir, cell| Workdir::new(dir).map_err(Into::into).and_then(|workdir| run(&workdir, cell)).map_err(|e| e.to_string()), on_outcome)
+}
+
+/// Hands the cells to `slots` worker threads, each running `run` with a working
+/// directory of its own. When `ordered`, outcomes that finish early are held back
+/// until those of all earlier cells have been handed on.
+fn schedule<R, F>(cells: Vec<CellJob>, slots: usize, ordered: bool, run: R, mut on_outcome: F) -> Result<(), Box<dyn Error>>
+where
+    R: Fn(&Path, &CellJob) -> Result<CellOutcome, String> + Sync,
+    F: FnMut(&CellJob, CellOutcome) -> Result<(), Box<dyn Error>>,
+{
+    let root = Workdir::temp()?;
+    let dirs: Vec<PathBuf> = (0..slots).map(|slot| root.root().join(format!("job{}", slot))).collect();
+
+    let queue = Mutex::new(cells.into_iter().enumerate().collect::<VecDeque<_>>());
+    let (tx, rx) = mpsc::channel();
+    let result = thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+        for dir in &dirs {
+            let tx = tx.clone();
+            let (queue, run) = (&queue, &run);
+            scope.spawn(move || {
+                while !shutdown::requested() {
+                    let Some((idx, cell)) = queue.lock().unwrap().pop_front() else { break };
+                    let outcome = run(dir, &cell);
+                    if tx.send((idx, cell, outcome)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // Finished cells by their index in `cells`, until they are handed on
+        let mut held = BTreeMap::new();
+        let mut next = 0;
+        for (idx, cell, outcome) in rx {
+            held.insert(idx, (cell, outcome));
+            loop {
+                let ready = if ordered { held.remove(&next) } else { held.pop_first().map(|(_, ready)| ready) };
+                let Some((cell, outcome)) = ready else { break };
+                next += 1;
+                let handled = outcome.map_err(Into::into).and_then(|outcome| on_outcome(&cell, outcome));
+                if let Err(e) = handled {
+                    // Let running cells finish but start no new ones
+                    queue.lock().unwrap().clear();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    });
+
+    std::fs::remove_dir_all(root.root())?;
+    result
+}
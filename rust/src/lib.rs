@@ -0,0 +1,58 @@
+/// Prints a line of progress output to stdout, like `println!`, unless the porcelain
+/// mode reserves stdout for the final JSON summary (see `console`).
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::console::porcelain() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod bootstrap;
+pub mod cluster;
+pub mod compare;
+pub mod compression;
+pub mod console;
+pub mod cooccurrence;
+pub mod corpus_diff;
+pub mod dates;
+pub mod dynamic;
+#[cfg(feature = "tui")]
+pub mod explore;
+#[cfg(feature = "arrow")]
+pub mod feather;
+pub mod filters;
+pub mod generate;
+pub mod hierarchy;
+pub mod lineage;
+pub mod mapped;
+pub mod matrix;
+pub mod modeling;
+pub mod normalize;
+pub mod phrases;
+pub mod pipeline;
+#[cfg(feature = "pos")]
+pub mod pos;
+pub mod preprocessing;
+pub mod prometheus;
+pub mod quality;
+pub mod readers;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod report_compare;
+pub mod sampling;
+pub mod score;
+pub mod serve;
+pub mod shutdown;
+pub mod similar;
+pub mod stability;
+pub mod timer;
+pub mod tokenizer;
+pub mod tune;
+pub mod validate;
+pub mod vocabulary;
+pub mod watch;
+pub mod weights;
+pub mod word_clusters;
+pub mod workdir;
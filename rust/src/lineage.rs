@@ -0,0 +1,251 @@
+use crate::compare::similarity_matrix;
+use crate::console;
+use crate::modeling::{NmfModel, TopWords, Topics};
+use crate::validate::match_topics;
+use ndarray::ArrayView1;
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+
+/// How each topic of a refit model descends from the topics of the model before it,
+/// one row per topic and update
+pub const LINEAGE_FILE: &str = "topic_lineage.csv";
+/// Cosine similarity from which a new topic counts as descending from an old one
+pub const LINEAGE_THRESHOLD: f32 = 0.5;
+
+/// What happened to a topic between two models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineageEvent {
+    /// Continues one previous topic, paired with it one-to-one
+    Matched,
+    /// One of several topics a previous topic split into
+    Split,
+    /// Continues several previous topics, the first the one it is paired with
+    Merged,
+    /// Like no previous topic
+    New,
+    /// A previous topic no current topic continues
+    Retired,
+}
+
+impl std::fmt::Display for LineageEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LineageEvent::Matched => "matched",
+            LineageEvent::Split => "split",
+            LineageEvent::Merged => "merged",
+            LineageEvent::New => "new",
+            LineageEvent::Retired => "retired",
+        })
+    }
+}
+
+/// One topic's place in the lineage of an update.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineageEntry {
+    /// Count of updates the lineage file has recorded, starting from 1
+    pub generation: usize,
+    /// Topic of the current model, empty for a retired topic
+    pub topic: Option<usize>,
+    /// Previous topics it descends from, separated by ';'
+    pub previous: String,
+    pub event: LineageEvent,
+    /// Highest cosine similarity to a previous topic (to a current one when retired),
+    /// empty when the other model has no topics
+    pub similarity: Option<f32>,
+    pub top_words: String,
+}
+
+/// Pairs the topics of `current` one-to-one with those of `previous` by cosine
+/// similarity over the terms the models share, keeping pairs at least `threshold`
+/// similar. A previous topic left unpaired but that similar to a current topic merged
+/// into it, otherwise it retired; an unpaired current topic that similar to a previous
+/// topic split off it, otherwise it is new.
+pub fn lineage(previous: &NmfModel, current: &NmfModel, threshold: f32, generation: usize, top: TopWords) -> Result<Vec<LineageEntry>, Box<dyn Error>> {
+    let similarities = similarity_matrix(previous, current)?;
+    // The most similar topic and its similarity, the first on ties
+    let closest = |values: ArrayView1<f32>| values.iter().copied().enumerate().reduce(|best, next| if next.1 > best.1 { next } else { best });
+
+    let mut paired_from = vec![None; current.h.nrows()];
+    let mut paired_to = vec![None; previous.h.nrows()];
+    let pairs = if similarities.is_empty() { Vec::new() } else { match_topics(&similarities)? };
+    for (p, c) in pairs.into_iter().enumerate() {
+        if let Some(c) = c.filter(|&c| similarities[[p, c]] >= threshold) {
+            paired_from[c] = Some(p);
+            paired_to[p] = Some(c);
+        }
+    }
+    let mut absorbed = vec![Vec::new(); current.h.nrows()];
+    let mut retired = Vec::new();
+    for p in (0..previous.h.nrows()).filter(|&p| paired_to[p].is_none()) {
+        match closest(similarities.row(p)) {
+            Some((c, s)) if s >= threshold => absorbed[c].push(p),
+            _ => retired.push(p),
+        }
+    }
+
+    let mut events: Vec<(LineageEvent, Vec<usize>)> = (0..current.h.nrows())
+        .map(|c| match (paired_from[c], absorbed[c].as_slice()) {
+            (Some(p), []) => (LineageEvent::Matched, vec![p]),
+            (Some(p), others) => (LineageEvent::Merged, std::iter::once(p).chain(others.iter().copied()).collect()),
+            (None, []) => match closest(similarities.column(c)) {
+                Some((p, s)) if s >= threshold => (LineageEvent::Split, vec![p]),
+                _ => (LineageEvent::New, Vec::new()),
+            },
+            (None, others) => (LineageEvent::Merged, others.to_vec()),
+        })
+        .collect();
+    // The topic paired with the parent of a split is one of its parts too
+    let parents: Vec<usize> = events.iter().filter(|(event, _)| *event == LineageEvent::Split).map(|(_, from)| from[0]).collect();
+    for sibling in parents.into_iter().filter_map(|parent| paired_to[parent]) {
+        if events[sibling].0 == LineageEvent::Matched {
+            events[sibling].0 = LineageEvent::Split;
+        }
+    }
+
+    let join = |topics: &[usize]| topics.iter().map(usize::to_string).collect::<Vec<_>>().join(";");
+    let (current_topics, previous_topics) = (current.topics(top), previous.topics(top));
+    let words = |topics: &Topics, topic: usize| topics.get(topic).map_or(String::new(), |t| t.terms().join(" "));
+    let mut entries: Vec<LineageEntry> = events
+        .into_iter()
+        .enumerate()
+        .map(|(c, (event, from))| LineageEntry {
+            generation,
+            topic: Some(c),
+            previous: join(&from),
+            event,
+            similarity: closest(similarities.column(c)).map(|(_, s)| s),
+            top_words: words(&current_topics, c),
+        })
+        .collect();
+    entries.extend(retired.into_iter().map(|p| LineageEntry {
+        generation,
+        topic: None,
+        previous: p.to_string(),
+        event: LineageEvent::Retired,
+        similarity: closest(similarities.row(p)).map(|(_, s)| s),
+        top_words: words(&previous_topics, p),
+    }));
+    Ok(entries)
+}
+
+/// Number of updates recorded in the lineage file at `path`, 0 if there is none.
+pub fn generations(path: &str) -> Result<usize, Box<dyn Error>> {
+    if !Path::new(path).exists() {
+        return Ok(0);
+    }
+    let mut rdr = csv::Reader::from_path(path)?;
+    let mut last = 0;
+    for record in rdr.records() {
+        last = last.max(record?.get(0).unwrap_or_default().parse().unwrap_or(0));
+    }
+    Ok(last)
+}
+
+/// Appends `entries` to the lineage file at `path`, creating it with a header.
+pub fn append(path: &str, entries: &[LineageEntry]) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(path).exists();
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let mut wtr = csv::WriterBuilder::new().has_headers(!exists).from_writer(file);
+    for entry in entries {
+        wtr.serialize(entry)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Records how `current` descends from `previous` as the next generation of the
+/// lineage file at `path`, and prints the topics that did not keep their place.
+pub fn record(previous: &NmfModel, current: &NmfModel, path: &str, top: TopWords) -> Result<Vec<LineageEntry>, Box<dyn Error>> {
+    let generation = generations(path)? + 1;
+    let entries = lineage(previous, current, LINEAGE_THRESHOLD, generation, top)?;
+    append(path, &entries)?;
+    console::artifact("lineage", path);
+    let count = |event: LineageEvent| entries.iter().filter(|entry| entry.event == event).count();
+    progress!(
+        "Topic lineage: {} matched, {} split, {} merged, {} new, {} retired, written to {}",
+        count(LineageEvent::Matched), count(LineageEvent::Split), count(LineageEvent::Merged), count(LineageEvent::New), count(LineageEvent::Retired), path
+    );
+    for entry in entries.iter().filter(|entry| entry.event != LineageEvent::Matched || entry.topic.map(|topic| topic.to_string()) != Some(entry.previous.clone())) {
+        match entry.topic {
+            Some(topic) if entry.previous.is_empty() => progress!("  Topic {} is {}: {}", topic, entry.event, entry.top_words),
+            Some(topic) => progress!("  Topic {} {} from {}: {}", topic, entry.event, entry.previous, entry.top_words),
+            None => progress!("  Previous topic {} retired: {}", entry.previous, entry.top_words),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vocabulary::{self, Vocabulary};
+    use crate::workdir::Workdir;
+    use ndarray::Array2;
+
+    const TERMS: [&str; 9] = ["a", "b", "c", "d", "e", "f", "g", "h", "i"];
+
+    /// A model over `TERMS` whose topics weigh the given terms equally.
+    fn model(topics: &[&str]) -> NmfModel {
+        let vocab = Vocabulary::build(vocabulary::document_frequencies(&[TERMS.map(String::from).to_vec()]), 1, &[]);
+        let mut h = Array2::<f32>::zeros((topics.len(), vocab.len()));
+        for (topic, terms) in topics.iter().enumerate() {
+            for term in terms.chars() {
+                h[[topic, vocab.get(&term.to_string()).unwrap()]] = 1.0;
+            }
+        }
+        NmfModel { idf: ndarray::Array1::ones(vocab.len()), vocab, h, fit: None }
+    }
+
+    fn events(entries: &[LineageEntry]) -> Vec<(Option<usize>, LineageEvent, &str)> {
+        entries.iter().map(|entry| (entry.topic, entry.event, entry.previous.as_str())).collect()
+    }
+
+    #[test]
+    fn lineage_classifies_splits_merges_and_retirements() {
+        // "abcd" splits into "ab" and "cd", "ef" and "g" merge into "efg", "h" retires
+        // and "i" is new
+        let previous = model(&["abcd", "ef", "g", "h"]);
+        let current = model(&["ab", "cd", "efg", "i"]);
+        let entries = lineage(&previous, &current, LINEAGE_THRESHOLD, 1, TopWords::default()).unwrap();
+        assert_eq!(events(&entries), [
+            (Some(0), LineageEvent::Split, "0"),
+            (Some(1), LineageEvent::Split, "0"),
+            (Some(2), LineageEvent::Merged, "1;2"),
+            (Some(3), LineageEvent::New, ""),
+            (None, LineageEvent::Retired, "3"),
+        ]);
+        assert_eq!(entries[3].similarity, Some(0.0));
+        assert!((entries[2].similarity.unwrap() - 2.0 / 6f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unchanged_topics_match_in_place() {
+        let previous = model(&["ab", "cd", "ef"]);
+        let current = model(&["cd", "ab", "ef"]);
+        let entries = lineage(&previous, &current, LINEAGE_THRESHOLD, 1, TopWords::default()).unwrap();
+        assert_eq!(events(&entries), [
+            (Some(0), LineageEvent::Matched, "1"),
+            (Some(1), LineageEvent::Matched, "0"),
+            (Some(2), LineageEvent::Matched, "2"),
+        ]);
+    }
+
+    #[test]
+    fn record_leaves_similarity_empty_without_previous_topics() {
+        let workdir = Workdir::temp().unwrap();
+        let path = workdir.path(LINEAGE_FILE);
+        let entries = record(&model(&[]), &model(&["ab", "cd"]), &path, TopWords::default()).unwrap();
+        assert_eq!(events(&entries), [(Some(0), LineageEvent::New, ""), (Some(1), LineageEvent::New, "")]);
+        assert!(entries.iter().all(|entry| entry.similarity.is_none()));
+
+        record(&model(&["ab", "cd"]), &model(&["ab", "cd"]), &path, TopWords::default()).unwrap();
+        assert_eq!(generations(&path).unwrap(), 2);
+        let mut rdr = csv::Reader::from_path(&path).unwrap();
+        let similarities: Vec<Option<f32>> = rdr.records().map(|record| record.unwrap()[4].parse().ok()).collect();
+        assert_eq!(similarities[..2], [None, None]);
+        assert!(similarities[2..].iter().all(|similarity| (similarity.unwrap() - 1.0).abs() < 1e-6));
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+}
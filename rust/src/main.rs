@@ -1,125 +1,1549 @@
-mod preprocessing;
-mod modeling;
+mod cli;
+mod environment;
+mod jobs;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod perf;
+mod preflight;
+mod provenance;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
-use std::fs::File;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
 use std::io::{Write, BufWriter};
+#[cfg(any(windows, target_os = "linux"))]
 use std::mem;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use sysinfo::{Pid, System, ProcessesToUpdate};
+use time::OffsetDateTime;
+#[cfg(windows)]
 use winapi::shared::minwindef::FILETIME;
-use winapi::um::processthreadsapi::GetProcessTimes;
+#[cfg(windows)]
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentThread, GetProcessTimes, GetThreadTimes};
+#[cfg(windows)]
+use winapi::um::winbase::GetProcessIoCounters;
+#[cfg(windows)]
+use winapi::um::winnt::IO_COUNTERS;
 use csv::Writer;
+use clap::{CommandFactory, FromArgMatches};
+use regex::Regex;
+use cli::{Cli, Command, MetricsFormat};
+use environment::StepThermal;
+use jobs::CellJob;
+use preproccess::compression::{self, Compression, Encoder};
+use preproccess::console;
+use preproccess::modeling::{self, EarlyStopping, ModelConfig, ModelSummary, NmfModel, StoppingRule, TopWords, TopicWords};
+use preproccess::normalize::NormalizeConfig;
+use preproccess::phrases::PhraseConfig;
+use preproccess::pipeline::{self, Instrument, PipelineBuilder, StepOutput};
+use preproccess::progress;
+use preproccess::preprocessing::{self, PreprocessConfig, PreprocessingSummary};
+use preproccess::quality::TOPIC_QUALITY_FILE;
+use preproccess::timer::StepTimer;
+use preproccess::tokenizer::TokenizerConfig;
+use preproccess::vocabulary::VocabularyFilter;
+use preproccess::generate::{self, SyntheticConfig};
+use preproccess::tune::{self, PipelineFile};
+use preproccess::{bootstrap, cluster, compare, cooccurrence, corpus_diff, dynamic, lineage, matrix, report_compare, score, serve, shutdown, similar, stability, validate, watch, word_clusters};
+#[cfg(feature = "tui")]
+use preproccess::explore;
+use preproccess::workdir::{Workdir, DISTRIBUTIONS_FILE};
 
-fn initialize_csv(sample: usize) -> Result<Writer<File>, Box<dyn std::error::Error>> {
-    // Specify the output directory
-    let output_dir = "../rust_metrics"; // Adjust this path as needed
-    if !std::path::Path::new(output_dir).exists() {
-        std::fs::create_dir_all(output_dir)?; // Create the directory if it doesn't exist
+/// Time, memory and CPU readings of every run of one step at one sample size.
+#[derive(Default)]
+struct StepReadings {
+    /// (iteration, dataset) of each reading
+    cells: Vec<(usize, usize)>,
+    time: Vec<f64>,
+    memory_delta: Vec<f64>,
+    memory_peak: Vec<f64>,
+    cpu: Vec<f64>,
+    cpu_seconds: Vec<f64>,
+}
+
+/// Mean, sample standard deviation, min and max.
+fn describe(values: &[f64]) -> [f64; 4] {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = if values.len() > 1 {
+        values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    [mean, variance.sqrt(), min, max]
+}
+
+/// Median of `values`, which must not be empty.
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2.0 } else { sorted[mid] }
+}
+
+/// Median and median absolute deviation, the latter scaled by 1.4826 so it estimates
+/// the standard deviation of normally distributed values.
+fn median_mad(values: &[f64]) -> (f64, f64) {
+    let center = median(values);
+    let deviations: Vec<f64> = values.iter().map(|x| (x - center).abs()).collect();
+    (center, 1.4826 * median(&deviations))
+}
+
+/// Collects readings over the whole grid and writes per-(sample, k, step) statistics to
+/// summary.csv once it completes, with the step times more than `outlier_mads` median
+/// absolute deviations from their median listed in outliers.csv.
+struct SummarySink {
+    dir: PathBuf,
+    outlier_mads: f64,
+    readings: Vec<((usize, usize, String), StepReadings)>,
+}
+
+impl SummarySink {
+    fn new(dir: &Path, outlier_mads: f64) -> SummarySink {
+        SummarySink { dir: dir.to_path_buf(), outlier_mads, readings: Vec::new() }
+    }
+
+    /// Indices of the readings whose time is an outlier. A MAD of zero flags nothing,
+    /// since any difference from the median would count.
+    fn outliers(&self, readings: &StepReadings) -> (f64, f64, Vec<usize>) {
+        let (center, mad) = median_mad(&readings.time);
+        let outliers = (0..readings.time.len()).filter(|&idx| mad > 0.0 && (readings.time[idx] - center).abs() > self.outlier_mads * mad).collect();
+        (center, mad, outliers)
+    }
+
+    fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.dir.join("summary.csv");
+        let mut writer = Writer::from_path(&path)?;
+        let mut header = vec!["Sample".to_string(), "K".to_string(), "Step".to_string(), "Runs".to_string()];
+        for metric in ["Time (s)", "RSS Delta (MiB)", "RSS Peak (MiB)", "CPU Usage (%)", "CPU Time (s)"] {
+            for stat in ["Mean", "Std", "Min", "Max"] {
+                header.push(format!("{} {}", metric, stat));
+            }
+        }
+        header.extend(["Time (s) Median", "Time (s) MAD", "Time Outliers"].map(String::from));
+        writer.write_record(&header)?;
+
+        let outliers_path = self.dir.join("outliers.csv");
+        let mut outlier_writer = Writer::from_path(&outliers_path)?;
+        outlier_writer.write_record(["Sample", "K", "Step", "Iteration", "Dataset", "Time (s)", "Median (s)", "MADs"])?;
+        let mut flagged = 0;
+        for ((sample, k, step), readings) in &self.readings {
+            let mut row = vec![sample.to_string(), k.to_string(), step.clone(), readings.time.len().to_string()];
+            for values in [&readings.time, &readings.memory_delta, &readings.memory_peak, &readings.cpu, &readings.cpu_seconds] {
+                row.extend(describe(values).iter().map(|x| x.to_string()));
+            }
+            let (center, mad, outliers) = self.outliers(readings);
+            row.extend([center.to_string(), mad.to_string(), outliers.len().to_string()]);
+            writer.write_record(&row)?;
+
+            for idx in outliers {
+                let (iteration, dataset) = readings.cells[idx];
+                let time = readings.time[idx];
+                let mads = (time - center) / mad;
+                outlier_writer.serialize((sample, k, step, iteration, dataset, time, center, mads))?;
+                progress!("Outlier: N={} k={} {} iteration {} dataset {} took {:.3}s, median {:.3}s ({:+.1} MADs)", sample, k, step, iteration, dataset, time, center, mads);
+                flagged += 1;
+            }
+        }
+        writer.flush()?;
+        outlier_writer.flush()?;
+        progress!("Aggregate statistics written to {}", path.display());
+        console::artifact("summary", &path);
+        console::artifact("outliers", &outliers_path);
+        console::metric("outliers", flagged);
+        if flagged > 0 {
+            progress!("{} outlier step times listed in {}", flagged, outliers_path.display());
+        }
+        Ok(())
+    }
+}
+
+impl MetricsSink for SummarySink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let key = (record.sample, record.k, record.step.to_string());
+        let position = match self.readings.iter().position(|(k, _)| *k == key) {
+            Some(position) => position,
+            None => {
+                self.readings.push((key, StepReadings::default()));
+                self.readings.len() - 1
+            }
+        };
+        let readings = &mut self.readings[position].1;
+        readings.cells.push((record.iteration, record.dataset));
+        readings.time.push(record.metrics.elapsed.as_secs_f64());
+        readings.memory_delta.push(record.metrics.memory.rss_delta_mib());
+        readings.memory_peak.push(record.metrics.memory.rss_peak_mib);
+        readings.cpu.push(record.metrics.cpu_usage);
+        readings.cpu_seconds.push(record.metrics.cpu_seconds);
+        Ok(())
     }
 
-    // Create the file in the output directory
-    let filepath = format!("{}/N{}_metrics.csv", output_dir, sample);
-    let writer = Writer::from_path(filepath)?;
-    Ok(writer)
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write()
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting Data Analysis Pipeline");
+/// topic_quality.csv in the run directory: diversity and redundancy of the topics of
+/// every fitted dataset, see `preproccess::quality`.
+struct QualitySink {
+    writer: Writer<File>,
+}
 
-    // Number of iterations
-    let iterations = 5;
-    let datasets = 100;
+impl QualitySink {
+    fn new(dir: &Path) -> Result<QualitySink, Box<dyn std::error::Error>> {
+        let path = dir.join(TOPIC_QUALITY_FILE);
+        let appended = path.exists();
+        let mut writer = Writer::from_writer(OpenOptions::new().create(true).append(true).open(path)?);
+        if !appended {
+            writer.write_record([
+                "Sample", "K", "Iteration", "Dataset", "Diversity", "Mean Overlap", "Max Overlap", "Most Similar Topics",
+            ])?;
+        }
+        Ok(QualitySink { writer })
+    }
+}
 
-    for sample in [100, 250, 500, 750, 1000] {
-        // Initialize a new CSV file for each sample
-        let mut writer = initialize_csv(sample)?;
-        writer.write_record(&["Iteration", "Dataset", "Step", "Time (s)", "Memory (MB)", "CPU Usage (%)", "Topics"])?;
+impl MetricsSink for QualitySink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(quality) = record.model().and_then(|summary| summary.quality.as_ref()) else {
+            return Ok(());
+        };
+        self.writer.write_record([
+            record.sample.to_string(),
+            record.k.to_string(),
+            record.iteration.to_string(),
+            record.dataset.to_string(),
+            quality.diversity.to_string(),
+            quality.mean_overlap.to_string(),
+            quality.max_overlap.to_string(),
+            quality.most_similar.map(|(a, b)| format!("{} {}", a, b)).unwrap_or_default(),
+        ])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
 
-        for i in 0..iterations {
-            for j in 0..datasets {
-                println!("\nIteration {}/{}", i + 1, iterations);
-                println!("Dataset {}/{}", j + 1, datasets);
-                println!("========================================");
+/// Parent of the per-run metrics directories.
+const METRICS_ROOT: &str = "../rust_metrics";
+
+/// The benchmark grid: every sample size runs each of its datasets `ITERATIONS` times.
+const ITERATIONS: usize = 5;
+const DATASETS: usize = 100;
+const SAMPLES: [usize; 5] = [100, 250, 500, 750, 1000];
+
+/// Cells of the benchmark grid completed so far, one JSON object per line.
+const PROGRESS_FILE: &str = "progress.jsonl";
 
-                println!("Starting Data Analysis Pipeline");
-                measure_step(
-                    "preprocessing",
-                    i + 1,
-                    sample,
-                    j + 1,
-                    || preprocessing::start(&format!("../bootstrap_samples/N_{}/sample_{}", sample, j + 1)),
-                    &mut writer,
-                )?;
-                measure_step("modeling", i + 1, sample, j + 1, || modeling::start(), &mut writer)?;
+/// Cost of the preprocessing and of each configuration `tune` tried, in the workdir
+const TUNE_METRICS_FILE: &str = "tune_metrics.csv";
+
+/// Creates `rust_metrics/<run_id>`, named after the current UTC time unless a run id is
+/// given. Refuses to reuse an existing directory so earlier results are never overwritten.
+fn create_run_dir(run_id: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let run_id = match run_id {
+        Some(run_id) => run_id.to_string(),
+        None => {
+            let now = OffsetDateTime::now_utc();
+            format!("{:04}{:02}{:02}-{:02}{:02}{:02}", now.year(), now.month() as u8, now.day(), now.hour(), now.minute(), now.second())
+        }
+    };
+    let dir = Path::new(METRICS_ROOT).join(&run_id);
+    if dir.exists() {
+        return Err(format!("Results for run {} already exist in {}", run_id, dir.display()).into());
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Opens the results directory of an interrupted run, refusing to resume it with
+/// parameters other than those its manifest.json records, whose rows wouldn't be
+/// comparable with the ones already written.
+fn resume_run_dir(run_id: &str, preprocess_config: &PreprocessConfig, config: &ModelConfig, grid: &serde_json::Value) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = Path::new(METRICS_ROOT).join(run_id);
+    if !dir.join(PROGRESS_FILE).exists() {
+        return Err(format!("{} has no {}; the run is complete or was never started", dir.display(), PROGRESS_FILE).into());
+    }
+    let manifest_path = dir.join("manifest.json");
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)
+        .map_err(|e| format!("{}: {}", manifest_path.display(), e))?;
+    let current = [
+        ("seed", serde_json::json!(config.seed)),
+        ("grid", grid.clone()),
+        ("preprocessing", preprocess_params(preprocess_config)),
+        ("modeling", model_params(config)),
+    ];
+    let mut mismatches = Vec::new();
+    for (section, current) in &current {
+        let recorded = &manifest[section];
+        match (recorded.as_object(), current.as_object()) {
+            (Some(recorded), Some(current)) => {
+                for (key, value) in current {
+                    if recorded.get(key).unwrap_or(&serde_json::Value::Null) != value {
+                        mismatches.push(format!("{}.{}: {} in the run, {} now", section, key, recorded.get(key).unwrap_or(&serde_json::Value::Null), value));
+                    }
+                }
             }
+            _ if recorded != current => mismatches.push(format!("{}: {} in the run, {} now", section, recorded, current)),
+            _ => {}
         }
     }
-    Ok(())
+    if !mismatches.is_empty() {
+        return Err(format!("Run {} was started with other parameters, resume it with the same options ({})", run_id, mismatches.join("; ")).into());
+    }
+    Ok(dir)
 }
 
-fn measure_step<F>(
-    name: &str,
+/// Opens a metrics file, appending when a resumed run already wrote to it. Returns
+/// whether it was appended to.
+fn metrics_file(dir: &Path, sample: usize, extension: &str, compression: Compression) -> Result<(Encoder, bool), Box<dyn std::error::Error>> {
+    let filepath = compression.path(&dir.join(format!("N{}_metrics.{}", sample, extension)).to_string_lossy());
+    if Path::new(&filepath).exists() {
+        Ok((compression::append(filepath)?, true))
+    } else {
+        Ok((compression::create(filepath)?, false))
+    }
+}
+
+/// Completed cells of the grid, appended to progress.jsonl as each one finishes so an
+/// interrupted run can pick up where it stopped.
+struct Progress {
+    writer: File,
+    completed: HashSet<(usize, usize, usize, usize)>,
+}
+
+#[derive(serde::Deserialize)]
+struct CompletedCell {
+    sample: usize,
+    /// Absent in runs without `--k-values`, which used the model's k
+    #[serde(default)]
+    k: Option<usize>,
     iteration: usize,
+    dataset: usize,
+    outcome: CellOutcome,
+}
+
+impl CompletedCell {
+    fn k(&self) -> usize {
+        self.k.expect("set by Progress::open")
+    }
+}
+
+impl Progress {
+    /// Opens the progress file of `dir`, returning the cells it already records. Cells
+    /// recorded without a topic count get `default_k`.
+    fn open(dir: &Path, default_k: usize) -> Result<(Progress, Vec<CompletedCell>), Box<dyn std::error::Error>> {
+        let path = dir.join(PROGRESS_FILE);
+        let mut cells = Vec::new();
+        if path.exists() {
+            for line in std::fs::read_to_string(&path)?.lines() {
+                // A line cut short by a hard kill is simply run again
+                if let Ok(mut cell) = serde_json::from_str::<CompletedCell>(line) {
+                    cell.k = cell.k.or(Some(default_k));
+                    cells.push(cell);
+                }
+            }
+        }
+        let completed = cells.iter().map(|cell| (cell.sample, cell.k(), cell.iteration, cell.dataset)).collect();
+        let writer = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok((Progress { writer, completed }, cells))
+    }
+
+    fn contains(&self, sample: usize, k: usize, iteration: usize, dataset: usize) -> bool {
+        self.completed.contains(&(sample, k, iteration, dataset))
+    }
+
+    fn record(&mut self, sample: usize, k: usize, iteration: usize, dataset: usize, outcome: &CellOutcome) -> Result<(), Box<dyn std::error::Error>> {
+        let line = serde_json::json!({
+            "sample": sample,
+            "k": k,
+            "iteration": iteration,
+            "dataset": dataset,
+            "outcome": outcome,
+        });
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()?;
+        self.completed.insert((sample, k, iteration, dataset));
+        Ok(())
+    }
+}
+
+/// One measured pipeline step.
+struct StepRecord<'a> {
     sample: usize,
+    /// Number of topics the dataset was modeled with
+    k: usize,
+    iteration: usize,
     dataset: usize,
-    step: F,
-    writer: &mut Writer<File>,
-) -> Result<(), Box<dyn std::error::Error>>
+    step: &'a str,
+    metrics: &'a StepMetrics,
+    output: Option<&'a StepOutput>,
+    /// Wall time of the step's sub-stages
+    substeps: &'a [(String, Duration)],
+    /// Dominant topic and its share of each document, when collected
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    assignments: &'a [(usize, f32)],
+}
+
+impl StepRecord<'_> {
+    /// Summary of the fitted model, for the modeling step.
+    fn model(&self) -> Option<&ModelSummary> {
+        match self.output {
+            Some(StepOutput::Modeling(summary)) => Some(summary),
+            _ => None,
+        }
+    }
+
+    /// Summary of the preprocessed corpus, for the preprocessing step.
+    fn preprocessed(&self) -> Option<&PreprocessingSummary> {
+        match self.output {
+            Some(StepOutput::Preprocessing(summary)) => Some(summary),
+            _ => None,
+        }
+    }
+}
+
+/// Destination for the benchmark's per-step metrics.
+trait MetricsSink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Called once the whole grid has run.
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Columns of N{sample}_metrics.csv. Sizes are in MiB (2^20 bytes); RSS is resident
+/// memory and Virtual the process's virtual size, each before, after and at the peak of
+/// the step, with the change over it. Final Error is the relative error ‖V − WH‖/‖V‖ of
+/// the last iteration; Frobenius Error, Relative Error (‖V − WH‖²/‖V‖²) and Explained
+/// Variance are of the factors the model was saved with. CPU MHz is the mean clock of the
+/// logical cores and CPU Temp the hottest sensor, each read just before and after the step.
+const METRICS_HEADER: [&str; 37] = [
+    "Iteration", "Dataset", "K", "Step", "Time (s)",
+    "RSS Before (MiB)", "RSS After (MiB)", "RSS Delta (MiB)", "RSS Peak (MiB)",
+    "Virtual Before (MiB)", "Virtual After (MiB)", "Virtual Delta (MiB)", "Virtual Peak (MiB)",
+    "CPU Usage (%)", "CPU Time (s)", "Topics", "Held-out Error",
+    "NMF Iterations", "Time per Iteration (s)", "H Update (s)", "W Update (s)", "Error Computation (s)", "Final Error",
+    "Frobenius Error", "Relative Error", "Explained Variance",
+    "Read (MiB)", "Written (MiB)", "Read Ops", "Write Ops", "Instructions", "Cache Misses", "Branch Misses",
+    "CPU MHz Before", "CPU MHz After", "CPU Temp Before (C)", "CPU Temp After (C)",
+];
+
+/// Types of the `METRICS_HEADER` columns in N{sample}_metrics.arrow.
+#[cfg(feature = "arrow")]
+const METRICS_COLUMNS: [preproccess::feather::Column; 37] = {
+    use preproccess::feather::Column::{Float, Int, Text};
+    [
+        Int, Int, Int, Text, Float,
+        Float, Float, Float, Float,
+        Float, Float, Float, Float,
+        Float, Float, Text, Float,
+        Int, Float, Float, Float, Float, Float,
+        Float, Float, Float,
+        Float, Float, Int, Int, Int, Int, Int,
+        Float, Float, Float, Float,
+    ]
+};
+
+/// Flat N{sample}_metrics.csv, one row per step.
+struct CsvSink {
+    writer: Option<Writer<Encoder>>,
+}
+
+impl CsvSink {
+    fn new(dir: &Path, sample: usize, compression: Compression) -> Result<CsvSink, Box<dyn std::error::Error>> {
+        let (file, appended) = metrics_file(dir, sample, "csv", compression)?;
+        let mut writer = Writer::from_writer(file);
+        if !appended {
+            writer.write_record(METRICS_HEADER)?;
+        }
+        Ok(CsvSink { writer: Some(writer) })
+    }
+}
+
+impl MetricsSink for CsvSink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = self.writer.as_mut().ok_or("metrics file already finished")?;
+        for row in metrics_rows(record) {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(writer) = self.writer.take() {
+            compression::finish_csv(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// N{sample}_metrics.jsonl, one JSON object per step carrying the run parameters and
+/// nested NMF timings alongside the CSV fields.
+struct JsonlSink {
+    writer: Encoder,
+    seed: u64,
+    params: serde_json::Value,
+}
+
+/// Modeling parameters recorded alongside the metrics.
+fn model_params(config: &ModelConfig) -> serde_json::Value {
+    serde_json::json!({
+        "k": config.k,
+        "solver": config.solver,
+        "early_stopping": config.early_stopping.map(|e| serde_json::json!({"fraction": e.fraction, "patience": e.patience})),
+        "min_df": config.min_df,
+        "idf": config.idf,
+        "max_iter": config.max_iter,
+        "tol": config.tol,
+        "lambda": config.lambda,
+        "stopping": {
+            "convergence": config.stopping.convergence,
+            "error_floor": config.stopping.error_floor,
+            "gradient_tol": config.stopping.gradient_tol,
+            "time_budget_s": config.stopping.time_budget.map(|budget| budget.as_secs_f64()),
+        },
+        "holdout": config.holdout,
+        "deterministic": config.deterministic,
+        "seed_topics": config.seed_topics.len(),
+        "auto_stopwords": config.auto_stopwords,
+        "vocab_filter": {
+            "exclude_patterns": config.vocab_filter.exclude_patterns.iter().map(|pattern| pattern.as_str()).collect::<Vec<_>>(),
+            "max_repeat": config.vocab_filter.max_repeat,
+            "blocklist_terms": config.vocab_filter.blocklist.len(),
+            "allowlist_terms": config.vocab_filter.allowlist.as_ref().map(|terms| terms.len()),
+        },
+        "exclude_empty": config.exclude_empty,
+        "warm_start": config.warm_start,
+        "document_weights": config.document_weights,
+        "mapped": config.mapped,
+        "max_memory": config.max_memory,
+        "seed_strength": config.seed_strength,
+        "background": config.background,
+        "subtopics": config.subtopics,
+        "top_words": {"count": config.top_words.count, "min_weight": config.top_words.min_weight},
+    })
+}
+
+/// Preprocessing parameters recorded in manifest.json.
+fn preprocess_params(preprocess_config: &PreprocessConfig) -> serde_json::Value {
+    let tokenizer = &preprocess_config.tokenizer;
+    serde_json::json!({
+        "text_column": preprocess_config.text_column,
+        "id_column": preprocess_config.id_column,
+        "token_pattern": tokenizer.token_pattern,
+        "strip_pattern": tokenizer.strip_pattern,
+        "keep_digits": tokenizer.keep_digits,
+        "numbers": format!("{:?}", tokenizer.numbers),
+        "hyphens": format!("{:?}", tokenizer.hyphens),
+        "strip_urls": tokenizer.strip_urls,
+        "strip_emails": tokenizer.strip_emails,
+        "nfkc": preprocess_config.normalize.nfkc,
+        "fold_accents": preprocess_config.normalize.fold_accents,
+        "spelling_words": preprocess_config.normalize.spelling_words,
+        "spelling_distance": preprocess_config.normalize.spelling_distance,
+        "char_ngrams": preprocess_config.char_ngrams.map(|n| serde_json::json!({"min": n.min, "max": n.max})),
+        "stemmer": format!("{:?}", preprocess_config.stemmer),
+        "stem_language": preprocess_config.stem_language,
+        "default_stopwords": preprocess_config.default_stopwords,
+        "stopword_files": preprocess_config.stopword_files,
+        "extra_stopwords": preprocess_config.extra_stopwords,
+        "stopword_stage": format!("{:?}", preprocess_config.stopword_stage),
+        "min_token_length": preprocess_config.min_token_length,
+        "max_token_length": preprocess_config.max_token_length,
+        "token_exclude_patterns": preprocess_config.token_exclude_patterns.iter().map(|pattern| pattern.as_str()).collect::<Vec<_>>(),
+        "phrases": preprocess_config.phrases.as_ref().map(|p| serde_json::json!({
+            "min_count": p.min_count,
+            "threshold": p.threshold,
+        })),
+    })
+}
+
+/// Writes manifest.json describing how the run's metrics were produced: the
+/// arguments and parameters, the build and host, and the content hashes of the
+/// datasets and other input files.
+fn write_manifest(dir: &Path, preprocess_config: &PreprocessConfig, config: &ModelConfig, grid: serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Hashing the inputs for manifest.json...");
+    let input_files = preprocess_config.stopword_files.iter()
+        .chain(&preprocess_config.dates)
+        .chain(&preprocess_config.normalize.spelling_words)
+        .chain(&config.vocab_path)
+        .chain(&config.warm_start)
+        .chain(&config.document_weights)
+        .map(String::as_str)
+        .chain(Path::new(preprocessing::STOPWORDS_FILE).exists().then_some(preprocessing::STOPWORDS_FILE));
+    let environment = environment::context();
+    if environment["on_battery"] == true {
+        progress!("Warning: running on battery power, timings may be throttled");
+    }
+    let manifest = serde_json::json!({
+        "run_id": dir.file_name().map(|name| name.to_string_lossy()),
+        "arguments": std::env::args().skip(1).collect::<Vec<_>>(),
+        "grid": grid,
+        "seed": config.seed,
+        "logical_cores": logical_cores(),
+        "build": provenance::build(),
+        "host": provenance::host(),
+        "environment": environment,
+        "inputs": {
+            "datasets": provenance::datasets(&SAMPLES),
+            "files": provenance::files(input_files),
+        },
+        "preprocessing": preprocess_params(preprocess_config),
+        "modeling": model_params(config),
+        "vocab": config.vocab_path,
+    });
+    std::fs::write(dir.join("manifest.json"), serde_json::to_string_pretty(&manifest)?)?;
+    console::artifact("manifest", dir.join("manifest.json"));
+    Ok(())
+}
+
+impl JsonlSink {
+    fn new(dir: &Path, sample: usize, config: &ModelConfig) -> Result<JsonlSink, Box<dyn std::error::Error>> {
+        Ok(JsonlSink {
+            writer: metrics_file(dir, sample, "jsonl", config.compression)?.0,
+            seed: config.seed,
+            params: model_params(config),
+        })
+    }
+}
+
+impl MetricsSink for JsonlSink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let mut params = self.params.clone();
+        params["k"] = record.k.into();
+        let nmf = record.model().map(|summary| {
+            let timings = &summary.timings;
+            serde_json::json!({
+                "iterations": timings.iterations,
+                "time_per_iteration_s": timings.per_iteration().as_secs_f64(),
+                "h_update_s": timings.h_update.as_secs_f64(),
+                "w_update_s": timings.w_update.as_secs_f64(),
+                "error_s": timings.error.as_secs_f64(),
+                "final_error": timings.errors.last(),
+                "errors": timings.errors,
+                "fit": summary.fit,
+            })
+        });
+        let line = serde_json::json!({
+            "sample": record.sample,
+            "iteration": record.iteration,
+            "dataset": record.dataset,
+            "k": record.k,
+            "step": record.step,
+            "time_s": record.metrics.elapsed.as_secs_f64(),
+            "memory": record.metrics.memory,
+            "cpu_usage": record.metrics.cpu_usage,
+            "cpu_seconds": record.metrics.cpu_seconds,
+            "seed": self.seed,
+            "params": params,
+            "topics": record.model().map(|s| &s.topics),
+            "heldout_error": record.model().and_then(|s| s.heldout_error),
+            "preprocessing": record.preprocessed(),
+            "nmf": nmf,
+            "io": record.metrics.io,
+            "hardware": record.metrics.hardware,
+            "thermal": record.metrics.thermal,
+            "substeps": record.substeps.iter()
+                .map(|(name, elapsed)| (name.clone(), serde_json::json!(elapsed.as_secs_f64())))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// N{sample}_metrics.arrow with the CSV rows. An Arrow IPC file can't be appended to,
+/// so it is rewritten after every step and always holds the steps run so far.
+#[cfg(feature = "arrow")]
+struct ArrowSink {
+    path: String,
+    rows: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "arrow")]
+impl ArrowSink {
+    fn new(dir: &Path, sample: usize) -> ArrowSink {
+        ArrowSink { path: dir.join(format!("N{}_metrics.arrow", sample)).to_string_lossy().into_owned(), rows: Vec::new() }
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl MetricsSink for ArrowSink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn std::error::Error>> {
+        self.rows.extend(metrics_rows(record));
+        let columns: Vec<_> = METRICS_HEADER.into_iter().zip(METRICS_COLUMNS).collect();
+        preproccess::feather::write_table(&columns, &self.rows, &self.path)
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    console::set_porcelain(cli.porcelain);
+    let result = run(cli);
+    if console::porcelain() {
+        let command = matches.subcommand_name().unwrap_or("benchmark");
+        println!("{}", console::summary(command, result.as_ref().err().map(ToString::to_string).as_deref()));
+    }
+    result
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let tokenizer = TokenizerConfig {
+        token_pattern: cli.token_pattern.clone(),
+        strip_pattern: cli.strip_pattern.clone(),
+        keep_digits: cli.keep_digits,
+        numbers: cli.numbers,
+        hyphens: cli.hyphens,
+        strip_urls: cli.strip_urls,
+        strip_emails: cli.strip_emails,
+    };
+    let workdir = if cli.temp_workdir {
+        let workdir = Workdir::temp()?;
+        progress!("Working directory: {}", workdir.root().display());
+        workdir
+    } else {
+        Workdir::new(&cli.workdir)?
+    };
+    let model_file = |model: Option<String>| model.unwrap_or_else(|| workdir.path(modeling::MODEL_FILE));
+    let preprocess_config = PreprocessConfig {
+        text_column: cli.text_column.clone(),
+        id_column: cli.id_column.clone(),
+        compression: cli.compress,
+        tokenizer,
+        normalize: NormalizeConfig {
+            nfkc: cli.nfkc,
+            fold_accents: cli.fold_accents,
+            spelling_words: cli.spelling_words.clone(),
+            spelling_distance: cli.spelling_distance,
+        },
+        char_ngrams: cli.char_ngrams,
+        stopword_stage: cli.stopword_stage,
+        min_token_length: cli.min_token_length,
+        max_token_length: cli.max_token_length,
+        token_exclude_patterns: cli.token_exclude_patterns.iter().map(|pattern| Regex::new(pattern)).collect::<Result<_, _>>()?,
+        stemmer: cli.stemmer,
+        stem_language: cli.stem_language.clone(),
+        default_stopwords: !cli.no_default_stopwords,
+        stopword_files: cli.stopword_files.clone(),
+        extra_stopwords: cli.extra_stopwords.clone(),
+        phrases: cli.phrases.then_some(PhraseConfig {
+            min_count: cli.phrase_min_count,
+            threshold: cli.phrase_threshold,
+        }),
+        #[cfg(feature = "pos")]
+        pos: cli.pos_tags.clone().map(|tags| preproccess::pos::PosConfig {
+            model_path: cli.pos_model.clone(),
+            tags,
+        }),
+        dates: cli.dates.clone(),
+        workdir: workdir.clone(),
+        #[cfg(feature = "arrow")]
+        arrow: cli.arrow,
+        #[cfg(feature = "remote")]
+        remote_cache: cli.remote_cache.clone(),
+    };
+    let seed_topics = match &cli.seed_topics {
+        Some(path) => modeling::load_seed_topics(path, &preprocess_config)?,
+        None => Vec::new(),
+    };
+    let vocab_filter = VocabularyFilter {
+        exclude_patterns: cli.exclude_patterns.iter().map(|pattern| Regex::new(pattern)).collect::<Result<_, _>>()?,
+        max_repeat: cli.max_repeat,
+        blocklist: cli.vocab_blocklist.as_deref().map(VocabularyFilter::load_list).transpose()?.unwrap_or_default(),
+        allowlist: cli.vocab_allowlist.as_deref().map(VocabularyFilter::load_list).transpose()?,
+    };
+    let pipeline_file = cli.config.as_deref().map(PipelineFile::load).transpose()?.unwrap_or_default();
+    let defaults = ModelConfig::default();
+    let config = ModelConfig {
+        k: pipeline_file.k.unwrap_or(defaults.k),
+        min_df: pipeline_file.min_df.unwrap_or(defaults.min_df),
+        idf: cli.idf,
+        lambda: pipeline_file.lambda.unwrap_or(defaults.lambda),
+        holdout: cli.holdout,
+        seed: cli.seed,
+        deterministic: cli.deterministic,
+        clusters: cli.clusters,
+        topic_examples: cli.topic_examples,
+        subtopics: cli.subtopics,
+        solver: cli.solver.or(pipeline_file.solver).unwrap_or(defaults.solver),
+        early_stopping: cli.validation_fraction.map(|fraction| EarlyStopping {
+            fraction,
+            patience: cli.patience,
+            seed: cli.seed,
+        }),
+        stopping: StoppingRule {
+            convergence: cli.convergence,
+            error_floor: cli.error_floor,
+            gradient_tol: cli.gradient_tol,
+            time_budget: cli.time_budget.map(Duration::from_secs_f64),
+        },
+        checkpoint_every: cli.checkpoint_every,
+        warm_start: cli.warm_start.clone(),
+        document_weights: cli.document_weights.clone(),
+        mapped: cli.mmap,
+        max_memory: cli.max_memory,
+        #[cfg(feature = "arrow")]
+        arrow: cli.arrow,
+        topic_words: cli.topic_words.then_some(cli.topic_words_top.map_or(TopicWords::Dense, TopicWords::Top)),
+        keywords: cli.keywords,
+        top_topics: cli.top_topics,
+        normalize_topics: cli.normalize_topics,
+        top_words: TopWords { count: cli.top_words, min_weight: cli.min_word_weight },
+        seed_topics,
+        seed_strength: cli.seed_strength,
+        background: cli.background,
+        auto_stopwords: cli.auto_stopwords.or(pipeline_file.max_df),
+        vocab_filter,
+        vocab_path: cli.vocab.clone(),
+        compression: cli.compress,
+        exclude_empty: cli.exclude_empty,
+        workdir: workdir.clone(),
+        ..defaults
+    };
+    for &k in cli.k_values.as_deref().unwrap_or(&[config.k]) {
+        if config.seed_topics.len() > k {
+            return Err(format!("{} seeded topics given but the model has only {} topics", config.seed_topics.len(), k).into());
+        }
+    }
+
+    match cli.command {
+        Some(Command::Watch(args)) => watch::run(&args.input_dir, args.refit_every, args.grow_vocabulary, args.metrics_addr.as_deref(), &preprocess_config, &config),
+        Some(Command::Score(args)) => {
+            shutdown::install()?;
+            score::run(&args.input, &model_file(args.model), &args.output, args.batch_size, &preprocess_config, &config).map(|_| ())
+        }
+        Some(Command::Serve(args)) => serve::run(&model_file(args.model), &args.addr, &preprocess_config, &config),
+        #[cfg(feature = "tui")]
+        Some(Command::Explore(args)) => explore::run(&model_file(args.model), args.documents, &preprocess_config, &config),
+        Some(Command::Bootstrap(args)) => bootstrap::run(&args.source_dir, &args.sizes, args.count, &args.output, cli.seed, args.index, &args.sampling()),
+        Some(Command::Generate(args)) => generate::run(&args.output, &SyntheticConfig {
+            documents: args.documents,
+            topics: args.topics,
+            vocabulary: args.vocabulary,
+            mean_length: args.mean_length,
+            alpha: args.alpha,
+            beta: args.beta,
+            noise: args.noise,
+            seed: cli.seed,
+            format: args.format,
+        }),
+        Some(Command::Similar(args)) => similar::run(args.doc, args.file.as_deref(), args.top, &model_file(args.model), &preprocess_config, &config),
+        Some(Command::Cell(args)) => {
+            shutdown::install()?;
+            let config = ModelConfig { k: args.k.unwrap_or(config.k), seed: args.cell_seed.unwrap_or(config.seed), ..config.clone() };
+            let outcome = run_cell(&args.input, &preprocess_config, &config, &cli.metrics_format, CpuClock::Process)?;
+            if let Some(keep_model) = &args.keep_model {
+                std::fs::copy(workdir.path(modeling::MODEL_FILE), keep_model)?;
+                console::artifact("model", keep_model);
+            }
+            std::fs::write(&args.output, serde_json::to_string(&outcome)?)?;
+            console::artifact("outcome", &args.output);
+            Ok(())
+        }
+        Some(Command::Dynamic(args)) => {
+            preprocessing::start(&args.input, &preprocess_config)?;
+            dynamic::run(&config, args.slice)
+        }
+        Some(Command::WordClusters(args)) => {
+            if let Some(input) = &args.input {
+                preprocessing::start(input, &preprocess_config)?;
+            }
+            word_clusters::run(&ModelConfig { k: args.k, ..config })
+        }
+        Some(Command::Cooccurrence(args)) => {
+            if let Some(input) = &args.input {
+                preprocessing::start(input, &preprocess_config)?;
+            }
+            let window = (args.window > 0).then_some(args.window);
+            cooccurrence::run(&config, window, args.weighting, args.min_count, &args.output)
+        }
+        Some(Command::Tune(args)) => {
+            shutdown::install()?;
+            let mut measurements = StepMeasurements::new(CpuClock::Process);
+            let trials = tune::run(&args.input, &args.options(), &preprocess_config, &config, &mut measurements)?;
+            write_tune_metrics(&workdir.path(TUNE_METRICS_FILE), &trials, &mut measurements)
+        }
+        Some(Command::CompareModels(args)) => compare::run(&args.model_a, &args.model_b, &args.matrix, &args.output, config.top_words),
+        Some(Command::TopicLineage(args)) => {
+            lineage::record(&NmfModel::load(&args.previous)?, &NmfModel::load(&args.current)?, &args.output, config.top_words).map(|_| ())
+        }
+        Some(Command::DiffCorpus(args)) => corpus_diff::run(&args.before, &args.after, &args.vocabulary, &args.output, args.top),
+        Some(Command::Stability(args)) => stability::run(&args.models, args.similarity, args.top, config.top_words, &args.output),
+        Some(Command::ReportCompare(args)) => {
+            let mapping = args.mapping.as_deref().map_or_else(|| Ok(report_compare::ColumnMapping::default()), report_compare::ColumnMapping::load)?;
+            report_compare::run(&args.rust, &args.other, &mapping, &args.label, &args.output, &args.summary)
+        }
+        Some(Command::FitMatrix(args)) => matrix::run(&args.input, &args.terms, &config),
+        Some(Command::Validate(args)) => validate::run(&model_file(args.model), &workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)), &args.reference_h, args.reference_w.as_deref(), args.reference_terms.as_deref(), args.top, &args.output),
+        None if cli.dry_run => preflight::run(&preprocess_config, &config, cli.jobs.max(cli.threads), cli.k_values.as_deref()),
+        None => {
+            shutdown::install()?;
+            let options = BenchmarkOptions {
+                formats: &cli.metrics_format,
+                jobs: cli.jobs,
+                threads: cli.threads,
+                run_id: cli.run_id.as_deref(),
+                resume: cli.resume.as_deref(),
+                keep_models: cli.keep_models,
+                k_values: cli.k_values.as_deref(),
+                warmup: cli.warmup,
+                outlier_mads: cli.outlier_mads,
+            };
+            run_benchmark(&preprocess_config, &config, &options)
+        }
+    }
+}
+
+/// Metrics of one (iteration, dataset) cell of the benchmark grid.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CellOutcome {
+    preprocessing: StepMetrics,
+    modeling: StepMetrics,
+    /// Absent from the progress of runs recorded before it was kept
+    #[serde(default)]
+    preprocessed: PreprocessingSummary,
+    summary: ModelSummary,
+    substeps: Vec<(String, Duration)>,
+    /// Dominant topic of each document, for formats that store them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    assignments: Vec<(usize, f32)>,
+}
+
+impl CellOutcome {
+    fn record(&self, sample: usize, k: usize, iteration: usize, dataset: usize, sinks: &mut [Box<dyn MetricsSink>]) -> Result<(), Box<dyn std::error::Error>> {
+        let outputs = [StepOutput::Preprocessing(self.preprocessed.clone()), StepOutput::Modeling(self.summary.clone())];
+        let records = [
+            StepRecord { sample, k, iteration, dataset, step: "preprocessing", metrics: &self.preprocessing, output: Some(&outputs[0]), substeps: &[], assignments: &[] },
+            StepRecord { sample, k, iteration, dataset, step: "modeling", metrics: &self.modeling, output: Some(&outputs[1]), substeps: &self.substeps, assignments: &self.assignments },
+        ];
+        for record in &records {
+            for sink in sinks.iter_mut() {
+                sink.record(record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Preprocesses and models one dataset, measuring both steps with CPU time from `clock`.
+fn run_cell(input: &str, preprocess_config: &PreprocessConfig, config: &ModelConfig, formats: &[MetricsFormat], clock: CpuClock) -> Result<CellOutcome, Box<dyn std::error::Error>> {
+    progress!("Starting Data Analysis Pipeline");
+    let mut pipeline = PipelineBuilder::new()
+        .stage(pipeline::Preprocess::new(preprocess_config))
+        .stage(pipeline::Model::new(config))
+        .build();
+    let mut measurements = StepMeasurements::new(clock);
+    let summary = pipeline.run(input.to_string(), &mut measurements)?;
+    let preprocessed = match measurements.take_output("preprocessing") {
+        Some(StepOutput::Preprocessing(preprocessed)) => preprocessed,
+        _ => return Err("The preprocessing stage reported no summary".into()),
+    };
+    let assignments = if formats.iter().any(MetricsFormat::stores_assignments) {
+        let w = modeling::load_topic_distributions(&config.workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)))?;
+        w.rows().into_iter().map(cluster::dominant_topic).collect()
+    } else {
+        Vec::new()
+    };
+    Ok(CellOutcome {
+        preprocessing: measurements.take("preprocessing")?,
+        modeling: measurements.take("modeling")?,
+        preprocessed,
+        summary,
+        substeps: measurements.substeps,
+        assignments,
+    })
+}
+
+/// How the benchmark grid is run and where its results go.
+struct BenchmarkOptions<'a> {
+    formats: &'a [MetricsFormat],
+    jobs: usize,
+    /// Datasets fitted concurrently on threads of this process, instead of `jobs`
+    threads: usize,
+    run_id: Option<&'a str>,
+    resume: Option<&'a str>,
+    /// Keep each cell's model under `<run dir>/models/N{sample}`
+    keep_models: bool,
+    /// Topic counts every dataset is modeled with, instead of only the model's k
+    k_values: Option<&'a [usize]>,
+    /// Unrecorded passes over each sample's datasets before the measured iterations
+    warmup: usize,
+    /// Distance from the median, in MADs, beyond which a step time is an outlier
+    outlier_mads: f64,
+}
+
+fn run_benchmark(preprocess_config: &PreprocessConfig, config: &ModelConfig, options: &BenchmarkOptions) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Starting Data Analysis Pipeline");
+    let (formats, jobs) = (options.formats, options.jobs);
+
+    let (iterations, datasets, samples) = (ITERATIONS, DATASETS, SAMPLES);
+    let k_values = options.k_values.map_or_else(|| vec![config.k], <[usize]>::to_vec);
+
+    let grid = serde_json::json!({
+        "samples": samples,
+        "k_values": k_values,
+        "iterations": iterations,
+        "datasets": datasets,
+        "jobs": jobs,
+        "threads": options.threads,
+        "keep_models": options.keep_models,
+        "warmup": options.warmup,
+        "outlier_mads": options.outlier_mads,
+        "metrics_format": formats.iter().map(|format| format!("{:?}", format)).collect::<Vec<_>>(),
+    });
+    let run_dir = match options.resume {
+        Some(run_id) => resume_run_dir(run_id, preprocess_config, config, &grid)?,
+        None => {
+            let run_dir = create_run_dir(options.run_id)?;
+            write_manifest(&run_dir, preprocess_config, config, grid)?;
+            run_dir
+        }
+    };
+    progress!("Writing results to {}", run_dir.display());
+    console::artifact("run_dir", &run_dir);
+    let mut summary_sink: Box<dyn MetricsSink> = Box::new(SummarySink::new(&run_dir, options.outlier_mads));
+    // Unlike the summary, not replayed on resume: completed cells are already in the file
+    let mut quality_sink: Box<dyn MetricsSink> = Box::new(QualitySink::new(&run_dir)?);
+    let (mut progress, completed) = Progress::open(&run_dir, config.k)?;
+    if !completed.is_empty() {
+        progress!("Resuming after {} completed datasets", completed.len());
+    }
+    for cell in &completed {
+        cell.outcome.record(cell.sample, cell.k(), cell.iteration, cell.dataset, std::slice::from_mut(&mut summary_sink))?;
+    }
+
+    // Metrics files of the sample being run, finished once it is done
+    let mut sinks: Vec<Box<dyn MetricsSink>> = Vec::new();
+    'grid: for sample in samples {
+        let models_dir = run_dir.join("models").join(format!("N{}", sample));
+        if options.keep_models {
+            std::fs::create_dir_all(&models_dir)?;
+        }
+        let keep_model = |k: usize, iteration: usize, dataset: usize| {
+            let name = match options.k_values {
+                Some(_) => format!("k{}_iteration{}_dataset{}.json", k, iteration, dataset),
+                None => format!("iteration{}_dataset{}.json", iteration, dataset),
+            };
+            options.keep_models.then(|| models_dir.join(name))
+        };
+
+        // Initialize new metrics files for each sample
+        for mut sink in sinks.drain(..) {
+            sink.finish()?;
+        }
+        for format in formats {
+            sinks.push(match format {
+                MetricsFormat::Csv => Box::new(CsvSink::new(&run_dir, sample, config.compression)?),
+                MetricsFormat::Jsonl => Box::new(JsonlSink::new(&run_dir, sample, config)?),
+                #[cfg(feature = "arrow")]
+                MetricsFormat::Arrow => {
+                    let mut sink: Box<dyn MetricsSink> = Box::new(ArrowSink::new(&run_dir, sample));
+                    // The file is rewritten as a whole, so it needs the cells run before an interruption
+                    for cell in completed.iter().filter(|cell| cell.sample == sample) {
+                        cell.outcome.record(cell.sample, cell.k(), cell.iteration, cell.dataset, std::slice::from_mut(&mut sink))?;
+                    }
+                    sink
+                }
+                #[cfg(feature = "sqlite")]
+                MetricsFormat::Sqlite => Box::new(sqlite::SqliteSink::open(&run_dir)?),
+            });
+        }
+
+        // Warm up only samples with cells left to run, e.g. not those finished before a resume
+        let pending = k_values.iter().any(|&k| (0..iterations).any(|i| (0..datasets).any(|j| !progress.contains(sample, k, i + 1, j + 1))));
+        let warmup = if pending { options.warmup } else { 0 };
+
+        if jobs > 1 || options.threads > 1 {
+            let input = |dataset: usize| {
+                let input = sample_path(sample, dataset);
+                std::fs::canonicalize(&input).map_or(input, |path| path.to_string_lossy().into_owned())
+            };
+            // Run to completion before the measured cells, so none of these share the machine with them
+            let mut warmup_cells = Vec::new();
+            for pass in 0..warmup {
+                for j in 0..datasets {
+                    warmup_cells.push(CellJob { k: k_values[0], iteration: pass + 1, dataset: j + 1, input: input(j + 1), keep_model: None, seed: config.seed, warmup: true });
+                }
+            }
+            let mut cells = Vec::new();
+            for &k in &k_values {
+                for i in 0..iterations {
+                    for j in 0..datasets {
+                        if progress.contains(sample, k, i + 1, j + 1) {
+                            continue;
+                        }
+                        let seed = cell_seed(config, sample, k, i + 1, j + 1);
+                        cells.push(CellJob { k, iteration: i + 1, dataset: j + 1, input: input(j + 1), keep_model: keep_model(k, i + 1, j + 1), seed, warmup: false });
+                    }
+                }
+            }
+            let mut on_outcome = |cell: &CellJob, outcome: CellOutcome| {
+                if shutdown::aborted() {
+                    // The fit was cut short, so the cell is run again on resume
+                    return Ok(());
+                }
+                if cell.warmup {
+                    progress!("Finished warm-up {}, dataset {} (not recorded)", cell.iteration, cell.dataset);
+                    return Ok(());
+                }
+                progress!("Finished k={}, iteration {}, dataset {}", cell.k, cell.iteration, cell.dataset);
+                outcome.record(sample, cell.k, cell.iteration, cell.dataset, &mut sinks)?;
+                outcome.record(sample, cell.k, cell.iteration, cell.dataset, std::slice::from_mut(&mut summary_sink))?;
+                outcome.record(sample, cell.k, cell.iteration, cell.dataset, std::slice::from_mut(&mut quality_sink))?;
+                progress.record(sample, cell.k, cell.iteration, cell.dataset, &outcome)
+            };
+            for (cells, what) in [(warmup_cells, "warm-up datasets"), (cells, "datasets")] {
+                if cells.is_empty() {
+                    continue;
+                }
+                if options.threads > 1 {
+                    progress!("Running {} {} of N={} on {} threads", cells.len(), what, sample, options.threads);
+                    jobs::run_cells_in_threads(cells, options.threads, config.deterministic, |workdir, cell| {
+                        let preprocess_config = PreprocessConfig { workdir: workdir.clone(), ..preprocess_config.clone() };
+                        let config = ModelConfig { k: cell.k, seed: cell.seed, workdir: workdir.clone(), ..config.clone() };
+                        let outcome = run_cell(&cell.input, &preprocess_config, &config, formats, CpuClock::Thread)?;
+                        if let Some(keep_model) = &cell.keep_model {
+                            std::fs::copy(workdir.path(modeling::MODEL_FILE), keep_model)?;
+                        }
+                        Ok(outcome)
+                    }, &mut on_outcome)?;
+                } else {
+                    progress!("Running {} {} of N={} in {} parallel jobs", cells.len(), what, sample, jobs);
+                    jobs::run_cells(cells, jobs, config.deterministic, &mut on_outcome)?;
+                }
+                if shutdown::requested() {
+                    break 'grid;
+                }
+            }
+            continue;
+        }
+
+        for pass in 0..warmup {
+            for j in 0..datasets {
+                if shutdown::requested() {
+                    break 'grid;
+                }
+                progress!("\nWarm-up {}/{}, dataset {}/{} (not recorded)", pass + 1, warmup, j + 1, datasets);
+                run_cell(&sample_path(sample, j + 1), preprocess_config, &ModelConfig { k: k_values[0], ..config.clone() }, formats, CpuClock::Process)?;
+                if shutdown::aborted() {
+                    break 'grid;
+                }
+            }
+        }
+        for &k in &k_values {
+            for i in 0..iterations {
+                for j in 0..datasets {
+                    let config = &ModelConfig { k, seed: cell_seed(config, sample, k, i + 1, j + 1), ..config.clone() };
+                    if progress.contains(sample, k, i + 1, j + 1) {
+                        continue;
+                    }
+                    if shutdown::requested() {
+                        break 'grid;
+                    }
+                    if options.k_values.is_some() {
+                        progress!("\nTopics: {}", k);
+                    }
+                    progress!("\nIteration {}/{}", i + 1, iterations);
+                    progress!("Dataset {}/{}", j + 1, datasets);
+                    progress!("========================================");
+
+                    let outcome = run_cell(&sample_path(sample, j + 1), preprocess_config, config, formats, CpuClock::Process)?;
+                    if shutdown::aborted() {
+                        break 'grid;
+                    }
+                    if let Some(path) = keep_model(k, i + 1, j + 1) {
+                        std::fs::copy(config.workdir.path(modeling::MODEL_FILE), path)?;
+                    }
+                    outcome.record(sample, k, i + 1, j + 1, &mut sinks)?;
+                    outcome.record(sample, k, i + 1, j + 1, std::slice::from_mut(&mut summary_sink))?;
+                    outcome.record(sample, k, i + 1, j + 1, std::slice::from_mut(&mut quality_sink))?;
+                    progress.record(sample, k, i + 1, j + 1, &outcome)?;
+                }
+            }
+        }
+    }
+    for mut sink in sinks {
+        sink.finish()?;
+    }
+    summary_sink.finish()?;
+
+    if shutdown::requested() {
+        let run_id = run_dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        return Err(format!("Benchmark interrupted after {} datasets; continue it with --resume {}", progress.completed.len(), run_id).into());
+    }
+    std::fs::remove_file(run_dir.join(PROGRESS_FILE))?;
+    Ok(())
+}
+
+/// Seed of one benchmark cell: in deterministic mode derived from the master seed and
+/// the cell's place in the grid, so it doesn't depend on which job or thread runs it.
+fn cell_seed(config: &ModelConfig, sample: usize, k: usize, iteration: usize, dataset: usize) -> u64 {
+    if !config.deterministic {
+        return config.seed;
+    }
+    [sample, k, iteration, dataset].into_iter().fold(config.seed, |seed, stream| modeling::child_seed(seed, stream as u64))
+}
+
+/// Location of a bootstrap dataset: a sample directory, or in its absence a
+/// same-named index file (`bootstrap --index`) or CSV/JSON Lines corpus file.
+fn sample_path(sample: usize, dataset: usize) -> String {
+    let dir = format!("../bootstrap_samples/N_{}/sample_{}", sample, dataset);
+    if Path::new(&dir).exists() {
+        return dir;
+    }
+    ["list", "csv", "jsonl", "ndjson"]
+        .iter()
+        .map(|ext| format!("{}.{}", dir, ext))
+        .find(|file| Path::new(file).exists())
+        .unwrap_or(dir)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StepMetrics {
+    elapsed: Duration,
+    memory: MemoryUsage,
+    /// CPU time as a percentage of the elapsed time on all logical cores
+    cpu_usage: f64,
+    /// CPU time summed over all threads
+    #[serde(default)]
+    cpu_seconds: f64,
+    io: IoCounters,
+    /// Hardware performance counters, when built with the `perf` feature on Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hardware: Option<HardwareCounts>,
+    #[serde(default)]
+    thermal: StepThermal,
+}
+
+/// Disk and other I/O performed by the process.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct IoCounters {
+    read_bytes: u64,
+    write_bytes: u64,
+    read_ops: u64,
+    write_ops: u64,
+}
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Resident (RSS) and virtual memory of the process around a step, in MiB.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct MemoryUsage {
+    rss_before_mib: f64,
+    rss_after_mib: f64,
+    rss_peak_mib: f64,
+    virtual_before_mib: f64,
+    virtual_after_mib: f64,
+    virtual_peak_mib: f64,
+}
+
+impl MemoryUsage {
+    /// Memory the step left allocated (negative if it freed memory allocated earlier).
+    fn rss_delta_mib(&self) -> f64 {
+        self.rss_after_mib - self.rss_before_mib
+    }
+
+    fn virtual_delta_mib(&self) -> f64 {
+        self.virtual_after_mib - self.virtual_before_mib
+    }
+}
+
+/// How often `MemorySampler` polls the process's memory for its peak.
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Polls the process's memory on a background thread while a step runs, as the peak of
+/// a step is otherwise lost once it frees its allocations.
+struct MemorySampler {
+    before: (u64, u64),
+    stop: std::sync::mpsc::Sender<()>,
+    sampler: std::thread::JoinHandle<(u64, u64)>,
+}
+
+/// Resident and virtual size of the process in bytes.
+fn process_memory(sys: &mut System, pid: Pid) -> (u64, u64) {
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    sys.process(pid).map_or((0, 0), |p| (p.memory(), p.virtual_memory()))
+}
+
+impl MemorySampler {
+    fn start() -> MemorySampler {
+        let pid = Pid::from(std::process::id() as usize);
+        let before = process_memory(&mut System::new(), pid);
+        let (stop, stopped) = std::sync::mpsc::channel();
+        let sampler = std::thread::spawn(move || {
+            let mut sys = System::new();
+            let mut peak = before;
+            loop {
+                let (rss, virtual_size) = process_memory(&mut sys, pid);
+                peak = (peak.0.max(rss), peak.1.max(virtual_size));
+                if stopped.recv_timeout(MEMORY_SAMPLE_INTERVAL) != Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                    return peak;
+                }
+            }
+        });
+        MemorySampler { before, stop, sampler }
+    }
+
+    fn stop(self) -> MemoryUsage {
+        let _ = self.stop.send(());
+        let peak = self.sampler.join().unwrap_or(self.before);
+        let after = process_memory(&mut System::new(), Pid::from(std::process::id() as usize));
+        let mib = |bytes: u64| bytes as f64 / MIB;
+        MemoryUsage {
+            rss_before_mib: mib(self.before.0),
+            rss_after_mib: mib(after.0),
+            rss_peak_mib: mib(peak.0.max(after.0)),
+            virtual_before_mib: mib(self.before.1),
+            virtual_after_mib: mib(after.1),
+            virtual_peak_mib: mib(peak.1.max(after.1)),
+        }
+    }
+}
+
+/// Hardware events counted by the CPU while a step ran.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct HardwareCounts {
+    instructions: u64,
+    cache_misses: u64,
+    branch_misses: u64,
+}
+
+impl IoCounters {
+    fn since(self, start: IoCounters) -> IoCounters {
+        IoCounters {
+            read_bytes: self.read_bytes - start.read_bytes,
+            write_bytes: self.write_bytes - start.write_bytes,
+            read_ops: self.read_ops - start.read_ops,
+            write_ops: self.write_ops - start.write_ops,
+        }
+    }
+}
+
+/// Whose CPU time a step is charged with: the whole process, or only the calling
+/// thread when datasets share the process (`--threads`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CpuClock {
+    Process,
+    Thread,
+}
+
+/// Metrics of the stages of a pipeline, each measured with `measure_step`, and the
+/// outputs they reported.
+struct StepMeasurements {
+    clock: CpuClock,
+    steps: Vec<(String, StepMetrics)>,
+    /// Sub-stages of all stages, in the order they ran
+    substeps: Vec<(String, Duration)>,
+    outputs: Vec<(String, StepOutput)>,
+}
+
+impl StepMeasurements {
+    fn new(clock: CpuClock) -> StepMeasurements {
+        StepMeasurements { clock, steps: Vec::new(), substeps: Vec::new(), outputs: Vec::new() }
+    }
+
+    fn take(&mut self, name: &str) -> Result<StepMetrics, Box<dyn std::error::Error>> {
+        let position = self.steps.iter().position(|(step, _)| step == name).ok_or_else(|| format!("No {} stage was measured", name))?;
+        Ok(self.steps.remove(position).1)
+    }
+
+    fn take_output(&mut self, name: &str) -> Option<StepOutput> {
+        let position = self.outputs.iter().position(|(step, _)| step == name)?;
+        Some(self.outputs.remove(position).1)
+    }
+}
+
+impl Instrument for StepMeasurements {
+    fn measure(&mut self, name: &str, stage: &mut dyn FnMut() -> Result<(), Box<dyn std::error::Error>>) -> Result<(), Box<dyn std::error::Error>> {
+        let ((), metrics) = measure_step(name, self.clock, stage)?;
+        self.steps.push((name.to_string(), metrics));
+        Ok(())
+    }
+
+    fn substeps(&mut self, _name: &str, timer: &StepTimer) {
+        self.substeps.extend_from_slice(timer.stages());
+    }
+
+    fn output(&mut self, name: &str, output: StepOutput) {
+        self.outputs.push((name.to_string(), output));
+    }
+}
+
+/// Runs `step`, measuring its time, memory, CPU time on `clock`, disk I/O and, with the
+/// `perf` feature, hardware counters. With `CpuClock::Thread` the memory and I/O are
+/// the whole process's and no hardware counters are read, as other threads' steps
+/// would be counted too.
+fn measure_step<T, F>(name: &str, clock: CpuClock, step: F) -> Result<(T, StepMetrics), Box<dyn std::error::Error>>
 where
-    F: FnOnce() -> Result<Vec<String>, Box<dyn std::error::Error>>,
+    F: FnOnce() -> Result<T, Box<dyn std::error::Error>>,
 {
-    println!("Starting {} pipeline...", name);
+    progress!("Starting {} pipeline...", name);
 
+    let thermal_before = environment::thermal();
     let timer = Instant::now();
-    let mut sys = System::new_all();
-    let pid = Pid::from(std::process::id() as usize);
-    sys.refresh_processes(ProcessesToUpdate::All, true);
-    let memory_before = sys.process(pid).map(|p| p.memory()).unwrap_or(0);
-    let process_handle = unsafe { winapi::um::processthreadsapi::GetCurrentProcess() };
-    let start_cpu_time = get_process_cpu_time(process_handle)?;
+    let memory_sampler = MemorySampler::start();
+    let cpu_time = || match clock {
+        CpuClock::Process => get_process_cpu_time(),
+        CpuClock::Thread => get_thread_cpu_time(),
+    };
+    let start_cpu_time = cpu_time()?;
+    let start_io = get_process_io_counters()?;
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    let perf_counters = match clock {
+        CpuClock::Thread => None,
+        CpuClock::Process => match perf::PerfCounters::start() {
+            Ok(counters) => Some(counters),
+            Err(e) => {
+                progress!("  Hardware counters unavailable: {}", e);
+                None
+            }
+        },
+    };
 
-    let result = step();
+    let result = step()?;
+
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    let hardware = perf_counters.map(perf::PerfCounters::stop).transpose()?;
+    #[cfg(not(all(feature = "perf", target_os = "linux")))]
+    let hardware: Option<HardwareCounts> = None;
 
     let elapsed = timer.elapsed();
-    sys.refresh_processes(ProcessesToUpdate::All, true);
-    let memory_after = sys.process(pid).map(|p| p.memory()).unwrap_or(0);
-
-    let memory_usage_b = memory_after;
-    let memory_usage_mb = memory_usage_b as f64 / (1024.0*1024.0);
-
-    let end_cpu_time = get_process_cpu_time(process_handle)?;
-    let cpu_usage = calculate_cpu_usage(start_cpu_time, end_cpu_time, elapsed);
-
-    println!("{} Metrics:", name);
-    println!("  Time: {:.2?}", elapsed);
-    println!("  Memory: {:.2} MB", memory_usage_mb);
-    println!("  CPU Usage: {:.1}%", cpu_usage);
-    println!();
-
-    // Handle the result based on the step
-    let topics = match result {
-        Ok(topics) if name == "modeling" => topics.join(" | "), // Join topics for modeling
-        Ok(_) => "N/A".to_string(), // Use "N/A" for preprocessing
-        Err(e) => return Err(e),    // Propagate errors
-    };
+    let memory = memory_sampler.stop();
+
+    let end_cpu_time = cpu_time()?;
+    let cpu_seconds = end_cpu_time.saturating_sub(start_cpu_time).as_secs_f64();
+    let cpu_usage = calculate_cpu_usage(cpu_seconds, elapsed);
+    let io = get_process_io_counters()?.since(start_io);
+    let thermal = StepThermal { before: thermal_before, after: environment::thermal() };
 
-    // Write metrics to the CSV file
-    writer.serialize((
-        iteration,
-        dataset,
-        name,
-        elapsed.as_secs_f64(),
-        memory_usage_mb,
+    progress!("{} Metrics:", name);
+    progress!("  Time: {:.2?}", elapsed);
+    progress!("  Memory: {:.2} MiB RSS ({:+.2} MiB, peak {:.2} MiB), {:.2} MiB virtual ({:+.2} MiB, peak {:.2} MiB)",
+        memory.rss_after_mib, memory.rss_delta_mib(), memory.rss_peak_mib,
+        memory.virtual_after_mib, memory.virtual_delta_mib(), memory.virtual_peak_mib);
+    progress!("  CPU Usage: {:.1}% of {} cores ({:.2} CPU-seconds)", cpu_usage, logical_cores(), cpu_seconds);
+    progress!("  Disk I/O: {:.2} MiB read in {} ops, {:.2} MiB written in {} ops",
+        io.read_bytes as f64 / MIB, io.read_ops,
+        io.write_bytes as f64 / MIB, io.write_ops);
+    if let Some(hardware) = &hardware {
+        progress!("  Hardware: {} instructions, {} cache misses, {} branch mispredicts",
+            hardware.instructions, hardware.cache_misses, hardware.branch_misses);
+    }
+    if let (Some(before), Some(after)) = (thermal.before.mean_mhz, thermal.after.mean_mhz) {
+        progress!("  CPU Clock: {:.0} MHz before, {:.0} MHz after", before, after);
+    }
+    if let (Some(before), Some(after)) = (thermal.before.max_celsius, thermal.after.max_celsius) {
+        progress!("  CPU Temperature: {:.0}°C before, {:.0}°C after", before, after);
+    }
+    progress!();
+
+    let metrics = StepMetrics {
+        elapsed,
+        memory,
         cpu_usage,
+        cpu_seconds,
+        io,
+        hardware,
+        thermal,
+    };
+    Ok((result, metrics))
+}
+
+/// The `METRICS_HEADER` fields of a step.
+fn metrics_row(iteration: usize, dataset: usize, k: usize, name: &str, metrics: &StepMetrics, summary: Option<&ModelSummary>) -> Vec<String> {
+    let topics = summary.map_or("N/A".to_string(), |s| s.topics.iter().map(ToString::to_string).collect::<Vec<_>>().join(" | "));
+    let timings = summary.map(|s| &s.timings);
+    let fit = summary.and_then(|s| s.fit);
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    vec![
+        iteration.to_string(),
+        dataset.to_string(),
+        k.to_string(),
+        name.to_string(),
+        metrics.elapsed.as_secs_f64().to_string(),
+        metrics.memory.rss_before_mib.to_string(),
+        metrics.memory.rss_after_mib.to_string(),
+        metrics.memory.rss_delta_mib().to_string(),
+        metrics.memory.rss_peak_mib.to_string(),
+        metrics.memory.virtual_before_mib.to_string(),
+        metrics.memory.virtual_after_mib.to_string(),
+        metrics.memory.virtual_delta_mib().to_string(),
+        metrics.memory.virtual_peak_mib.to_string(),
+        metrics.cpu_usage.to_string(),
+        metrics.cpu_seconds.to_string(),
         topics,
-    ))?;
-    writer.flush()?;
+        optional(summary.and_then(|s| s.heldout_error).map(|e| e.to_string())),
+        optional(timings.map(|t| t.iterations.to_string())),
+        optional(timings.map(|t| t.per_iteration().as_secs_f64().to_string())),
+        optional(timings.map(|t| t.h_update.as_secs_f64().to_string())),
+        optional(timings.map(|t| t.w_update.as_secs_f64().to_string())),
+        optional(timings.map(|t| t.error.as_secs_f64().to_string())),
+        optional(timings.and_then(|t| t.errors.last()).map(|e| e.to_string())),
+        optional(fit.map(|f| f.frobenius_error.to_string())),
+        optional(fit.map(|f| f.relative_error.to_string())),
+        optional(fit.map(|f| f.explained_variance.to_string())),
+        (metrics.io.read_bytes as f64 / MIB).to_string(),
+        (metrics.io.write_bytes as f64 / MIB).to_string(),
+        metrics.io.read_ops.to_string(),
+        metrics.io.write_ops.to_string(),
+        optional(metrics.hardware.map(|h| h.instructions.to_string())),
+        optional(metrics.hardware.map(|h| h.cache_misses.to_string())),
+        optional(metrics.hardware.map(|h| h.branch_misses.to_string())),
+        optional(metrics.thermal.before.mean_mhz.map(|mhz| mhz.to_string())),
+        optional(metrics.thermal.after.mean_mhz.map(|mhz| mhz.to_string())),
+        optional(metrics.thermal.before.max_celsius.map(|celsius| celsius.to_string())),
+        optional(metrics.thermal.after.max_celsius.map(|celsius| celsius.to_string())),
+    ]
+}
 
+/// Writes the measured cost of `tune`'s preprocessing and trials as `METRICS_HEADER`
+/// rows, numbering the trials as iterations.
+fn write_tune_metrics(path: &str, trials: &[tune::Trial], measurements: &mut StepMeasurements) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(METRICS_HEADER)?;
+    writer.write_record(metrics_row(0, 0, 0, "preprocessing", &measurements.take("preprocessing")?, None))?;
+    for (idx, trial) in trials.iter().enumerate() {
+        let name = format!("trial {}", idx);
+        writer.write_record(metrics_row(idx, 0, trial.candidate.k, &name, &measurements.take(&name)?, None))?;
+    }
+    writer.flush()?;
+    progress!("Cost of each configuration written to {}", path);
+    console::artifact("tune_metrics", path);
     Ok(())
 }
 
+/// Rows of a step: its own, then one per sub-stage with only its time filled in.
+fn metrics_rows(record: &StepRecord) -> Vec<Vec<String>> {
+    let mut rows = vec![metrics_row(record.iteration, record.dataset, record.k, record.step, record.metrics, record.model())];
+    for (name, elapsed) in record.substeps {
+        let mut row = vec![
+            record.iteration.to_string(),
+            record.dataset.to_string(),
+            record.k.to_string(),
+            format!("{}/{}", record.step, name),
+            elapsed.as_secs_f64().to_string(),
+        ];
+        row.resize(METRICS_HEADER.len(), String::new());
+        rows.push(row);
+    }
+    rows
+}
+
 // Windows-specific CPU time functions
-fn get_process_cpu_time(handle: winapi::um::winnt::HANDLE) -> Result<u64, Box<dyn std::error::Error>> {
+#[cfg(windows)]
+fn get_process_cpu_time() -> Result<Duration, Box<dyn std::error::Error>> {
     unsafe {
         let mut creation_time: FILETIME = mem::zeroed();
         let mut exit_time: FILETIME = mem::zeroed();
@@ -127,7 +1551,7 @@ fn get_process_cpu_time(handle: winapi::um::winnt::HANDLE) -> Result<u64, Box<dy
         let mut user_time: FILETIME = mem::zeroed();
         
         if GetProcessTimes(
-            handle,
+            GetCurrentProcess(),
             &mut creation_time,
             &mut exit_time,
             &mut kernel_time,
@@ -137,19 +1561,100 @@ fn get_process_cpu_time(handle: winapi::um::winnt::HANDLE) -> Result<u64, Box<dy
             return Err("Failed to get process times".into());
         }
 
-        let total_time = file_time_to_u64(kernel_time) + file_time_to_u64(user_time);
-        Ok(total_time)
+        Ok(file_time_to_duration(kernel_time) + file_time_to_duration(user_time))
     }
 }
 
-fn file_time_to_u64(ft: FILETIME) -> u64 {
-    ((ft.dwHighDateTime as u64) << 32) | (ft.dwLowDateTime as u64)
+#[cfg(windows)]
+fn get_thread_cpu_time() -> Result<Duration, Box<dyn std::error::Error>> {
+    unsafe {
+        let mut creation_time: FILETIME = mem::zeroed();
+        let mut exit_time: FILETIME = mem::zeroed();
+        let mut kernel_time: FILETIME = mem::zeroed();
+        let mut user_time: FILETIME = mem::zeroed();
+
+        if GetThreadTimes(GetCurrentThread(), &mut creation_time, &mut exit_time, &mut kernel_time, &mut user_time) == 0 {
+            return Err("Failed to get thread times".into());
+        }
+        Ok(file_time_to_duration(kernel_time) + file_time_to_duration(user_time))
+    }
 }
 
-fn calculate_cpu_usage(start: u64, end: u64, elapsed: std::time::Duration) -> f64 {
-    let cpu_time_diff = end - start;
-    let elapsed_ns = elapsed.as_nanos() as f64;
-    let cpu_time_ns = cpu_time_diff as f64 * 100.0; // Convert 100ns units to ns
-    
-    (cpu_time_ns / elapsed_ns * 100.0).min(100.0)
+#[cfg(windows)]
+fn get_process_io_counters() -> Result<IoCounters, Box<dyn std::error::Error>> {
+    unsafe {
+        let mut counters: IO_COUNTERS = mem::zeroed();
+        if GetProcessIoCounters(GetCurrentProcess(), &mut counters) == 0 {
+            return Err("Failed to get process I/O counters".into());
+        }
+
+        Ok(IoCounters {
+            read_bytes: counters.ReadTransferCount,
+            write_bytes: counters.WriteTransferCount,
+            read_ops: counters.ReadOperationCount,
+            write_ops: counters.WriteOperationCount,
+        })
+    }
+}
+
+/// A FILETIME span, counted in 100ns units.
+#[cfg(windows)]
+fn file_time_to_duration(ft: FILETIME) -> Duration {
+    let ticks = ((ft.dwHighDateTime as u64) << 32) | (ft.dwLowDateTime as u64);
+    Duration::from_nanos(ticks * 100)
+}
+
+// Linux counterparts, from getrusage and /proc
+#[cfg(target_os = "linux")]
+fn get_process_cpu_time() -> Result<Duration, Box<dyn std::error::Error>> {
+    rusage_cpu_time(libc::RUSAGE_SELF)
+}
+
+#[cfg(target_os = "linux")]
+fn get_thread_cpu_time() -> Result<Duration, Box<dyn std::error::Error>> {
+    rusage_cpu_time(libc::RUSAGE_THREAD)
+}
+
+/// User plus system CPU time of the process or thread `who`.
+#[cfg(target_os = "linux")]
+fn rusage_cpu_time(who: libc::c_int) -> Result<Duration, Box<dyn std::error::Error>> {
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    if unsafe { libc::getrusage(who, &mut usage) } != 0 {
+        return Err(format!("Failed to get CPU times: {}", std::io::Error::last_os_error()).into());
+    }
+    let duration = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    Ok(duration(usage.ru_utime) + duration(usage.ru_stime))
+}
+
+/// Bytes and calls of every read and write, like the Windows counters, so cached
+/// and pipe I/O count as well as the disk's.
+#[cfg(target_os = "linux")]
+fn get_process_io_counters() -> Result<IoCounters, Box<dyn std::error::Error>> {
+    let io = std::fs::read_to_string("/proc/self/io").map_err(|e| format!("Failed to get process I/O counters: {}", e))?;
+    let counter = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':')?.trim().parse().ok())
+            .ok_or_else(|| format!("/proc/self/io has no {} counter", name))
+    };
+    Ok(IoCounters {
+        read_bytes: counter("rchar")?,
+        write_bytes: counter("wchar")?,
+        read_ops: counter("syscr")?,
+        write_ops: counter("syscw")?,
+    })
+}
+
+/// Logical cores available to the process, which CPU usage is normalized against.
+fn logical_cores() -> usize {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get())
+}
+
+/// CPU time as a percentage of what all logical cores could have done in `elapsed`, so a
+/// parallel step saturating every core reports 100%.
+fn calculate_cpu_usage(cpu_seconds: f64, elapsed: std::time::Duration) -> f64 {
+    let capacity = elapsed.as_secs_f64() * logical_cores() as f64;
+    if capacity == 0.0 {
+        return 0.0;
+    }
+    (cpu_seconds / capacity * 100.0).min(100.0)
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use memmap2::Mmap;
+use ndarray::{Array2, ArrayViewMut1};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+
+pub const MAPPED_MATRIX_FILE: &str = "document_term_matrix.f32";
+/// Rows of a mapped matrix read into memory at a time
+pub const BLOCK_ROWS: usize = 1024;
+
+const VALUE_BYTES: usize = std::mem::size_of::<f32>();
+
+/// A dense row-major f32 matrix kept in a memory-mapped file, so it can be larger than
+/// RAM. Rows are read a block at a time.
+pub struct MappedMatrix {
+    mmap: Mmap,
+    rows: usize,
+    cols: usize,
+}
+
+impl MappedMatrix {
+    /// Writes the matrix to `path` row by row, `fill` setting the values of each
+    /// (zeroed) row, and maps the file.
+    pub fn create(path: &str, rows: usize, cols: usize, mut fill: impl FnMut(usize, ArrayViewMut1<f32>)) -> Result<MappedMatrix> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut row = ndarray::Array1::<f32>::zeros(cols);
+        for index in 0..rows {
+            row.fill(0.0);
+            fill(index, row.view_mut());
+            for value in &row {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+        MappedMatrix::open(path, rows, cols)
+    }
+
+    /// Maps a file written by `create` with the given shape.
+    pub fn open(path: &str, rows: usize, cols: usize) -> Result<MappedMatrix> {
+        let file = File::open(path)?;
+        // Safety: the file is private to this run's workdir and not modified while mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() != rows * cols * VALUE_BYTES {
+            anyhow::bail!("{} holds {} bytes, expected a {}x{} f32 matrix", path, mmap.len(), rows, cols);
+        }
+        Ok(MappedMatrix { mmap, rows, cols })
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    /// Copies `rows` of the matrix into memory.
+    pub fn block(&self, rows: Range<usize>) -> Array2<f32> {
+        let bytes = &self.mmap[rows.start * self.cols * VALUE_BYTES..rows.end * self.cols * VALUE_BYTES];
+        let values = bytes
+            .chunks_exact(VALUE_BYTES)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        Array2::from_shape_vec((rows.len(), self.cols), values).expect("block shape matches its values")
+    }
+
+    /// Row ranges of at most `BLOCK_ROWS` rows covering the matrix.
+    pub fn blocks(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        (0..self.rows).step_by(BLOCK_ROWS).map(|start| start..(start + BLOCK_ROWS).min(self.rows))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::console;
+use crate::modeling::{self, ModelConfig, MODEL_FILE, TOPIC_WORDS_FILE};
+use crate::timer::StepTimer;
+use crate::validate::read_npy;
+use crate::vocabulary::Vocabulary;
+use ndarray::{Array1, Array2};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Reads a MatrixMarket file, either `coordinate` (real, integer or pattern entries)
+/// or dense `array`, with the `general` symmetry a document-term matrix has.
+fn read_mtx(path: &str) -> Result<Array2<f32>, Box<dyn Error>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let banner = lines.next().ok_or_else(|| format!("{} is empty", path))??.to_lowercase();
+    let fields: Vec<&str> = banner.split_whitespace().collect();
+    let [_, "matrix", format, field, symmetry] = fields[..] else {
+        return Err(format!("{} is not a MatrixMarket matrix: {}", path, banner).into());
+    };
+    if symmetry != "general" || field == "complex" {
+        return Err(format!("{}: unsupported MatrixMarket {} {} matrix", path, field, symmetry).into());
+    }
+
+    let mut body = lines.filter(|line| !line.as_ref().is_ok_and(|line| line.starts_with('%') || line.trim().is_empty()));
+    let size = body.next().ok_or_else(|| format!("{} has no size line", path))??;
+    let size: Vec<usize> = size.split_whitespace().map(str::parse).collect::<Result<_, _>>()?;
+    let (rows, cols) = match size[..] {
+        [rows, cols, ..] => (rows, cols),
+        _ => return Err(format!("{}: malformed size line", path).into()),
+    };
+
+    let mut matrix = Array2::<f32>::zeros((rows, cols));
+    if format == "array" {
+        // Dense values in column-major order
+        for (i, line) in body.enumerate() {
+            let value: f32 = line?.trim().parse()?;
+            *matrix.get_mut((i % rows, i / rows)).ok_or_else(|| format!("{}: more than {} values", path, rows * cols))? = value;
+        }
+        return Ok(matrix);
+    }
+    for line in body {
+        let line = line?;
+        let mut parts = line.split_whitespace();
+        let (Some(row), Some(col)) = (parts.next(), parts.next()) else {
+            return Err(format!("{}: malformed entry {:?}", path, line).into());
+        };
+        let (row, col): (usize, usize) = (row.parse()?, col.parse()?);
+        let value = match parts.next() {
+            Some(value) => value.parse()?,
+            None => 1.0,
+        };
+        let entry = matrix.get_mut((row.wrapping_sub(1), col.wrapping_sub(1)))
+            .ok_or_else(|| format!("{}: entry ({}, {}) outside the {}x{} matrix", path, row, col, rows, cols))?;
+        *entry += value;
+    }
+    Ok(matrix)
+}
+
+/// Values of a 1-D array in an .npz archive, whatever its numeric dtype.
+fn npz_array(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    archive.by_name(name).map_err(|e| format!("{}: {}", name, e))?.read_to_end(&mut bytes)?;
+    let npy = || npyz::NpyFile::new(&bytes[..]);
+    if let Ok(values) = npy()?.into_vec::<f64>() {
+        return Ok(values);
+    }
+    if let Ok(values) = npy()?.into_vec::<f32>() {
+        return Ok(values.into_iter().map(f64::from).collect());
+    }
+    if let Ok(values) = npy()?.into_vec::<i64>() {
+        return Ok(values.into_iter().map(|x| x as f64).collect());
+    }
+    Ok(npy()?.into_vec::<i32>()?.into_iter().map(f64::from).collect())
+}
+
+/// Reads a sparse matrix written by `scipy.sparse.save_npz` in CSR or CSC format.
+fn read_npz(path: &str) -> Result<Array2<f32>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut format = Vec::new();
+    archive.by_name("format.npy")?.read_to_end(&mut format)?;
+    let csc = format.windows(3).any(|w| w == b"csc");
+    if !csc && !format.windows(3).any(|w| w == b"csr") {
+        return Err(format!("{}: only CSR and CSC matrices are supported", path).into());
+    }
+
+    let shape = npz_array(&mut archive, "shape.npy")?;
+    let (rows, cols) = match shape[..] {
+        [rows, cols] => (rows as usize, cols as usize),
+        _ => return Err(format!("{} is not a 2-D matrix", path).into()),
+    };
+    let data = npz_array(&mut archive, "data.npy")?;
+    let indices = npz_array(&mut archive, "indices.npy")?;
+    let indptr = npz_array(&mut archive, "indptr.npy")?;
+
+    // indptr[i]..indptr[i + 1] are the stored entries of row (CSR) or column (CSC) i
+    let outer_len = if csc { cols } else { rows };
+    if indptr.len() != outer_len + 1 {
+        return Err(format!("{}: indptr has {} entries, expected {} for a {}x{} matrix", path, indptr.len(), outer_len + 1, rows, cols).into());
+    }
+    if indptr[0] < 0.0 || indptr.windows(2).any(|bounds| bounds[0] > bounds[1]) {
+        return Err(format!("{}: indptr must start at zero or above and never decrease", path).into());
+    }
+    let stored = indptr[outer_len] as usize;
+    if stored > indices.len() || stored > data.len() {
+        return Err(format!("{}: indptr counts {} entries but there are {} indices and {} values", path, stored, indices.len(), data.len()).into());
+    }
+
+    let mut matrix = Array2::<f32>::zeros((rows, cols));
+    for (outer, bounds) in indptr.windows(2).enumerate() {
+        for entry in bounds[0] as usize..bounds[1] as usize {
+            let (Some(&inner), Some(&value)) = (indices.get(entry), data.get(entry)) else {
+                return Err(format!("{}: entry {} is past the stored entries", path, entry).into());
+            };
+            let (row, col) = if csc { (inner as usize, outer) } else { (outer, inner as usize) };
+            *matrix.get_mut((row, col)).ok_or_else(|| format!("{}: entry ({}, {}) out of bounds", path, row, col))? += value as f32;
+        }
+    }
+    Ok(matrix)
+}
+
+/// Loads a document-term matrix from a MatrixMarket (.mtx), scipy sparse (.npz) or
+/// dense NumPy (.npy) file.
+pub fn load_matrix(path: &str) -> Result<Array2<f32>, Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("mtx") => read_mtx(path),
+        Some("npz") => read_npz(path),
+        Some("npy") => read_npy(path),
+        _ => Err(format!("{}: expected a .mtx, .npz or .npy matrix", path).into()),
+    }
+}
+
+/// Fits a model directly on a precomputed document-term matrix whose columns are the
+/// terms listed in `terms`, skipping tokenization and TF-IDF. The matrix is factorized
+/// as given, so the saved model's IDF weights are all one.
+pub fn run(input: &str, terms: &str, config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let mut timer = StepTimer::new();
+    let v = timer.time("load_matrix", || load_matrix(input))?;
+    let vocab = Vocabulary::load(terms)?;
+    if v.ncols() != vocab.len() {
+        return Err(format!("{} has {} columns but {} lists {} terms", input, v.ncols(), terms, vocab.len()).into());
+    }
+    if v.iter().any(|&x| x < 0.0) {
+        return Err(format!("{} has negative entries, which NMF cannot fit", input).into());
+    }
+    progress!("Loaded a {}x{} matrix with {} nonzero entries", v.nrows(), v.ncols(), v.iter().filter(|&&x| x != 0.0).count());
+
+    let idf = Array1::ones(vocab.len());
+    let fit = modeling::fit_matrix(&v, vocab, idf, None, config, &mut timer)?;
+
+    let workdir = &config.workdir;
+    modeling::save_document_topics(&fit.w, config)?;
+    fit.model.save(&workdir.path(MODEL_FILE))?;
+    if let Some(layout) = config.topic_words {
+        modeling::save_topic_word_matrix(&fit.model.h, &fit.model.vocab, layout, &workdir.path(&config.compression.path(TOPIC_WORDS_FILE)))?;
+    }
+
+    let topics = fit.model.topics(config.top_words);
+    for topic in &topics {
+        progress!("{}", topic);
+    }
+    console::artifact("model", workdir.path(MODEL_FILE));
+    console::topics(&topics);
+    let timings = &fit.timings;
+    progress!("NMF: {} iterations in {:.2?} ({:.2?} per iteration)", timings.iterations, timings.total(), timings.per_iteration());
+    if let Some(fit) = &fit.model.fit {
+        progress!("Reconstruction error {:.4} (relative {:.4}), {:.1}% of variance explained",
+            fit.frobenius_error, fit.relative_error, fit.explained_variance * 100.0);
+        console::metric("reconstruction_error", fit.frobenius_error);
+        console::metric("relative_error", fit.relative_error);
+        console::metric("explained_variance", fit.explained_variance);
+    }
+    for (step, elapsed) in timer.stages() {
+        progress!("  {}: {:.2?}", step, elapsed);
+    }
+    Ok(())
+}
@@ -1,14 +1,47 @@
+use crate::cluster;
+use crate::compression::{self, Compression};
+use crate::console;
+#[cfg(feature = "arrow")]
+use crate::feather;
+use crate::hierarchy::{self, TOPIC_TREE_FILE};
+use crate::lineage::{self, LINEAGE_FILE};
+use crate::mapped::{MappedMatrix, MAPPED_MATRIX_FILE};
+use crate::normalize::Normalizer;
+use crate::preprocessing::{self, PreprocessConfig, Preprocessor};
+use crate::quality::{self, TopicQuality, QUALITY_TOP_WORDS, REDUNDANT_OVERLAP};
+use crate::shutdown;
+use crate::timer::StepTimer;
+use crate::tokenizer::RegexTokenizer;
+use crate::workdir::{Workdir, CLUSTERS_DIR, DISTRIBUTIONS_FILE, FILES_FILE, TOKENS_FILE, TOPIC_EXAMPLES_DIR};
+use crate::vocabulary::{self, Vocabulary, VocabularyFilter};
+use crate::weights::DocumentWeights;
 use anyhow::Result;
+use clap::ValueEnum;
 use csv::ReaderBuilder;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json;
-use ndarray::{Array1, Array2, Axis};
+use ndarray::{s, Array1, Array2, ArrayViewMut1, Axis, Zip};
 use ndarray_rand::RandomExt;
 use std::error::Error;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rand_distr::Uniform;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const MODEL_FILE: &str = "nmf_model.json";
+pub const AUTO_STOPWORDS_FILE: &str = "auto_stopwords.txt";
+pub const PRUNED_TERMS_FILE: &str = "pruned_terms.txt";
+pub const SKIPPED_DOCUMENTS_FILE: &str = "skipped_documents.csv";
+pub const CHECKPOINT_FILE: &str = "nmf_checkpoint.json";
+pub const TOPIC_WORDS_FILE: &str = "topic_word_matrix.csv";
+pub const KEYWORDS_FILE: &str = "keywords.csv";
+pub const TOP_TOPICS_FILE: &str = "document_top_topics.csv";
 
 #[derive(Debug, Deserialize)]
 struct Record {
@@ -16,8 +49,258 @@ struct Record {
     tokens: String,
 }
 
-fn load_documents(filepath: &str) -> Result<Vec<Vec<String>>> {
-    let file = File::open(filepath)?;
+#[derive(Clone)]
+pub struct ModelConfig {
+    pub min_df: usize,
+    /// IDF formula the TF-IDF weights use
+    pub idf: Idf,
+    pub k: usize,
+    pub max_iter: usize,
+    pub tol: f32,
+    /// L1 regularization of W and H added to the updates
+    pub lambda: f32,
+    /// Fraction of documents held out of the fit to measure out-of-sample error
+    pub holdout: f32,
+    pub seed: u64,
+    /// Draw every random initialization from `seed`, so a fit is bit-identical across
+    /// runs, see `init_seed`
+    pub deterministic: bool,
+    /// Group documents by dominant topic after fitting
+    pub clusters: bool,
+    /// Export this many highest weighted documents of each topic, with text snippets
+    pub topic_examples: Option<usize>,
+    /// Preprocessed seed words for the leading topics, see `load_seed_topics`
+    pub seed_topics: Vec<Vec<String>>,
+    /// Strength of the prior pulling seed words into their topics
+    pub seed_strength: f32,
+    /// Dedicate the last topic to the corpus-wide term weights, see `Background`
+    pub background: Option<Background>,
+    /// Treat terms in more than this share of documents as stopwords,
+    /// listing them in `AUTO_STOPWORDS_FILE`
+    pub auto_stopwords: Option<f32>,
+    /// Pattern and list rules pruning the vocabulary, listing the terms they drop in
+    /// `PRUNED_TERMS_FILE`
+    pub vocab_filter: VocabularyFilter,
+    /// Shared vocabulary file: loaded when it exists, otherwise built from
+    /// the first fitted dataset and saved there
+    pub vocab_path: Option<String>,
+    /// Codec for tokens.csv and the topic distributions
+    pub compression: Compression,
+    /// Leave documents without any vocabulary terms out of the factorization;
+    /// their rows of W are zero
+    pub exclude_empty: bool,
+    /// Where the model and its outputs are written and tokens.csv is read from
+    pub workdir: Workdir,
+    /// Split every topic into this many subtopics, written to `TOPIC_TREE_FILE`
+    pub subtopics: Option<usize>,
+    pub solver: Solver,
+    /// Stop on a validation share of V's entries instead of `tol`
+    pub early_stopping: Option<EarlyStopping>,
+    /// Convergence criteria besides `tol` and `max_iter`
+    pub stopping: StoppingRule,
+    /// Save W and H to `CHECKPOINT_FILE` every this many iterations
+    pub checkpoint_every: Option<usize>,
+    /// Checkpoint or saved model to start the factorization from, see `load_warm_start`
+    pub warm_start: Option<String>,
+    /// Weights of the documents in the objective, see `DocumentWeights`
+    pub document_weights: Option<String>,
+    /// Write H to `TOPIC_WORDS_FILE`
+    pub topic_words: Option<TopicWords>,
+    /// Write each document's this many highest weighted TF-IDF terms to `KEYWORDS_FILE`
+    pub keywords: Option<usize>,
+    /// Write each document's this many highest weighted topics to `TOP_TOPICS_FILE`
+    pub top_topics: Option<usize>,
+    /// Scale each document's topic weights to sum to 1 in the written distributions
+    pub normalize_topics: bool,
+    /// Words listed per topic when printing and reporting topics
+    pub top_words: TopWords,
+    /// Keep the TF-IDF matrix in a memory-mapped file rather than in memory, see `nmf_mapped`
+    pub mapped: bool,
+    /// Most MiB the dense TF-IDF matrix may take, see `check_memory_budget`
+    pub max_memory: Option<f64>,
+    /// Also write the topic distributions as an Arrow IPC file
+    #[cfg(feature = "arrow")]
+    pub arrow: bool,
+}
+
+/// Layout of the exported topic-word matrix, see `save_topic_word_matrix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicWords {
+    /// One row per topic with a column per vocabulary term
+    Dense,
+    /// One (topic, term, weight) row for each of a topic's highest weighted terms
+    Top(usize),
+}
+
+/// How many of a topic's words `Topics` lists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopWords {
+    /// Most words listed per topic
+    pub count: usize,
+    /// Words weighted at most this much are left out
+    pub min_weight: f32,
+}
+
+impl Default for TopWords {
+    fn default() -> Self {
+        TopWords { count: 10, min_weight: 0.001 }
+    }
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        ModelConfig {
+            min_df: 3,
+            idf: Idf::Smooth,
+            k: 5,
+            max_iter: 200,
+            tol: 1e-4,
+            lambda: 0.01,
+            holdout: 0.0,
+            seed: 42,
+            deterministic: false,
+            clusters: false,
+            topic_examples: None,
+            seed_topics: Vec::new(),
+            seed_strength: 1.0,
+            background: None,
+            auto_stopwords: None,
+            vocab_filter: VocabularyFilter::default(),
+            vocab_path: None,
+            compression: Compression::None,
+            exclude_empty: false,
+            workdir: Workdir::default(),
+            subtopics: None,
+            solver: Solver::Mu,
+            top_topics: None,
+            normalize_topics: false,
+            stopping: StoppingRule::default(),
+            early_stopping: None,
+            checkpoint_every: None,
+            warm_start: None,
+            document_weights: None,
+            topic_words: None,
+            keywords: None,
+            top_words: TopWords::default(),
+            mapped: false,
+            max_memory: None,
+            #[cfg(feature = "arrow")]
+            arrow: false,
+        }
+    }
+}
+
+impl ModelConfig {
+    /// Seed of the random initializations: `seed` when deterministic, otherwise none
+    /// so each fit starts from fresh entropy.
+    pub fn init_seed(&self) -> Option<u64> {
+        self.deterministic.then_some(self.seed)
+    }
+}
+
+/// Seed of the `stream`-th random stream derived from `seed` (SplitMix64), so fits
+/// run in parallel or repeated get distinct seeds that don't depend on the order they
+/// run in.
+pub fn child_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Generator for a random initialization: seeded when `seed` is given.
+fn init_rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)
+}
+
+/// A fitted topic model: the vocabulary and IDF weights used for vectorization
+/// together with the topic-word matrix H, so new documents can be projected
+/// onto the learned topics.
+#[derive(Serialize, Deserialize)]
+pub struct NmfModel {
+    pub vocab: Vocabulary,
+    pub idf: Array1<f32>,
+    pub h: Array2<f32>,
+    /// How well the factorization reproduced the training matrix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fit: Option<ReconstructionError>,
+}
+
+impl NmfModel {
+    /// Projects documents onto the fixed topics, returning their document-topic rows.
+    /// W starts from `seed` when given.
+    pub fn transform(&self, documents: &[Vec<String>], max_iter: usize, tol: f32, seed: Option<u64>) -> Array2<f32> {
+        let tfidf = create_tfidf_matrix(documents, &self.vocab, &self.idf);
+        project(&tfidf, &self.h, max_iter, tol, seed)
+    }
+
+    /// Relative error ‖V − WH‖ / ‖V‖ of documents the model wasn't fit on, projected
+    /// onto its topics with `config`'s iterations, tolerance and seed.
+    pub fn heldout_error(&self, documents: &[Vec<String>], config: &ModelConfig) -> f32 {
+        let tfidf = create_tfidf_matrix(documents, &self.vocab, &self.idf);
+        let w = project(&tfidf, &self.h, config.max_iter, config.tol, config.init_seed());
+        relative_error(&tfidf, &w, &self.h)
+    }
+
+    pub fn topics(&self, top: TopWords) -> Topics {
+        Topics::new(&self.h, &self.vocab, top)
+    }
+
+    /// Adds the terms that now qualify for the vocabulary under `config`'s `min_df`,
+    /// filters and auto stopwords, given the document frequencies of a corpus of
+    /// `documents` that has grown since the fit. The terms get the columns after the
+    /// existing ones, their IDF weight over the corpus and zero weights in H, so the
+    /// topics and the columns of known terms are unchanged until `fit_terms`.
+    /// Returns the columns added.
+    pub fn grow_vocabulary(&mut self, doc_counts: &HashMap<String, usize>, documents: usize, config: &ModelConfig) -> Result<Range<usize>> {
+        let candidates: HashMap<String, usize> = doc_counts
+            .iter()
+            .filter(|&(term, &count)| count >= config.min_df && !self.vocab.contains(term) && config.vocab_filter.keeps(term))
+            .map(|(term, &count)| (term.clone(), count))
+            .collect();
+        let auto_stopwords_file = config.workdir.path(AUTO_STOPWORDS_FILE);
+        let excluded = match config.auto_stopwords {
+            Some(_) if Path::new(&auto_stopwords_file).exists() => std::fs::read_to_string(&auto_stopwords_file)?.lines().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+
+        let start = self.vocab.len();
+        let added = self.vocab.update(&candidates, config.min_df, &excluded);
+        let idf = added.iter().map(|term| config.idf.weight(documents as f32, candidates[term] as f32));
+        self.idf = self.idf.iter().copied().chain(idf).collect();
+        let mut h = Array2::<f32>::zeros((self.h.nrows(), self.vocab.len()));
+        h.slice_mut(s![.., ..start]).assign(&self.h);
+        self.h = h;
+        Ok(start..self.vocab.len())
+    }
+
+    /// Fits the topic weights in H of the terms in `columns` to the TF-IDF matrix of
+    /// `documents`, with the documents' topic rows `w` held fixed, leaving the other
+    /// terms' weights as they are. Used on terms `grow_vocabulary` added, whose weights
+    /// start at zero, which multiplicative updates would never move from.
+    pub fn fit_terms(&mut self, documents: &[Vec<String>], w: &Array2<f32>, columns: Range<usize>, max_iter: usize, tol: f32, seed: Option<u64>) {
+        let tfidf = create_tfidf_matrix(documents, &self.vocab, &self.idf);
+        // V ≈ WH restricted to the columns is Vᵀ ≈ Hᵀ Wᵀ, a projection onto fixed Wᵀ
+        let v = tfidf.slice(s![.., columns.clone()]).t().to_owned();
+        let h = project(&v, &w.t().to_owned(), max_iter, tol, seed);
+        self.h.slice_mut(s![.., columns]).assign(&h.t());
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<NmfModel> {
+        let file = File::open(path)?;
+        let model = serde_json::from_reader(BufReader::new(file))?;
+        Ok(model)
+    }
+}
+
+pub fn load_documents(filepath: &str) -> Result<Vec<Vec<String>>> {
+    let file = compression::open(filepath)?;
     let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(file);
     let mut documents = Vec::new();
 
@@ -29,154 +312,1079 @@ fn load_documents(filepath: &str) -> Result<Vec<Vec<String>>> {
     Ok(documents)
 }
 
-fn build_vocabulary(documents: &[Vec<String>], min_df: usize) -> HashMap<String, usize> {
-    let mut doc_counts = HashMap::new();
-    for doc in documents {
-        let unique_tokens: HashSet<_> = doc.iter().collect();
-        for token in unique_tokens {
-            *doc_counts.entry(token.clone()).or_insert(0) += 1;
+fn build_vocabulary(documents: &[Vec<String>], config: &ModelConfig) -> Result<Vocabulary> {
+    let mut doc_counts = vocabulary::document_frequencies(documents);
+    if !config.vocab_filter.is_empty() {
+        let mut pruned: Vec<String> = doc_counts.keys().filter(|term| !config.vocab_filter.keeps(term)).cloned().collect();
+        pruned.sort();
+        for term in &pruned {
+            doc_counts.remove(term);
         }
+        let path = config.workdir.path(PRUNED_TERMS_FILE);
+        std::fs::write(&path, pruned.iter().map(|t| format!("{}\n", t)).collect::<String>())?;
+        progress!("Pruned {} terms by the vocabulary filters, written to {}", pruned.len(), path);
+        console::artifact("pruned_terms", &path);
     }
+    let auto_stopwords = match config.auto_stopwords {
+        Some(ratio) => {
+            let terms = vocabulary::frequent_terms(&doc_counts, documents.len(), ratio);
+            let path = config.workdir.path(AUTO_STOPWORDS_FILE);
+            std::fs::write(&path, terms.iter().map(|t| format!("{}\n", t)).collect::<String>())?;
+            progress!("Flagged {} corpus-specific stopwords, written to {}", terms.len(), path);
+            console::artifact("auto_stopwords", &path);
+            terms
+        }
+        None => Vec::new(),
+    };
+    Ok(Vocabulary::build(doc_counts, config.min_df, &auto_stopwords))
+}
 
-    let mut vocab = HashMap::new();
-    let mut next_idx = 0;
-    for (token, count) in doc_counts {
-        if count >= min_df {
-            vocab.insert(token, next_idx);
-            next_idx += 1;
+/// Inverse document frequency formula, for n documents of which df contain the term.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Idf {
+    /// ln(n / df) + 1, scikit-learn's `TfidfTransformer(smooth_idf=False)`
+    Standard,
+    /// ln((1 + n) / (1 + df)) + 1, scikit-learn's default `smooth_idf=True`
+    #[default]
+    Smooth,
+    /// max(0, ln((n − df) / df)), so terms in half the documents or more get no weight
+    Probabilistic,
+    /// 1 for every term, leaving the term frequencies unweighted
+    None,
+}
+
+impl Idf {
+    fn weight(self, documents: f32, df: f32) -> f32 {
+        match self {
+            Idf::Standard => (documents / df.max(1.0)).ln() + 1.0,
+            Idf::Smooth => ((documents + 1.0) / (df + 1.0)).ln() + 1.0,
+            Idf::Probabilistic => ((documents - df) / df.max(1.0)).max(1.0).ln(),
+            Idf::None => 1.0,
         }
     }
-    //println!("Vocab: {:?}", vocab);
-    vocab
 }
 
-fn create_tfidf_matrix(documents: &[Vec<String>], vocab: &HashMap<String, usize>) -> Array2<f32> {
-    let (num_docs, vocab_size) = (documents.len(), vocab.len());
-    let mut tf = Array2::<f32>::zeros((num_docs, vocab_size));
-    let mut idf = Array1::<f32>::zeros(vocab_size);
+fn compute_idf(documents: &[Vec<String>], vocab: &Vocabulary, variant: Idf) -> Array1<f32> {
+    let mut idf = Array1::<f32>::zeros(vocab.len());
+
+    let num_docs_f32 = documents.len() as f32;
+    for (token, token_idx) in vocab.iter() {
+        let docs_with_token = documents.iter()
+            .filter(|doc| doc.iter().any(|t| t == token))
+            .count() as f32;
+        idf[token_idx] = variant.weight(num_docs_f32, docs_with_token);
+    }
+    idf
+}
+
+pub(crate) fn create_tfidf_matrix(documents: &[Vec<String>], vocab: &Vocabulary, idf: &Array1<f32>) -> Array2<f32> {
+    let mut tfidf = Array2::<f32>::zeros((documents.len(), vocab.len()));
+    for (doc, row) in documents.iter().zip(tfidf.rows_mut()) {
+        tfidf_row(doc, vocab, idf, row);
+    }
+    tfidf
+}
 
+/// Fills a zeroed row with the TF-IDF weights of one document.
+fn tfidf_row(doc: &[String], vocab: &Vocabulary, idf: &Array1<f32>, mut row: ArrayViewMut1<f32>) {
     // Calculate Term Frequency (TF) using filtered document length
-    for (doc_idx, doc) in documents.iter().enumerate() {
-        let mut valid_tokens = 0;
-        for token in doc {
-            if vocab.contains_key(token) {
-                valid_tokens += 1;
+    let valid_tokens = doc.iter().filter(|token| vocab.contains(token)).count();
+    if valid_tokens == 0 { return; }
+
+    let doc_len = valid_tokens as f32;
+    for token in doc {
+        if let Some(token_idx) = vocab.get(token) {
+            row[token_idx] += 1.0 / doc_len;
+        }
+    }
+
+    // Calculate TF-IDF and ensure non-negativity
+    row *= idf;
+    row.mapv_inplace(|x| x.max(0.0));  // Clip negative values to 0
+}
+
+#[derive(Deserialize)]
+struct SeedFile {
+    topics: Vec<SeedTopic>,
+}
+
+#[derive(Deserialize)]
+struct SeedTopic {
+    words: Vec<String>,
+}
+
+/// Reads seed word lists from a TOML file of the form
+///
+/// ```toml
+/// [[topics]]
+/// words = ["vaccine", "virus"]
+/// ```
+///
+/// The words are run through the same normalization, cleaning and stemming as the
+/// documents so they match vocabulary entries. Topic `i` of the model is seeded by
+/// entry `i`.
+pub fn load_seed_topics(path: &str, config: &PreprocessConfig) -> Result<Vec<Vec<String>>> {
+    let seeds: SeedFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+    let stemmer = config.stemmer.algorithm(&config.stem_language).map_err(anyhow::Error::msg)?;
+    let normalizer = Normalizer::new(&config.normalize).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let preprocessor = Preprocessor::with_tokenizer(Box::new(RegexTokenizer::new(&config.tokenizer)?), HashSet::new())
+        .with_stemmer(stemmer)
+        .with_normalizer(normalizer);
+    Ok(seeds.topics
+        .iter()
+        .map(|topic| topic.words
+            .iter()
+            .flat_map(|word| preprocessor.process(word))
+            .collect())
+        .collect())
+}
+
+/// Builds the k × vocabulary mask marking each seeded topic's seed words. Seed words
+/// outside the vocabulary are reported and recorded as the `missing_seed_words`
+/// metric; fails when a topic has none of its seed words left.
+fn seed_mask(seed_topics: &[Vec<String>], k: usize, vocab: &Vocabulary) -> Result<Array2<f32>> {
+    let mut mask = Array2::<f32>::zeros((k, vocab.len()));
+    let mut missing = Vec::new();
+    for (topic, words) in seed_topics.iter().enumerate().take(k) {
+        let mut unknown = Vec::new();
+        for word in words {
+            match vocab.get(word) {
+                Some(idx) => mask[[topic, idx]] = 1.0,
+                None => unknown.push(word.clone()),
             }
         }
-        if valid_tokens == 0 { continue; }
+        if unknown.len() == words.len() {
+            anyhow::bail!("None of the seed words of topic {} are in the vocabulary ({})", topic, unknown.join(", "));
+        }
+        if !unknown.is_empty() {
+            progress!("Seed words of topic {} not in the vocabulary: {}", topic, unknown.join(", "));
+            missing.extend(unknown);
+        }
+    }
+    if !missing.is_empty() {
+        console::metric("missing_seed_words", missing);
+    }
+    Ok(mask)
+}
+
+/// A topic started from the corpus-wide weight of each term in V, so common but
+/// uninformative words concentrate there instead of spreading over every topic.
+/// Takes the last of the k topics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Background {
+    /// Updated like the other topics after the start
+    Free,
+    /// Held at the corpus profile throughout the fit
+    Fixed,
+}
+
+/// Corpus profile of the background topic: each term's summed weight over the rows
+/// of V, scaled so the largest is 1.
+fn background_profile(column_sums: Array1<f32>, eps: f32) -> Array1<f32> {
+    let max = column_sums.fold(0.0f32, |max, &x| max.max(x));
+    column_sums.mapv(|x| x / max.max(eps) + eps)
+}
+
+/// Where the time of an `nmf` run went, and how the error converged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NmfTimings {
+    pub iterations: usize,
+    pub h_update: Duration,
+    pub w_update: Duration,
+    /// Reconstruction error and convergence check
+    pub error: Duration,
+    /// Relative reconstruction error ‖V − WH‖ / ‖V‖ after each iteration
+    #[serde(default)]
+    pub errors: Vec<f32>,
+    /// Relative error on the hidden validation entries after each iteration, when
+    /// early stopping is enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validation_errors: Vec<f32>,
+}
+
+/// Final reconstruction error of a factorization.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ReconstructionError {
+    /// Frobenius norm ‖V − WH‖ of the residual
+    pub frobenius_error: f32,
+    /// Squared residual relative to the data, ‖V − WH‖² / ‖V‖²
+    pub relative_error: f32,
+    /// Share of ‖V‖² the factorization reproduces, 1 − `relative_error`
+    pub explained_variance: f32,
+    /// Iterations the factorization ran for
+    pub iterations: usize,
+    /// Criterion that ended the factorization
+    #[serde(default)]
+    pub stopped_by: StopReason,
+}
+
+impl ReconstructionError {
+    /// From the squared residual ‖V − WH‖² and ‖V‖².
+    fn new(residual: f32, norm_v: f32, iterations: usize, stopped_by: StopReason) -> ReconstructionError {
+        let relative_error = residual / norm_v.max(f32::EPSILON);
+        ReconstructionError {
+            frobenius_error: residual.sqrt(),
+            relative_error,
+            explained_variance: 1.0 - relative_error,
+            iterations,
+            stopped_by,
+        }
+    }
+}
 
-        let doc_len = valid_tokens as f32;
-        for token in doc {
-            if let Some(&token_idx) = vocab.get(token) {
-                tf[[doc_idx, token_idx]] += 1.0 / doc_len;
+/// What the error improvement compared against `tol` is relative to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Convergence {
+    /// The error after the first iteration, so a large initial error makes later
+    /// improvements look small
+    #[default]
+    Initial,
+    /// The previous iteration's error
+    Previous,
+}
+
+/// When a factorization stops before `max_iter`, besides the `tol` criterion (or the
+/// early stopping that replaces it). Every criterion that is set applies; the first
+/// one met ends the fit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StoppingRule {
+    pub convergence: Convergence,
+    /// Stop once the relative error ‖V − WH‖ / ‖V‖ falls to this
+    pub error_floor: Option<f32>,
+    /// Stop once the norm of the projected gradient falls to this share of its norm
+    /// after the first iteration (Lin, 2007)
+    pub gradient_tol: Option<f32>,
+    /// Stop once the factorization has run this long
+    pub time_budget: Option<Duration>,
+}
+
+/// Why a factorization stopped iterating.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// Ran all `max_iter` iterations
+    #[default]
+    MaxIter,
+    /// The error improved by less than `tol`
+    Tolerance,
+    ErrorFloor,
+    GradientNorm,
+    TimeBudget,
+    /// The validation error stopped improving
+    EarlyStopping,
+    /// Ctrl-C
+    Interrupted,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StopReason::MaxIter => "max iterations",
+            StopReason::Tolerance => "error tolerance",
+            StopReason::ErrorFloor => "error floor",
+            StopReason::GradientNorm => "gradient norm",
+            StopReason::TimeBudget => "time budget",
+            StopReason::EarlyStopping => "early stopping",
+            StopReason::Interrupted => "interrupted",
+        })
+    }
+}
+
+/// Convergence state of a factorization under its `StoppingRule`.
+struct StopCheck {
+    rule: StoppingRule,
+    tol: f32,
+    started: Instant,
+    error_at_init: f32,
+    prev_error: f32,
+    gradient_at_init: f32,
+}
+
+impl StopCheck {
+    fn new(options: &NmfOptions) -> StopCheck {
+        StopCheck {
+            rule: options.stopping,
+            tol: options.tol,
+            started: Instant::now(),
+            error_at_init: 0.0,
+            prev_error: 0.0,
+            gradient_at_init: 0.0,
+        }
+    }
+
+    /// Records iteration `iter`'s squared residual `error` and relative error,
+    /// returning the criterion met, if any. `gradient` computes the projected gradient
+    /// norm, only when the rule needs it; `tolerance` is false when early stopping
+    /// replaces the `tol` criterion.
+    fn check(&mut self, iter: usize, error: f32, relative_error: f32, tolerance: bool, gradient: impl FnOnce() -> f32) -> Option<StopReason> {
+        if iter == 0 {
+            self.error_at_init = error;
+            self.prev_error = error;
+        }
+        let reference = match self.rule.convergence {
+            Convergence::Initial => self.error_at_init,
+            Convergence::Previous => self.prev_error,
+        };
+        let error_diff = (self.prev_error - error) / reference;
+        self.prev_error = error;
+
+        if tolerance && error_diff < self.tol && iter > 0 {
+            return Some(StopReason::Tolerance);
+        }
+        if self.rule.error_floor.is_some_and(|floor| relative_error <= floor) {
+            return Some(StopReason::ErrorFloor);
+        }
+        if let Some(gradient_tol) = self.rule.gradient_tol {
+            let norm = gradient();
+            if iter == 0 {
+                self.gradient_at_init = norm;
+            } else if norm <= gradient_tol * self.gradient_at_init {
+                return Some(StopReason::GradientNorm);
             }
         }
+        if self.rule.time_budget.is_some_and(|budget| self.started.elapsed() >= budget) {
+            return Some(StopReason::TimeBudget);
+        }
+        None
     }
+}
 
-    // Calculate IDF with smoothing to ensure positivity
-    let num_docs_f32 = num_docs as f32;
-    for (token, &token_idx) in vocab {
-        let docs_with_token = documents.iter()
-            .filter(|doc| doc.contains(token))
-            .count() as f32;
-        idf[token_idx] = 1.0 + ((num_docs_f32 + 1.0) / (docs_with_token + 1.0)).ln();
+/// Norm of the gradient of ½‖C(V − WH)‖² (C the diagonal of document weights) in W
+/// and H, projected onto the feasible directions: entries at zero only count when the
+/// gradient would push them further down.
+fn projected_gradient_norm(residual: &Array2<f32>, w: &Array2<f32>, h: &Array2<f32>, weights: Option<&Array1<f32>>) -> f32 {
+    let residual = weight_rows(residual.clone(), weights);
+    // Both gradients are the negated products with V − WH
+    let grad_w = -residual.dot(&h.t());
+    let grad_h = -w.t().dot(&residual);
+    let projected = |x: &Array2<f32>, grad: &Array2<f32>| {
+        x.iter().zip(grad).filter(|&(&x, &g)| x > 0.0 || g < 0.0).map(|(_, g)| g.powi(2)).sum::<f32>()
+    };
+    (projected(w, &grad_w) + projected(h, &grad_h)).sqrt()
+}
+
+/// Factors, timings and final error of a factorization.
+pub struct FitResult {
+    pub w: Array2<f32>,
+    pub h: Array2<f32>,
+    pub timings: NmfTimings,
+    pub error: ReconstructionError,
+}
+
+impl NmfTimings {
+    pub fn total(&self) -> Duration {
+        self.h_update + self.w_update + self.error
     }
 
-    // Calculate TF-IDF and ensure non-negativity
-    let mut tfidf = tf;
-    for mut row in tfidf.rows_mut() {
-        row *= &idf;
-        row.mapv_inplace(|x| x.max(0.0));  // Clip negative values to 0
+    pub fn per_iteration(&self) -> Duration {
+        self.total().checked_div(self.iterations as u32).unwrap_or_default()
     }
+}
 
-    tfidf
+/// Update rule used to fit W and H.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Solver {
+    /// Lee-Seung multiplicative updates
+    #[default]
+    Mu,
+    /// Hierarchical alternating least squares: exact coordinate descent, one topic at a time
+    Hals,
+    /// Alternating projected gradient steps with Armijo line search (Lin, 2007)
+    ProjGrad,
 }
 
-fn nmf(v: &Array2<f32>, k: usize, max_iter: usize, tol: f32) -> (Array2<f32>, Array2<f32>) {
-    let (docs, vocab_size) = v.dim();
-    let eps = 1e-10;
-    let lambda = 0.01;  // Reduced regularization
+/// Settings of one factorization, see `nmf`.
+#[derive(Clone, Copy)]
+pub(crate) struct NmfOptions<'a> {
+    pub k: usize,
+    pub max_iter: usize,
+    pub tol: f32,
+    pub lambda: f32,
+    pub solver: Solver,
+    /// Seed of the random W and H, see `ModelConfig::init_seed`
+    pub seed: Option<u64>,
+    /// (mask, strength) pair pulling seed words into their topics
+    pub seeds: Option<(&'a Array2<f32>, f32)>,
+    /// Warm start for H from an earlier factorization
+    pub h_init: Option<&'a Array2<f32>>,
+    /// Warm start for W, used when its shape matches V's documents and `k`
+    pub w_init: Option<&'a Array2<f32>>,
+    pub background: Option<Background>,
+    pub early_stopping: Option<EarlyStopping>,
+    pub stopping: StoppingRule,
+    pub checkpoint: Option<CheckpointTarget<'a>>,
+    /// Weight of each row of V in the objective Σ cᵢ‖vᵢ − wᵢH‖² (multiplicative updates only)
+    pub weights: Option<&'a Array1<f32>>,
+}
+
+/// Where and how often `nmf` saves a `Checkpoint`.
+#[derive(Clone, Copy)]
+pub(crate) struct CheckpointTarget<'a> {
+    pub path: &'a str,
+    pub every: usize,
+    /// Terms of H's columns
+    pub terms: &'a [&'a str],
+}
+
+/// W and H part way through a factorization.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub iteration: usize,
+    /// Terms of H's columns, as column order differs between vocabulary builds
+    pub terms: Vec<String>,
+    pub w: Array2<f32>,
+    pub h: Array2<f32>,
+}
+
+impl Checkpoint {
+    /// Writes to a temporary file first, so an interrupted save leaves the previous
+    /// checkpoint intact.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let partial = format!("{}.partial", path);
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Checkpoint> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+}
+
+/// Files a factorization can be warm-started from.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WarmStartFile {
+    Checkpoint(Checkpoint),
+    Model(NmfModel),
+}
+
+/// The saved model a factorization is warm-started from, `None` for a checkpoint.
+pub fn warm_start_model(path: &str) -> Result<Option<NmfModel>> {
+    let file = File::open(path)?;
+    Ok(match serde_json::from_reader(BufReader::new(file))? {
+        WarmStartFile::Checkpoint(_) => None,
+        WarmStartFile::Model(model) => Some(model),
+    })
+}
+
+/// Moves the columns of `h`, labelled by `terms`, to their columns in `vocab`. Terms
+/// `vocab` does not know are dropped and its other terms start from zero.
+fn align_topics<'a>(h: &Array2<f32>, terms: impl IntoIterator<Item = &'a str>, vocab: &Vocabulary) -> (Array2<f32>, usize) {
+    let mut aligned = Array2::<f32>::zeros((h.nrows(), vocab.len()));
+    let mut shared = 0;
+    for (column, term) in terms.into_iter().enumerate() {
+        if let Some(target) = vocab.get(term) {
+            aligned.column_mut(target).assign(&h.column(column));
+            shared += 1;
+        }
+    }
+    (aligned, shared)
+}
+
+/// Initial (W, H) from a checkpoint or a saved model with `k` topics. Topics are mapped
+/// onto `vocab` by term, so a model fit on another bootstrap sample works too. Only a
+/// checkpoint provides W, which `nmf` uses when it was saved for the same documents.
+pub fn load_warm_start(path: &str, vocab: &Vocabulary, k: usize) -> Result<(Option<Array2<f32>>, Array2<f32>)> {
+    let file = File::open(path)?;
+    let (w, h, terms) = match serde_json::from_reader(BufReader::new(file))? {
+        WarmStartFile::Checkpoint(checkpoint) => {
+            progress!("Warm start from iteration {} of {}", checkpoint.iteration, path);
+            (Some(checkpoint.w), checkpoint.h, checkpoint.terms)
+        }
+        WarmStartFile::Model(model) => {
+            progress!("Warm start from the topics of {}", path);
+            let terms = model.vocab.terms().into_iter().map(String::from).collect();
+            (None, model.h, terms)
+        }
+    };
+    if h.nrows() != k || h.ncols() != terms.len() {
+        anyhow::bail!("{} has {} topics over {} terms, expected {} topics", path, h.nrows(), terms.len(), k);
+    }
+    let (h, shared) = align_topics(&h, terms.iter().map(String::as_str), vocab);
+    progress!("  {} of {} terms shared", shared, vocab.len());
+    Ok((w, h))
+}
+
+/// Stops the fit once the reconstruction error on a random share of V's entries,
+/// hidden from the updates, has not improved for `patience` iterations. Replaces the
+/// `tol` criterion on the training error.
+#[derive(Debug, Clone, Copy)]
+pub struct EarlyStopping {
+    /// Share of the entries held out
+    pub fraction: f32,
+    pub patience: usize,
+    pub seed: u64,
+}
+
+/// Validation entries of V and the early stopping state.
+struct Validation {
+    mask: Array2<bool>,
+    /// V with the hidden entries replaced by the current reconstruction, so the
+    /// updates fit only the visible ones
+    filled: Array2<f32>,
+    norm: f32,
+    patience: usize,
+    best: f32,
+    best_factors: Option<(Array2<f32>, Array2<f32>)>,
+    stale: usize,
+}
+
+impl Validation {
+    fn new(v: &Array2<f32>, early_stopping: EarlyStopping) -> Validation {
+        let mut rng = StdRng::seed_from_u64(early_stopping.seed);
+        let mask = v.map(|_| rng.gen_range(0.0..1.0) < early_stopping.fraction);
+        let norm = v.iter().zip(&mask).filter(|(_, hidden)| **hidden).map(|(x, _)| x.powi(2)).sum();
+        Validation {
+            mask,
+            filled: v.clone(),
+            norm,
+            patience: early_stopping.patience,
+            best: f32::INFINITY,
+            best_factors: None,
+            stale: 0,
+        }
+    }
+
+    fn fill(&mut self, wh: &Array2<f32>) {
+        Zip::from(&mut self.filled).and(&self.mask).and(wh).for_each(|x, &hidden, &estimate| {
+            if hidden {
+                *x = estimate;
+            }
+        });
+    }
+
+    fn error(&self, v: &Array2<f32>, wh: &Array2<f32>) -> f32 {
+        let mut residual = 0.0;
+        Zip::from(v).and(&self.mask).and(wh).for_each(|&x, &hidden, &estimate| {
+            if hidden {
+                residual += (x - estimate).powi(2);
+            }
+        });
+        (residual / self.norm.max(f32::EPSILON)).sqrt()
+    }
+
+    /// Records the error of the current factors, returning whether to stop.
+    fn update(&mut self, error: f32, w: &Array2<f32>, h: &Array2<f32>) -> bool {
+        if error < self.best {
+            self.best = error;
+            self.best_factors = Some((w.clone(), h.clone()));
+            self.stale = 0;
+        } else {
+            self.stale += 1;
+        }
+        self.stale >= self.patience
+    }
+}
+
+impl NmfOptions<'_> {
+    pub fn new(config: &ModelConfig) -> NmfOptions<'static> {
+        NmfOptions {
+            k: config.k,
+            max_iter: config.max_iter,
+            tol: config.tol,
+            lambda: config.lambda,
+            solver: config.solver,
+            seed: config.init_seed(),
+            seeds: None,
+            h_init: None,
+            w_init: None,
+            background: config.background,
+            early_stopping: config.early_stopping,
+            stopping: config.stopping,
+            checkpoint: None,
+            weights: None,
+        }
+    }
+}
+
+/// Seed prior added to the numerator of the H update, scaled to the data so the
+/// strength is corpus independent.
+fn add_seed_prior(numerator_h: &mut Array2<f32>, seeds: Option<(&Array2<f32>, f32)>) {
+    if let Some((mask, strength)) = seeds {
+        let prior = strength * numerator_h.mean().unwrap_or(0.0);
+        numerator_h.scaled_add(prior, mask);
+    }
+}
+
+/// HALS update of each row of H in turn, keeping the others fixed.
+fn hals_update_h(v: &Array2<f32>, w: &Array2<f32>, h: &mut Array2<f32>, seeds: Option<(&Array2<f32>, f32)>, lambda: f32, eps: f32) {
+    let wt = w.t();
+    let mut wtv = wt.dot(v);
+    add_seed_prior(&mut wtv, seeds);
+    let wtw = wt.dot(w);
+    for t in 0..h.nrows() {
+        let gradient = &wtv.row(t) - &wtw.row(t).dot(&*h) - lambda;
+        let updated = (&h.row(t) + &(gradient / wtw[[t, t]].max(eps))).mapv(|x| x.max(eps));
+        h.row_mut(t).assign(&updated);
+    }
+}
+
+/// HALS update of each column of W in turn, keeping the others fixed.
+fn hals_update_w(v: &Array2<f32>, w: &mut Array2<f32>, h: &Array2<f32>, lambda: f32, eps: f32) {
+    let ht = h.t();
+    let vht = v.dot(&ht);
+    let hht = h.dot(&ht);
+    for t in 0..w.ncols() {
+        let gradient = &vht.column(t) - &w.dot(&hht.column(t)) - lambda;
+        let updated = (&w.column(t) + &(gradient / hht[[t, t]].max(eps))).mapv(|x| x.max(eps));
+        w.column_mut(t).assign(&updated);
+    }
+}
+
+/// One projected gradient step on min ‖B − AX‖² over X ≥ 0, given AᵀA and AᵀB. The
+/// step size is searched Armijo style from the previous one in `alpha`, shrinking until
+/// the sufficient decrease condition holds or growing while it keeps holding.
+fn projected_gradient_step(ata: &Array2<f32>, atb: &Array2<f32>, x: &Array2<f32>, alpha: &mut f32) -> Array2<f32> {
+    const SIGMA: f32 = 0.01;
+    const BETA: f32 = 0.1;
+    const MAX_TRIES: usize = 20;
+
+    let gradient = ata.dot(x) - atb;
+    let sufficient_decrease = |candidate: &Array2<f32>| {
+        let d = candidate - x;
+        (1.0 - SIGMA) * (&gradient * &d).sum() + 0.5 * (&d * &ata.dot(&d)).sum() <= 0.0
+    };
+    let step = |alpha: f32| (x - &(&gradient * alpha)).mapv(|v| v.max(0.0));
+
+    let mut candidate = step(*alpha);
+    let shrink = !sufficient_decrease(&candidate);
+    for _ in 0..MAX_TRIES {
+        if shrink {
+            if sufficient_decrease(&candidate) {
+                break;
+            }
+            *alpha *= BETA;
+            candidate = step(*alpha);
+        } else {
+            let larger = step(*alpha / BETA);
+            if larger == candidate || !sufficient_decrease(&larger) {
+                break;
+            }
+            *alpha /= BETA;
+            candidate = larger;
+        }
+    }
+    candidate
+}
 
+/// Starting W and H for a (documents, terms) matrix: the warm starts in `options`, or
+/// random values, with the last row of H at the `background` profile when given.
+fn initial_factors((docs, vocab_size): (usize, usize), options: &NmfOptions, background: Option<&Array1<f32>>, eps: f32) -> (Array2<f32>, Array2<f32>) {
+    let k = options.k;
     // Initialize with higher values to prevent underflow
     let w_dist = Uniform::new(0.1, 1.0);
     let h_dist = Uniform::new(0.1, 1.0);
-    let mut w = Array2::random((docs, k), w_dist);
-    let mut h = Array2::random((k, vocab_size), h_dist);
+    let mut rng = init_rng(options.seed);
+    let w = match options.w_init {
+        Some(w_init) if w_init.dim() == (docs, k) => w_init.mapv(|x| x + eps),
+        Some(_) => {
+            progress!("Warm start W does not match the {} documents, starting W from random values", docs);
+            Array2::random_using((docs, k), w_dist, &mut rng)
+        }
+        None => Array2::random_using((docs, k), w_dist, &mut rng),
+    };
+    let mut h = match options.h_init {
+        // Lift exact zeros, which multiplicative updates could never move
+        Some(h_init) => h_init.mapv(|x| x + eps),
+        None => Array2::random_using((k, vocab_size), h_dist, &mut rng),
+    };
+    if let Some((mask, _)) = options.seeds {
+        h.zip_mut_with(mask, |h, &m| if m > 0.0 { *h = 1.0 });
+    }
+    if let Some(profile) = background {
+        h.row_mut(k - 1).assign(profile);
+    }
+    (w, h)
+}
+
+fn save_checkpoint(checkpoint: Option<CheckpointTarget>, iteration: usize, w: &Array2<f32>, h: &Array2<f32>) {
+    if let Some(target) = checkpoint.filter(|target| target.every > 0 && iteration.is_multiple_of(target.every)) {
+        let terms = target.terms.iter().map(|&term| term.to_string()).collect();
+        let saved = Checkpoint { iteration, terms, w: w.clone(), h: h.clone() }.save(target.path);
+        if let Err(e) = saved {
+            progress!("Could not save checkpoint {}: {}", target.path, e);
+        }
+    }
+}
+
+/// Resets the last row of H to a fixed background profile after an update.
+fn fix_background(h: &mut Array2<f32>, fixed: Option<&Array1<f32>>) {
+    if let Some(profile) = fixed {
+        let last = h.nrows() - 1;
+        h.row_mut(last).assign(profile);
+    }
+}
 
-    let mut error_at_init = 0 as f32;
-    let mut prev_error = 0 as f32;
+/// Scales each row of `m` by its document's weight.
+fn weight_rows(mut m: Array2<f32>, weights: Option<&Array1<f32>>) -> Array2<f32> {
+    if let Some(weights) = weights {
+        m *= &weights.view().insert_axis(Axis(1));
+    }
+    m
+}
+
+/// Sum of the squared entries of `m`, each row's scaled by its document's weight.
+fn weighted_squares(m: &Array2<f32>, weights: Option<&Array1<f32>>) -> f32 {
+    let squares = m.mapv(|x| x.powi(2));
+    match weights {
+        Some(weights) => squares.sum_axis(Axis(1)).dot(weights),
+        None => squares.sum(),
+    }
+}
+
+/// Factorizes `v` into W·H with `options.k` topics. When `seeds` is given as a
+/// (mask, strength) pair, masked entries of H start at the top of the init range and
+/// get an extra numerator term in the H update, softly pulling seed words into their
+/// topics. `h_init` and `w_init` warm-start H and W from an earlier factorization
+/// instead of random values. With `weights`, each document's residual counts that
+/// many times in the objective and the errors.
+pub(crate) fn nmf(v: &Array2<f32>, options: NmfOptions) -> FitResult {
+    let NmfOptions { max_iter, lambda, solver, seeds, h_init, early_stopping, checkpoint, weights, background, .. } = options;
+    let eps = 1e-10;
+
+    let profile = background.map(|_| background_profile(v.sum_axis(Axis(0)), eps));
+    // Row of H held at the background profile
+    let fixed = profile.as_ref().filter(|_| background == Some(Background::Fixed));
+    let (mut w, mut h) = initial_factors(v.dim(), &options, profile.as_ref(), eps);
+    if solver != Solver::Mu && h_init.is_none() {
+        // Additive updates overshoot from a start far above the data's scale, so
+        // match the mean of WH to that of V
+        let scale = (v.mean().unwrap_or(0.0) / w.dot(&h).mean().unwrap_or(1.0).max(eps)).sqrt();
+        w *= scale;
+        h *= scale;
+        if let Some(profile) = fixed {
+            // Only W takes up the scale of a fixed row
+            h.row_mut(options.k - 1).assign(profile);
+            w.column_mut(options.k - 1).mapv_inplace(|x| x * scale);
+        }
+    }
+
+    let norm_v = weighted_squares(v, weights);
+    let mut stop_check = StopCheck::new(&options);
+    let mut stopped_by = StopReason::MaxIter;
+    let mut timings = NmfTimings::default();
+    let (mut alpha_h, mut alpha_w) = (1.0f32, 1.0f32);
+    let mut validation = early_stopping.map(|early_stopping| Validation::new(v, early_stopping));
+    let full_v = v;
 
     for iter in 0..max_iter {
-        // Update H with safer regularization
-        let wt = w.t();
-        let numerator_h = wt.dot(v);
-        let denominator_h = wt.dot(&w.dot(&h)) + lambda + eps;
-        h = h * &(numerator_h / denominator_h);
-
-        // Update W with safer regularization
-        let ht = &h.t();
-        let numerator_w = v.dot(ht);
-        let denominator_w = w.dot(&h).dot(ht) + lambda + eps;
-        w = w * &(numerator_w / denominator_w);
+        if shutdown::aborted() {
+            progress!("NMF interrupted after {} iterations", iter);
+            stopped_by = StopReason::Interrupted;
+            break;
+        }
+        timings.iterations = iter + 1;
+
+        if let Some(validation) = &mut validation {
+            validation.fill(&w.dot(&h));
+        }
+        let v = validation.as_ref().map_or(full_v, |validation| &validation.filled);
+
+        match solver {
+            Solver::Mu => {
+                // Update H with safer regularization
+                let started = Instant::now();
+                // Weighted, (CW)ᵀV / (CW)ᵀWH for the diagonal C of document weights
+                let weighted_w = weights.map(|_| weight_rows(w.clone(), weights));
+                let wt = weighted_w.as_ref().unwrap_or(&w).t();
+                let mut numerator_h = wt.dot(v);
+                add_seed_prior(&mut numerator_h, seeds);
+                let denominator_h = wt.dot(&w.dot(&h)) + lambda + eps;
+                h = h * &(numerator_h / denominator_h);
+                fix_background(&mut h, fixed);
+                timings.h_update += started.elapsed();
+
+                // Update W with safer regularization
+                let started = Instant::now();
+                let ht = &h.t();
+                let numerator_w = weight_rows(v.dot(ht), weights);
+                let denominator_w = weight_rows(w.dot(&h).dot(ht), weights) + lambda + eps;
+                w = w * &(numerator_w / denominator_w);
+                timings.w_update += started.elapsed();
+            }
+            Solver::Hals => {
+                let started = Instant::now();
+                hals_update_h(v, &w, &mut h, seeds, lambda, eps);
+                fix_background(&mut h, fixed);
+                timings.h_update += started.elapsed();
+
+                let started = Instant::now();
+                hals_update_w(v, &mut w, &h, lambda, eps);
+                timings.w_update += started.elapsed();
+            }
+            Solver::ProjGrad => {
+                let started = Instant::now();
+                let wt = w.t();
+                let mut wtv = wt.dot(v);
+                add_seed_prior(&mut wtv, seeds);
+                h = projected_gradient_step(&wt.dot(&w), &(wtv - lambda), &h, &mut alpha_h);
+                fix_background(&mut h, fixed);
+                timings.h_update += started.elapsed();
+
+                // Same subproblem for Wᵀ with Hᵀ in place of W
+                let started = Instant::now();
+                let hvt = h.dot(&v.t()) - lambda;
+                w = projected_gradient_step(&h.dot(&h.t()), &hvt, &w.t().to_owned(), &mut alpha_w).reversed_axes();
+                timings.w_update += started.elapsed();
+            }
+        }
 
         // Calculate the Frobenius norm
+        let started = Instant::now();
+        let v = full_v;
         let wh = w.dot(&h);
-        let err = v - &wh;
-        let error = err.mapv(|x| x.powi(2)).sum();
-        
-        
-        if iter == 0 {
-            error_at_init = error;
-            prev_error = error_at_init;
-            
+        let residual = v - &wh;
+        let error = weighted_squares(&residual, weights);
+        let relative_error = (error / norm_v.max(f32::EPSILON)).sqrt();
+        timings.errors.push(relative_error);
+
+        save_checkpoint(checkpoint, iter + 1, &w, &h);
+        if let Some(validation) = &mut validation {
+            let validation_error = validation.error(v, &wh);
+            timings.validation_errors.push(validation_error);
+            if validation.update(validation_error, &w, &h) {
+                timings.error += started.elapsed();
+                stopped_by = StopReason::EarlyStopping;
+                break;
+            }
         }
+        let stop = stop_check.check(iter, error, relative_error, validation.is_none(), || projected_gradient_norm(&residual, &w, &h, weights));
+        timings.error += started.elapsed();
 
-        let error_diff = (prev_error - error) / error_at_init;
+        if let Some(reason) = stop {
+            stopped_by = reason;
+            break;
+        }
+    }
+    if let Some((best_w, best_h)) = validation.and_then(|validation| validation.best_factors) {
+        // Keep the factors from the iteration with the lowest validation error
+        w = best_w;
+        h = best_h;
+    }
+    let residual = weighted_squares(&(full_v - &w.dot(&h)), weights);
+    let error = ReconstructionError::new(residual, norm_v, timings.iterations, stopped_by);
+    FitResult { w, h, timings, error }
+}
 
-        prev_error = error;
+/// Multiplicative update `nmf` over a memory-mapped matrix, reading V one block of rows
+/// at a time: WᵀV is accumulated over the blocks, and each block's rows of W are
+/// updated and scored against V in a second pass.
+pub(crate) fn nmf_mapped(v: &MappedMatrix, options: NmfOptions) -> Result<FitResult> {
+    let NmfOptions { max_iter, lambda, solver, seeds, early_stopping, checkpoint, weights, stopping, .. } = options;
+    if solver != Solver::Mu || early_stopping.is_some() || weights.is_some() || stopping.gradient_tol.is_some() {
+        anyhow::bail!("A memory-mapped matrix is only fit with the multiplicative update solver, without early stopping, document weights or a gradient tolerance");
+    }
+    let eps = 1e-10;
+
+    let profile = options.background.map(|_| {
+        let column_sums = v.blocks().fold(Array1::zeros(v.dim().1), |sums, rows| sums + v.block(rows).sum_axis(Axis(0)));
+        background_profile(column_sums, eps)
+    });
+    let fixed = profile.as_ref().filter(|_| options.background == Some(Background::Fixed));
+    let (mut w, mut h) = initial_factors(v.dim(), &options, profile.as_ref(), eps);
+    let norm_v: f32 = v.blocks().map(|rows| v.block(rows).mapv(|x| x.powi(2)).sum()).sum();
+    let mut stop_check = StopCheck::new(&options);
+    let mut stopped_by = StopReason::MaxIter;
+    let mut residual = None;
+    let mut timings = NmfTimings::default();
 
-        if error_diff < tol && iter > 0 {
-            // println!("Error {}, Prev {}, Diff {}, innit {}", error, prev_error, error_diff, error_at_init);
-            // println!("Converged after {} iterations", iter + 1);
+    for iter in 0..max_iter {
+        if shutdown::aborted() {
+            progress!("NMF interrupted after {} iterations", iter);
+            stopped_by = StopReason::Interrupted;
             break;
         }
-        if iter % 10 == 0 {
-            // println!("Error {}, Prev {}, Diff {}, innit {}", error, prev_error, error_diff, error_at_init);
-            // println!("Iteration {}: error = {}", iter, error_diff);
+        timings.iterations = iter + 1;
+
+        let started = Instant::now();
+        let mut numerator_h = Array2::<f32>::zeros(h.dim());
+        for rows in v.blocks() {
+            numerator_h += &w.slice(s![rows.clone(), ..]).t().dot(&v.block(rows));
+        }
+        add_seed_prior(&mut numerator_h, seeds);
+        let denominator_h = w.t().dot(&w).dot(&h) + lambda + eps;
+        h *= &(numerator_h / denominator_h);
+        fix_background(&mut h, fixed);
+        timings.h_update += started.elapsed();
+
+        let ht = h.t();
+        let hht = h.dot(&ht);
+        let mut error = 0.0;
+        for rows in v.blocks() {
+            let started = Instant::now();
+            let v_block = v.block(rows.clone());
+            let mut w_block = w.slice_mut(s![rows, ..]);
+            let numerator_w = v_block.dot(&ht);
+            let denominator_w = w_block.dot(&hht) + lambda + eps;
+            w_block *= &(numerator_w / denominator_w);
+            timings.w_update += started.elapsed();
+
+            let started = Instant::now();
+            error += (&v_block - &w_block.dot(&h)).mapv(|x| x.powi(2)).sum();
+            timings.error += started.elapsed();
+        }
+
+        let started = Instant::now();
+        let relative_error = (error / norm_v.max(f32::EPSILON)).sqrt();
+        timings.errors.push(relative_error);
+        residual = Some(error);
+        save_checkpoint(checkpoint, iter + 1, &w, &h);
+        let stop = stop_check.check(iter, error, relative_error, true, || unreachable!("rejected above"));
+        timings.error += started.elapsed();
+
+        if let Some(reason) = stop {
+            stopped_by = reason;
+            break;
         }
     }
-    (w, h)
+    // The last pass scored W as updated against H, i.e. the returned factors
+    let residual = residual.unwrap_or_else(|| {
+        v.blocks().map(|rows| (&v.block(rows.clone()) - &w.slice(s![rows, ..]).dot(&h)).mapv(|x| x.powi(2)).sum()).sum()
+    });
+    let error = ReconstructionError::new(residual, norm_v, timings.iterations, stopped_by);
+    Ok(FitResult { w, h, timings, error })
 }
 
 
-fn print_topics(h: &Array2<f32>, vocab: &HashMap<String, usize>) -> Vec<String> {
-    let mut feature_names = vec![""; vocab.len()];
-    for (word, &idx) in vocab {
-        feature_names[idx] = word;
+/// Solves for W with H held fixed, using the same multiplicative W update as `nmf`.
+fn project(v: &Array2<f32>, h: &Array2<f32>, max_iter: usize, tol: f32, seed: Option<u64>) -> Array2<f32> {
+    let eps = 1e-10;
+    let lambda = 0.01;
+
+    let mut w = Array2::random_using((v.nrows(), h.nrows()), Uniform::new(0.1, 1.0), &mut init_rng(seed));
+    let ht = h.t();
+    let numerator_w = v.dot(&ht);
+    let hht = h.dot(&ht);
+
+    let mut prev_error = f32::MAX;
+    for _ in 0..max_iter {
+        let denominator_w = w.dot(&hht) + lambda + eps;
+        w *= &(&numerator_w / &denominator_w);
+
+        let error = (v - &w.dot(h)).mapv(|x| x.powi(2)).sum();
+        if prev_error - error < tol * prev_error {
+            break;
+        }
+        prev_error = error;
     }
+    w
+}
+
+/// A word of a topic and its weight in H.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicWord {
+    pub word: String,
+    pub weight: f32,
+}
 
-    let mut topics = Vec::new();
+/// A topic's highest weighted words, heaviest first. Displays as "Topic i: word word ...".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredTopic")]
+pub struct Topic {
+    pub index: usize,
+    pub words: Vec<TopicWord>,
+}
 
-    for (topic_idx, topic) in h.axis_iter(Axis(0)).enumerate() {
-        let mut weights: Vec<(&&str, f32)> = feature_names.iter()
-            .zip(topic.iter().copied())
-            .collect();
+impl Topic {
+    /// The words without their weights.
+    pub fn terms(&self) -> Vec<&str> {
+        self.words.iter().map(|word| word.word.as_str()).collect()
+    }
+}
+
+impl std::fmt::Display for Topic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Topic {}: {}", self.index, self.terms().join(" "))
+    }
+}
+
+/// A serialized topic: summaries written before topics carried their weights store the
+/// "Topic i: ..." line, read back with zero weights.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTopic {
+    Topic { index: usize, words: Vec<TopicWord> },
+    Line(String),
+}
 
-        weights.sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        let mut topic_string = format!("Topic {}: ", topic_idx);
-        for (word, weight) in weights.iter().take(10) {
-            if *weight > 0.001 {
-                topic_string.push_str(&format!("{} ", word));
+impl From<StoredTopic> for Topic {
+    fn from(stored: StoredTopic) -> Topic {
+        match stored {
+            StoredTopic::Topic { index, words } => Topic { index, words },
+            StoredTopic::Line(line) => {
+                let (label, words) = line.split_once(": ").unwrap_or((line.as_str(), ""));
+                Topic {
+                    index: label.trim_start_matches("Topic ").parse().unwrap_or(0),
+                    words: words.split_whitespace().map(|word| TopicWord { word: word.to_string(), weight: 0.0 }).collect(),
+                }
             }
         }
-        topics.push(topic_string.trim_end().to_string());
     }
+}
 
-    topics
+/// The top words of every topic of H.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Topics {
+    pub topics: Vec<Topic>,
 }
 
-fn save_topic_distributions(w: &Array2<f32>, output_path: &str) -> Result<()> {
-    let mut wtr = csv::Writer::from_path(output_path)?;
+impl Topics {
+    /// Lists up to `top.count` words of each topic, skipping those weighted at most
+    /// `top.min_weight`.
+    pub fn new(h: &Array2<f32>, vocab: &Vocabulary, top: TopWords) -> Topics {
+        let feature_names = vocab.terms();
+        let topics = h
+            .axis_iter(Axis(0))
+            .enumerate()
+            .map(|(index, topic)| {
+                let mut weights: Vec<(&str, f32)> = feature_names.iter().copied().zip(topic.iter().copied()).collect();
+                weights.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+                let words = weights
+                    .into_iter()
+                    .take(top.count)
+                    .filter(|&(_, weight)| weight > top.min_weight)
+                    .map(|(word, weight)| TopicWord { word: word.to_string(), weight })
+                    .collect();
+                Topic { index, words }
+            })
+            .collect();
+        Topics { topics }
+    }
+
+    pub fn get(&self, topic: usize) -> Option<&Topic> {
+        self.topics.get(topic)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Topic> {
+        self.topics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.topics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Topics {
+    type Item = &'a Topic;
+    type IntoIter = std::slice::Iter<'a, Topic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.topics.iter()
+    }
+}
+
+pub fn save_topic_distributions(w: &Array2<f32>, output_path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(compression::create(output_path)?);
 
     // Create header: ["Document", "Topic0", "Topic1", ...]
     let num_topics = w.ncols();
@@ -184,31 +1392,772 @@ fn save_topic_distributions(w: &Array2<f32>, output_path: &str) -> Result<()> {
     headers.extend((0..num_topics).map(|i| format!("Topic{}", i)));
     wtr.write_record(&headers)?;
 
+    write_topic_rows(&mut wtr, w, 0)?;
+    compression::finish_csv(wtr)?;
+    Ok(())
+}
+
+/// Writes the document-topic distributions W as the config asks: row-normalized or
+/// not, densely to `DISTRIBUTIONS_FILE` and, with `top_topics`, as each document's
+/// highest weighted topics to `TOP_TOPICS_FILE`.
+pub fn save_document_topics(w: &Array2<f32>, config: &ModelConfig) -> Result<()> {
+    let normalized = config.normalize_topics.then(|| normalize_rows(w.clone()));
+    let w = normalized.as_ref().unwrap_or(w);
+    let workdir = &config.workdir;
+    save_topic_distributions(w, &workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)))?;
+    if let Some(top) = config.top_topics {
+        let mut wtr = csv::Writer::from_writer(compression::create(workdir.path(&config.compression.path(TOP_TOPICS_FILE)))?);
+        wtr.write_record(["Document", "Topic", "Weight"])?;
+        write_top_topic_rows(&mut wtr, w, top, 0)?;
+        compression::finish_csv(wtr)?;
+    }
+    #[cfg(feature = "arrow")]
+    if config.arrow {
+        feather::write_distributions(w, &workdir.path(feather::DISTRIBUTIONS_ARROW)).map_err(|e| anyhow::anyhow!("{}", e))?;
+    }
+    Ok(())
+}
+
+/// Scales each row to sum to 1, leaving all-zero rows as they are.
+pub fn normalize_rows(mut w: Array2<f32>) -> Array2<f32> {
+    for mut row in w.rows_mut() {
+        let total = row.sum();
+        if total > 0.0 {
+            row /= total;
+        }
+    }
+    w
+}
+
+/// Reads a document-topic matrix written by `save_topic_distributions`.
+pub fn load_topic_distributions(path: &str) -> Result<Array2<f32>> {
+    let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(compression::open(path)?);
+    let mut values = Vec::new();
+    let mut rows = 0;
+    for result in rdr.records() {
+        let record = result?;
+        // Skip the leading document index column
+        for field in record.iter().skip(1) {
+            values.push(field.parse::<f32>()?);
+        }
+        rows += 1;
+    }
+    let cols = values.len().checked_div(rows).unwrap_or(0);
+    Ok(Array2::from_shape_vec((rows, cols), values)?)
+}
+
+/// Appends document-topic rows to the files `save_document_topics` wrote, numbering
+/// documents from `first_index`.
+pub fn append_document_topics(w: &Array2<f32>, first_index: usize, config: &ModelConfig) -> Result<()> {
+    let normalized = config.normalize_topics.then(|| normalize_rows(w.clone()));
+    let w = normalized.as_ref().unwrap_or(w);
+    let path = config.workdir.path(&config.compression.path(DISTRIBUTIONS_FILE));
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(compression::append(&path)?);
+    write_topic_rows(&mut wtr, w, first_index)?;
+    compression::finish_csv(wtr)?;
+    if let Some(top) = config.top_topics {
+        let path = config.workdir.path(&config.compression.path(TOP_TOPICS_FILE));
+        let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(compression::append(&path)?);
+        write_top_topic_rows(&mut wtr, w, top, first_index)?;
+        compression::finish_csv(wtr)?;
+    }
+    Ok(())
+}
+
+/// Writes the topic-word matrix H, with terms in column order, either in full or as
+/// the `top` terms of each topic.
+pub fn save_topic_word_matrix(h: &Array2<f32>, vocab: &Vocabulary, layout: TopicWords, output_path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(compression::create(output_path)?);
+    let terms = vocab.terms();
+    match layout {
+        TopicWords::Dense => {
+            let mut headers = vec!["Topic"];
+            headers.extend(&terms);
+            wtr.write_record(&headers)?;
+            for (topic, weights) in h.rows().into_iter().enumerate() {
+                let mut record = vec![format!("Topic{}", topic)];
+                record.extend(weights.iter().map(|w| format!("{:.6}", w)));
+                wtr.write_record(&record)?;
+            }
+        }
+        TopicWords::Top(top) => {
+            wtr.write_record(["Topic", "Rank", "Term", "Weight"])?;
+            for (topic, weights) in h.rows().into_iter().enumerate() {
+                let mut columns: Vec<usize> = (0..weights.len()).collect();
+                columns.sort_by(|&a, &b| weights[b].total_cmp(&weights[a]));
+                for (rank, &column) in columns.iter().take(top).enumerate() {
+                    wtr.write_record([format!("Topic{}", topic), rank.to_string(), terms[column].to_string(), format!("{:.6}", weights[column])])?;
+                }
+            }
+        }
+    }
+    compression::finish_csv(wtr)?;
+    Ok(())
+}
+
+/// Writes the `top` highest weighted TF-IDF terms of each document, one (document,
+/// file, rank, term, weight) row per term. Rows are vectorized one at a time with the
+/// model's vocabulary and IDF weights, so the matrix isn't held in memory again.
+pub fn save_keywords(documents: &[Vec<String>], paths: &[String], vocab: &Vocabulary, idf: &Array1<f32>, top: usize, output_path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(compression::create(output_path)?);
+    let terms = vocab.terms();
+    wtr.write_record(["Document", "File", "Rank", "Term", "Weight"])?;
+    let mut row = Array1::<f32>::zeros(vocab.len());
+    for (doc_idx, doc) in documents.iter().enumerate() {
+        row.fill(0.0);
+        tfidf_row(doc, vocab, idf, row.view_mut());
+        let mut columns: Vec<usize> = (0..row.len()).filter(|&column| row[column] > 0.0).collect();
+        columns.sort_by(|&a, &b| row[b].total_cmp(&row[a]));
+        let path = paths.get(doc_idx).map_or("", String::as_str);
+        for (rank, &column) in columns.iter().take(top).enumerate() {
+            wtr.write_record([doc_idx.to_string(), path.to_string(), rank.to_string(), terms[column].to_string(), format!("{:.6}", row[column])])?;
+        }
+    }
+    compression::finish_csv(wtr)?;
+    Ok(())
+}
+
+fn write_topic_rows<W: Write>(wtr: &mut csv::Writer<W>, w: &Array2<f32>, first_index: usize) -> Result<()> {
     // Write each document's topic distribution
     for (doc_idx, topic_weights) in w.rows().into_iter().enumerate() {
-        let mut record = vec![doc_idx.to_string()];
+        let mut record = vec![(first_index + doc_idx).to_string()];
         record.extend(topic_weights.iter().map(|w| format!("{:.6}", w)));
         wtr.write_record(&record)?;
     }
+    Ok(())
+}
+
+/// Writes a (document, topic, weight) row for each of a document's `top` highest
+/// weighted topics, leaving out topics it has no weight on.
+fn write_top_topic_rows<W: Write>(wtr: &mut csv::Writer<W>, w: &Array2<f32>, top: usize, first_index: usize) -> Result<()> {
+    for (doc_idx, topic_weights) in w.rows().into_iter().enumerate() {
+        let mut topics: Vec<usize> = (0..topic_weights.len()).filter(|&topic| topic_weights[topic] > 0.0).collect();
+        topics.sort_by(|&a, &b| topic_weights[b].total_cmp(&topic_weights[a]));
+        for &topic in topics.iter().take(top) {
+            wtr.write_record([(first_index + doc_idx).to_string(), topic.to_string(), format!("{:.6}", topic_weights[topic])])?;
+        }
+    }
+    Ok(())
+}
+
+/// Result of fitting a model on a set of documents.
+pub struct Fit {
+    pub model: NmfModel,
+    /// Document-topic matrix, one row per input document
+    pub w: Array2<f32>,
+    /// Documents whose TF-IDF rows are all zero
+    pub empty_documents: Vec<usize>,
+    pub timings: NmfTimings,
+}
+
+impl Fit {
+    fn new(vocab: Vocabulary, idf: Array1<f32>, result: FitResult, empty_documents: Vec<usize>) -> Fit {
+        let FitResult { w, h, timings, error } = result;
+        Fit { model: NmfModel { vocab, idf, h, fit: Some(error) }, w, empty_documents, timings }
+    }
+}
+
+fn empty_rows(v: &Array2<f32>) -> Vec<usize> {
+    v.rows()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, row)| row.iter().all(|&x| x == 0.0))
+        .map(|(idx, _)| idx)
+        .collect()
+}
 
+/// The shared vocabulary at `config.vocab_path` when it exists, otherwise one built
+/// from `documents` (and saved there, if set).
+pub(crate) fn load_vocabulary(documents: &[Vec<String>], config: &ModelConfig) -> Result<Vocabulary> {
+    match &config.vocab_path {
+        Some(path) if Path::new(path).exists() => Vocabulary::load(path),
+        _ => {
+            let vocab = build_vocabulary(documents, config)?;
+            if let Some(path) = &config.vocab_path {
+                vocab.save(path)?;
+                progress!("Saved vocabulary of {} terms to {}", vocab.len(), path);
+                console::artifact("vocabulary", path);
+            }
+            Ok(vocab)
+        }
+    }
+}
+
+/// MiB taken by a dense f32 matrix of `rows` × `cols`.
+fn dense_mib(rows: usize, cols: usize) -> f64 {
+    (rows * cols * std::mem::size_of::<f32>()) as f64 / (1024.0 * 1024.0)
+}
+
+/// Checks the dense TF-IDF matrix of `documents` over `vocab` against
+/// `config.max_memory`. Returns whether it fits; when it doesn't and `mappable`, the
+/// caller falls back to the memory-mapped matrix. Otherwise fails naming the
+/// `min_df` that would bring the vocabulary within the budget.
+pub(crate) fn check_memory_budget(documents: &[Vec<String>], vocab: &Vocabulary, config: &ModelConfig, mappable: Result<(), &str>) -> Result<bool> {
+    let Some(budget) = config.max_memory else { return Ok(true) };
+    let needed = dense_mib(documents.len(), vocab.len());
+    if needed <= budget {
+        return Ok(true);
+    }
+    let reason = match mappable {
+        Ok(()) => {
+            progress!("The dense TF-IDF matrix of {} documents x {} terms would take {:.1} MiB, over the --max-memory budget of {:.1} MiB; using a memory-mapped matrix instead",
+                documents.len(), vocab.len(), needed, budget);
+            return Ok(false);
+        }
+        Err(reason) => reason,
+    };
+
+    let max_terms = ((budget / dense_mib(documents.len(), 1)) as usize).min(vocab.len() - 1);
+    let suggestion = if max_terms == 0 {
+        "not even a single term fits, use fewer documents".to_string()
+    } else if config.vocab_path.as_deref().is_some_and(|path| Path::new(path).exists()) {
+        format!("use a --vocab of at most {} terms", max_terms)
+    } else {
+        // Document frequencies of the vocabulary terms, highest first: keeping only the
+        // terms more frequent than the first one over the budget leaves at most max_terms
+        let doc_counts = vocabulary::document_frequencies(documents);
+        let mut frequencies: Vec<usize> = vocab.iter().map(|(term, _)| doc_counts.get(term).copied().unwrap_or(0)).collect();
+        frequencies.sort_unstable_by(|a, b| b.cmp(a));
+        let min_df = (frequencies[max_terms] + 1).max(config.min_df + 1);
+        let kept = frequencies.iter().filter(|&&df| df >= min_df).count();
+        format!("try min_df = {} in the --config file, keeping {} of the {} terms where at most {} fit", min_df, kept, vocab.len(), max_terms)
+    };
+    anyhow::bail!("The dense TF-IDF matrix of {} documents x {} terms would take {:.1} MiB, over the --max-memory budget of {:.1} MiB, and {}; {}",
+        documents.len(), vocab.len(), needed, budget, reason, suggestion)
+}
+
+/// Why the fit can't use a memory-mapped matrix with `config` and `weights`, if it can't.
+fn mapped_unsupported(config: &ModelConfig, weights: Option<&Array1<f32>>) -> Result<(), &'static str> {
+    if config.subtopics.is_some() {
+        Err("the topic tree needs the matrix in memory")
+    } else if config.solver != Solver::Mu {
+        Err("a memory-mapped matrix is only fit with the multiplicative update solver")
+    } else if config.early_stopping.is_some() || config.stopping.gradient_tol.is_some() || weights.is_some() {
+        Err("a memory-mapped matrix is fit without early stopping, a gradient tolerance or document weights")
+    } else {
+        Ok(())
+    }
+}
+
+/// The vocabulary, IDF weights and TF-IDF matrix `fit` factorizes.
+pub(crate) fn vectorize(documents: &[Vec<String>], config: &ModelConfig) -> Result<(Vocabulary, Array1<f32>, Array2<f32>)> {
+    let vocab = load_vocabulary(documents, config)?;
+    check_memory_budget(documents, &vocab, config, Err("this command needs the matrix in memory"))?;
+    let idf = compute_idf(documents, &vocab, config.idf);
+    let tfidf = create_tfidf_matrix(documents, &vocab, &idf);
+    Ok((vocab, idf, tfidf))
+}
+
+/// Builds the vocabulary and TF-IDF matrix for `documents` and factorizes it.
+pub fn fit(documents: &[Vec<String>], config: &ModelConfig) -> Result<Fit> {
+    fit_timed(documents, config, &mut StepTimer::new())
+}
+
+/// Like `fit`, timing the vocabulary, TF-IDF and NMF stages on `timer`.
+pub fn fit_timed(documents: &[Vec<String>], config: &ModelConfig, timer: &mut StepTimer) -> Result<Fit> {
+    fit_weighted(documents, None, config, timer)
+}
+
+/// Like `fit_timed`, weighting each document's residual in the objective by its
+/// entry of `weights`.
+pub fn fit_weighted(documents: &[Vec<String>], weights: Option<&Array1<f32>>, config: &ModelConfig, timer: &mut StepTimer) -> Result<Fit> {
+    let vocab = timer.time("vocabulary", || load_vocabulary(documents, config))?;
+    if config.mapped || !check_memory_budget(documents, &vocab, config, mapped_unsupported(config, weights))? {
+        return fit_mapped(documents, vocab, weights, config, timer);
+    }
+    let (idf, tfidf) = timer.time("tfidf", || {
+        let idf = compute_idf(documents, &vocab, config.idf);
+        let tfidf = create_tfidf_matrix(documents, &vocab, &idf);
+        (idf, tfidf)
+    });
+    fit_matrix(&tfidf, vocab, idf, weights, config, timer)
+}
+
+/// Calls `factorize` with the NMF options `config` sets for a matrix over `vocab`.
+fn with_nmf_options<T>(vocab: &Vocabulary, config: &ModelConfig, weights: Option<&Array1<f32>>, factorize: impl FnOnce(NmfOptions) -> T) -> Result<T> {
+    if weights.is_some() && config.solver != Solver::Mu {
+        anyhow::bail!("Document weights are only supported by the multiplicative update solver");
+    }
+    if config.background.is_some() && (config.k < 2 || config.seed_topics.len() >= config.k) {
+        anyhow::bail!("A background topic takes the last topic, so k must be at least 2 and above the {} seeded topics", config.seed_topics.len());
+    }
+    let mask = (!config.seed_topics.is_empty()).then(|| seed_mask(&config.seed_topics, config.k, vocab)).transpose()?;
+    let warm_start = config.warm_start.as_deref().map(|path| load_warm_start(path, vocab, config.k)).transpose()?;
+    let checkpoint_path = config.workdir.path(CHECKPOINT_FILE);
+    let terms = vocab.terms();
+    let options = NmfOptions {
+        seeds: mask.as_ref().map(|mask| (mask, config.seed_strength)),
+        h_init: warm_start.as_ref().map(|(_, h)| h),
+        w_init: warm_start.as_ref().and_then(|(w, _)| w.as_ref()),
+        checkpoint: config.checkpoint_every.map(|every| CheckpointTarget { path: &checkpoint_path, every, terms: &terms }),
+        weights,
+        ..NmfOptions::new(config)
+    };
+    Ok(factorize(options))
+}
+
+/// Like `fit_matrix` on the TF-IDF matrix of `documents`, which is written to
+/// `MAPPED_MATRIX_FILE` in the workdir and memory-mapped instead of held in memory.
+/// With `exclude_empty` the empty documents are left out of the file.
+fn fit_mapped(documents: &[Vec<String>], vocab: Vocabulary, weights: Option<&Array1<f32>>, config: &ModelConfig, timer: &mut StepTimer) -> Result<Fit> {
+    let empty_documents: Vec<usize> = (0..documents.len())
+        .filter(|&doc_idx| !documents[doc_idx].iter().any(|token| vocab.contains(token)))
+        .collect();
+    let rows: Vec<usize> = if config.exclude_empty && !empty_documents.is_empty() {
+        let mut empty = vec![false; documents.len()];
+        for &doc_idx in &empty_documents {
+            empty[doc_idx] = true;
+        }
+        (0..documents.len()).filter(|&doc_idx| !empty[doc_idx]).collect()
+    } else {
+        (0..documents.len()).collect()
+    };
+    let (idf, v) = timer.time("tfidf", || -> Result<_> {
+        let idf = compute_idf(documents, &vocab, config.idf);
+        let v = MappedMatrix::create(&config.workdir.path(MAPPED_MATRIX_FILE), rows.len(), vocab.len(), |row_idx, row| {
+            tfidf_row(&documents[rows[row_idx]], &vocab, &idf, row);
+        })?;
+        Ok((idf, v))
+    })?;
+    let mut result = with_nmf_options(&vocab, config, weights, |options| timer.time("nmf", || nmf_mapped(&v, options)))??;
+    if rows.len() < documents.len() {
+        // Scatter W back into document order, leaving the empty documents' rows zero
+        let mut w = Array2::<f32>::zeros((documents.len(), result.w.ncols()));
+        for (row, &doc_idx) in rows.iter().enumerate() {
+            w.row_mut(doc_idx).assign(&result.w.row(row));
+        }
+        result.w = w;
+    }
+    Ok(Fit::new(vocab, idf, result, empty_documents))
+}
+
+/// Factorizes a document-term matrix whose columns are the terms of `vocab`, timing
+/// the NMF stage on `timer`. `idf` is kept in the model for projecting new documents.
+/// `weights`, one per row, weight the documents in the objective.
+pub fn fit_matrix(tfidf: &Array2<f32>, vocab: Vocabulary, idf: Array1<f32>, weights: Option<&Array1<f32>>, config: &ModelConfig, timer: &mut StepTimer) -> Result<Fit> {
+    if let Some(weights) = weights.filter(|weights| weights.len() != tfidf.nrows()) {
+        anyhow::bail!("{} document weights for {} documents", weights.len(), tfidf.nrows());
+    }
+    let empty_documents = empty_rows(tfidf);
+
+    let result = with_nmf_options(&vocab, config, weights, |options| timer.time("nmf", || if config.exclude_empty && !empty_documents.is_empty() {
+        // Factorize only the non-empty rows, then scatter W back into document order.
+        // The empty rows are zero in both V and WH, so the error is unchanged.
+        let mut empty = vec![false; tfidf.nrows()];
+        for &doc_idx in &empty_documents {
+            empty[doc_idx] = true;
+        }
+        let kept: Vec<usize> = (0..tfidf.nrows()).filter(|&idx| !empty[idx]).collect();
+        let kept_weights = weights.map(|weights| weights.select(Axis(0), &kept));
+        let kept_result = nmf(&tfidf.select(Axis(0), &kept), NmfOptions { weights: kept_weights.as_ref(), ..options });
+        let mut w = Array2::<f32>::zeros((tfidf.nrows(), config.k));
+        for (row, &doc_idx) in kept.iter().enumerate() {
+            w.row_mut(doc_idx).assign(&kept_result.w.row(row));
+        }
+        FitResult { w, ..kept_result }
+    } else {
+        nmf(tfidf, options)
+    }))?;
+
+    Ok(Fit::new(vocab, idf, result, empty_documents))
+}
+
+fn save_skipped_documents(documents: &[Vec<String>], empty_documents: &[usize], output_path: &str) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(output_path)?;
+    wtr.write_record(["Document", "Tokens", "Reason"])?;
+    for &doc_idx in empty_documents {
+        let tokens = documents[doc_idx].len();
+        let reason = if tokens == 0 { "no tokens after preprocessing" } else { "no vocabulary terms" };
+        wtr.serialize((doc_idx, tokens, reason))?;
+    }
     wtr.flush()?;
     Ok(())
 }
 
-pub fn start() -> Result<Vec<String>, Box<dyn Error>> {
-    let min_df = 3;
-    let k = 5;
-    let max_iter = 200;
-    let tol = 1e-4;
+/// Relative Frobenius error ‖V − WH‖ / ‖V‖ of a reconstruction.
+fn relative_error(v: &Array2<f32>, w: &Array2<f32>, h: &Array2<f32>) -> f32 {
+    let residual = (v - &w.dot(h)).mapv(|x| x.powi(2)).sum();
+    let total = v.mapv(|x| x.powi(2)).sum();
+    (residual / total.max(f32::EPSILON)).sqrt()
+}
+
+/// Fits on a random `config.holdout` share of the documents left out, then
+/// projects the held-out documents onto the learned topics. Returns the model,
+/// W for all documents in their original order, and the held-out relative error.
+fn fit_with_holdout(documents: &[Vec<String>], weights: Option<&Array1<f32>>, config: &ModelConfig, timer: &mut StepTimer) -> Result<(Fit, f32)> {
+    let mut order: Vec<usize> = (0..documents.len()).collect();
+    order.shuffle(&mut StdRng::seed_from_u64(config.seed));
+    let num_heldout = ((documents.len() as f32 * config.holdout).round() as usize).clamp(1, documents.len() - 1);
+    let (heldout_idx, train_idx) = order.split_at(num_heldout);
+
+    let train: Vec<Vec<String>> = train_idx.iter().map(|&i| documents[i].clone()).collect();
+    let heldout: Vec<Vec<String>> = heldout_idx.iter().map(|&i| documents[i].clone()).collect();
+
+    let train_weights = weights.map(|weights| weights.select(Axis(0), train_idx));
+    let Fit { model, w: w_train, empty_documents: empty_train, timings } = fit_weighted(&train, train_weights.as_ref(), config, timer)?;
+    let (v_heldout, w_heldout, error) = timer.time("holdout", || {
+        let v_heldout = create_tfidf_matrix(&heldout, &model.vocab, &model.idf);
+        let w_heldout = project(&v_heldout, &model.h, config.max_iter, config.tol, config.init_seed());
+        let error = relative_error(&v_heldout, &w_heldout, &model.h);
+        (v_heldout, w_heldout, error)
+    });
+
+    let mut w = Array2::<f32>::zeros((documents.len(), config.k));
+    for (row, &doc_idx) in train_idx.iter().enumerate() {
+        w.row_mut(doc_idx).assign(&w_train.row(row));
+    }
+    for (row, &doc_idx) in heldout_idx.iter().enumerate() {
+        w.row_mut(doc_idx).assign(&w_heldout.row(row));
+    }
+
+    let mut empty_documents: Vec<usize> = empty_train.iter()
+        .map(|&row| train_idx[row])
+        .chain(empty_rows(&v_heldout).iter().map(|&row| heldout_idx[row]))
+        .collect();
+    empty_documents.sort_unstable();
+
+    Ok((Fit { model, w, empty_documents, timings }, error))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ModelSummary {
+    pub topics: Topics,
+    pub heldout_error: Option<f32>,
+    pub timings: NmfTimings,
+    #[serde(default)]
+    pub quality: Option<TopicQuality>,
+    #[serde(default)]
+    pub fit: Option<ReconstructionError>,
+}
+
+/// Weights of the workdir's documents from a `DocumentWeights` file, matched by their
+/// paths or ids in files.csv.
+fn load_weights(path: &str, workdir: &Workdir, documents: usize) -> Result<Array1<f32>, Box<dyn Error>> {
+    let keys = preprocessing::load_file_paths(&workdir.path(FILES_FILE))?;
+    if keys.len() != documents {
+        return Err(format!("{} lists {} documents but the tokens have {}", FILES_FILE, keys.len(), documents).into());
+    }
+    let (weights, listed) = DocumentWeights::load(path).map_err(|e| format!("Document weights {}: {}", path, e))?.for_documents(&keys);
+    progress!("Weighted {} of {} documents from {}, the others weigh 1", listed, documents, path);
+    Ok(weights)
+}
+
+/// Fits a model on tokens.csv and writes its outputs, timing each sub-stage on `timer`.
+pub fn start(config: &ModelConfig, timer: &mut StepTimer) -> Result<ModelSummary, Box<dyn Error>> {
+    let workdir = &config.workdir;
+    let documents = timer.time("load_documents", || load_documents(&workdir.path(&config.compression.path(TOKENS_FILE))))?;
+    let weights = config.document_weights.as_deref().map(|path| load_weights(path, workdir, documents.len())).transpose()?;
+    let (Fit { model, w, empty_documents, timings }, heldout_error) = if config.holdout > 0.0 && documents.len() > 1 {
+        let (fit, error) = fit_with_holdout(&documents, weights.as_ref(), config, timer)?;
+        (fit, Some(error))
+    } else {
+        (fit_weighted(&documents, weights.as_ref(), config, timer)?, None)
+    };
+
+    let skipped_csv = workdir.path(SKIPPED_DOCUMENTS_FILE);
+    timer.time("save", || -> Result<()> {
+        save_skipped_documents(&documents, &empty_documents, &skipped_csv)?;
+        save_document_topics(&w, config)?;
+        if let Some(layout) = config.topic_words {
+            save_topic_word_matrix(&model.h, &model.vocab, layout, &workdir.path(&config.compression.path(TOPIC_WORDS_FILE)))?;
+        }
+        if let Some(top) = config.keywords {
+            let paths = preprocessing::load_file_paths(&workdir.path(FILES_FILE)).map_err(|e| anyhow::anyhow!("{}", e))?;
+            save_keywords(&documents, &paths, &model.vocab, &model.idf, top, &workdir.path(&config.compression.path(KEYWORDS_FILE)))?;
+        }
+        model.save(&workdir.path(MODEL_FILE))
+    })?;
+    console::artifact("model", workdir.path(MODEL_FILE));
+    console::artifact("distributions", workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)));
+    if config.topic_words.is_some() {
+        console::artifact("topic_words", workdir.path(&config.compression.path(TOPIC_WORDS_FILE)));
+    }
+    if config.keywords.is_some() {
+        console::artifact("keywords", workdir.path(&config.compression.path(KEYWORDS_FILE)));
+    }
+    if !empty_documents.is_empty() {
+        progress!("{} documents have no vocabulary terms, listed in {}", empty_documents.len(), skipped_csv);
+        console::artifact("skipped_documents", &skipped_csv);
+    }
+    let topics = model.topics(config.top_words);
+    console::topics(&topics);
+    if let Some(fit) = &model.fit {
+        progress!("Reconstruction error {:.4} after {} iterations (stopped by {}), {:.1}% of variance explained",
+            fit.frobenius_error, fit.iterations, fit.stopped_by, fit.explained_variance * 100.0);
+        console::metric("reconstruction_error", fit.frobenius_error);
+        console::metric("relative_error", fit.relative_error);
+        console::metric("explained_variance", fit.explained_variance);
+        console::metric("nmf_iterations", fit.iterations);
+    }
+    if let Some(error) = heldout_error {
+        console::metric("heldout_error", error);
+    }
+    if config.background.is_some() {
+        progress!("Topic {} is the background topic", config.k - 1);
+    }
+    if let Some(previous) = config.warm_start.as_deref().map(warm_start_model).transpose()?.flatten() {
+        lineage::record(&previous, &model, &workdir.path(LINEAGE_FILE), config.top_words)?;
+    }
+    let quality = quality::topic_quality(&model.h, &model.vocab, QUALITY_TOP_WORDS);
+    progress!("Topic diversity {:.3}, mean top-word overlap {:.3}", quality.diversity, quality.mean_overlap);
+    console::metric("topic_diversity", quality.diversity);
+    if let Some((a, b)) = quality.most_similar.filter(|_| quality.max_overlap > REDUNDANT_OVERLAP) {
+        progress!("Topics {} and {} look redundant: {:.0}% of their top words overlap", a, b, quality.max_overlap * 100.0);
+    }
+
+    if config.clusters {
+        let paths = preprocessing::load_file_paths(&workdir.path(FILES_FILE))?;
+        for cluster in cluster::write_clusters(&w, &paths, &workdir.path(CLUSTERS_DIR))? {
+            progress!("  Cluster {}: {} documents, mean probability {:.3}", cluster.topic, cluster.size, cluster.mean_probability);
+        }
+        console::artifact("clusters", workdir.path(CLUSTERS_DIR));
+    }
+
+    if let Some(top) = config.topic_examples {
+        let paths = preprocessing::load_file_paths(&workdir.path(FILES_FILE))?;
+        let examples_dir = workdir.path(TOPIC_EXAMPLES_DIR);
+        cluster::write_topic_examples(&w, &paths, top, &examples_dir)?;
+        progress!("Top {} example documents of each topic written to {}", top, examples_dir);
+        console::artifact("topic_examples", &examples_dir);
+    }
+
+    if let Some(k) = config.subtopics {
+        let tree = timer.time("hierarchy", || {
+            let tfidf = create_tfidf_matrix(&documents, &model.vocab, &model.idf);
+            hierarchy::topic_tree(&tfidf, &w, &model.h, &model.vocab, NmfOptions { k, background: None, ..NmfOptions::new(config) }, config.top_words)
+        });
+        let tree_json = workdir.path(TOPIC_TREE_FILE);
+        std::fs::write(&tree_json, serde_json::to_string_pretty(&tree)?)?;
+        for node in &tree {
+            progress!("  Topic {} ({} documents): {}", node.topic, node.documents, node.words);
+            for subtopic in &node.subtopics {
+                progress!("    {}.{} ({} documents): {}", node.topic, subtopic.topic, subtopic.documents, subtopic.words);
+            }
+        }
+        progress!("Topic tree written to {}", tree_json);
+        console::artifact("topic_tree", &tree_json);
+    }
+
+    Ok(ModelSummary { topics, heldout_error, timings, quality: Some(quality), fit: model.fit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::NormalizeConfig;
+
+    const TOPICS: usize = 3;
+    const TERMS_PER_TOPIC: usize = 4;
 
-    let documents = load_documents("tokens.csv")?;
-    let vocab = build_vocabulary(&documents, min_df);
-    let tfidf = create_tfidf_matrix(&documents, &vocab);
+    /// V = WH for 30 documents over 3 topics with disjoint blocks of 4 terms each. Every
+    /// other document mixes in a second topic; the pure ones make the factors unique.
+    fn planted_matrix() -> Array2<f32> {
+        let w = Array2::from_shape_fn((30, TOPICS), |(doc, topic)| match (topic + TOPICS - doc % TOPICS) % TOPICS {
+            0 => 1.0 + (doc / TOPICS) as f32 * 0.2,
+            1 if doc % 2 == 0 => 0.3,
+            _ => 0.0,
+        });
+        let h = Array2::from_shape_fn((TOPICS, TOPICS * TERMS_PER_TOPIC), |(topic, term)| {
+            if term / TERMS_PER_TOPIC == topic { 1.0 + (term % TERMS_PER_TOPIC) as f32 * 0.5 } else { 0.0 }
+        });
+        w.dot(&h)
+    }
+
+    fn options(solver: Solver) -> NmfOptions<'static> {
+        NmfOptions {
+            k: TOPICS,
+            max_iter: 500,
+            tol: 0.0,
+            lambda: 0.0,
+            solver,
+            seed: Some(7),
+            background: None,
+            early_stopping: None,
+            stopping: StoppingRule::default(),
+            ..NmfOptions::new(&ModelConfig::default())
+        }
+    }
+
+    /// Documents over the topics' terms like `planted_matrix`, with an empty document at
+    /// 5 and one whose only term is under `min_df` at 17.
+    fn planted_documents() -> Vec<Vec<String>> {
+        let mut documents: Vec<Vec<String>> = (0..30)
+            .map(|doc| {
+                (0..TERMS_PER_TOPIC)
+                    .flat_map(|term| std::iter::repeat_n(format!("topic{}term{}", doc % TOPICS, term), 1 + (doc + term) % 3))
+                    .collect()
+            })
+            .collect();
+        documents.insert(5, Vec::new());
+        documents.insert(17, vec!["rare".to_string()]);
+        documents
+    }
 
-    let (w, h) = nmf(&tfidf, k, max_iter, tol);
+    fn assert_close(actual: &Array2<f32>, expected: &Array2<f32>) {
+        assert_eq!(actual.dim(), expected.dim());
+        let largest = expected.iter().fold(0.0f32, |largest, x| largest.max(x.abs()));
+        let difference = actual.iter().zip(expected).fold(0.0f32, |largest, (a, e)| largest.max((a - e).abs()));
+        assert!(difference <= 1e-3 * largest, "factors differ by {} with entries up to {}", difference, largest);
+    }
+
+    /// Block of terms holding most of each topic's weight.
+    fn topic_blocks(h: &Array2<f32>) -> Vec<usize> {
+        h.rows().into_iter().map(|topic| {
+            let mass: Vec<f32> = topic.exact_chunks(TERMS_PER_TOPIC).into_iter().map(|block| block.sum()).collect();
+            let (block, &largest) = mass.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap();
+            assert!(largest > 0.95 * topic.sum(), "topic spread over blocks: {:?}", mass);
+            block
+        }).collect()
+    }
 
-    save_topic_distributions(&w, "document_topic_distributions.csv")?;
-    let topics = print_topics(&h, &vocab);
+    fn assert_recovers_planted_topics(solver: Solver) {
+        let v = planted_matrix();
+        let result = nmf(&v, options(solver));
+        let errors = &result.timings.errors;
+        // Up to rounding once the fit reaches the exact factors
+        assert!(errors.windows(2).all(|pair| pair[1] <= pair[0] * 1.001 + 1e-5), "{:?} error went up: {:?}", solver, errors);
+        assert!(result.error.relative_error < 1e-3, "{:?} relative error {}", solver, result.error.relative_error);
+        assert!(result.w.iter().chain(&result.h).all(|&x| x >= 0.0));
+        let mut blocks = topic_blocks(&result.h);
+        blocks.sort_unstable();
+        assert_eq!(blocks, (0..TOPICS).collect::<Vec<_>>(), "{:?} merged topics", solver);
+    }
 
-    Ok(topics)
-}
\ No newline at end of file
+    #[test]
+    fn mu_recovers_planted_topics() {
+        assert_recovers_planted_topics(Solver::Mu);
+    }
+
+    #[test]
+    fn hals_recovers_planted_topics() {
+        assert_recovers_planted_topics(Solver::Hals);
+    }
+
+    #[test]
+    fn projected_gradient_recovers_planted_topics() {
+        assert_recovers_planted_topics(Solver::ProjGrad);
+    }
+
+    #[test]
+    fn early_stopping_keeps_best_validation_factors() {
+        // Extra topics overfit the noise, so the validation error turns up again
+        let mut rng = StdRng::seed_from_u64(3);
+        let v = planted_matrix().mapv(|x| x + rng.gen_range(0.0..0.5));
+        let early_stopping = EarlyStopping { fraction: 0.2, patience: 10, seed: 5 };
+        let result = nmf(&v, NmfOptions { k: 8, max_iter: 2000, early_stopping: Some(early_stopping), ..options(Solver::Mu) });
+        assert_eq!(result.error.stopped_by, StopReason::EarlyStopping);
+
+        let validation_errors = &result.timings.validation_errors;
+        let best = validation_errors.iter().copied().reduce(f32::min).unwrap();
+        assert!(best < *validation_errors.last().unwrap());
+        let restored = Validation::new(&v, early_stopping).error(&v, &result.w.dot(&result.h));
+        assert!((restored - best).abs() < 1e-6, "restored factors score {}, best was {}", restored, best);
+    }
+
+    #[test]
+    fn mapped_nmf_matches_dense() {
+        let v = planted_matrix();
+        let workdir = Workdir::temp().unwrap();
+        let mapped = MappedMatrix::create(&workdir.path(MAPPED_MATRIX_FILE), v.nrows(), v.ncols(), |row, mut values| values.assign(&v.row(row))).unwrap();
+        let options = NmfOptions { max_iter: 100, ..options(Solver::Mu) };
+        let (dense, mapped) = (nmf(&v, options), nmf_mapped(&mapped, options).unwrap());
+        assert_close(&mapped.w, &dense.w);
+        assert_close(&mapped.h, &dense.h);
+        assert_eq!(mapped.timings.iterations, dense.timings.iterations);
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn mapped_fit_leaves_out_empty_documents() {
+        let documents = planted_documents();
+        let workdir = Workdir::temp().unwrap();
+        let config = ModelConfig { k: TOPICS, deterministic: true, exclude_empty: true, workdir: workdir.clone(), ..ModelConfig::default() };
+        let dense = fit(&documents, &config).unwrap();
+        let mapped = fit(&documents, &ModelConfig { mapped: true, ..config }).unwrap();
+
+        assert_eq!(mapped.empty_documents, [5, 17]);
+        assert_eq!(mapped.empty_documents, dense.empty_documents);
+        for &doc_idx in &mapped.empty_documents {
+            assert!(mapped.w.row(doc_idx).iter().all(|&x| x == 0.0));
+        }
+        assert_close(&mapped.w, &dense.w);
+        assert_close(&mapped.model.h, &dense.model.h);
+        // Only the documents with terms are written to the matrix file
+        let matrix_bytes = std::fs::metadata(workdir.path(MAPPED_MATRIX_FILE)).unwrap().len();
+        assert_eq!(matrix_bytes as usize, 30 * mapped.model.vocab.len() * std::mem::size_of::<f32>());
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn fit_over_memory_budget_falls_back_to_mapped() {
+        let documents = planted_documents();
+        let workdir = Workdir::temp().unwrap();
+        let config = ModelConfig { k: TOPICS, deterministic: true, workdir: workdir.clone(), ..ModelConfig::default() };
+        let dense = fit(&documents, &config).unwrap();
+        assert!(!Path::new(&workdir.path(MAPPED_MATRIX_FILE)).exists());
+
+        let budgeted = fit(&documents, &ModelConfig { max_memory: Some(0.0), ..config.clone() }).unwrap();
+        assert!(Path::new(&workdir.path(MAPPED_MATRIX_FILE)).exists());
+        assert_close(&budgeted.w, &dense.w);
+        assert_close(&budgeted.model.h, &dense.model.h);
+
+        // The topic tree can't use the mapped matrix, so the fit is refused instead
+        let error = fit(&documents, &ModelConfig { max_memory: Some(0.0), subtopics: Some(2), ..config }).err().unwrap();
+        assert!(error.to_string().contains("the topic tree needs the matrix in memory"), "{}", error);
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn grow_vocabulary_keeps_existing_topics() {
+        let workdir = Workdir::temp().unwrap();
+        let config = ModelConfig { k: TOPICS, deterministic: true, workdir: workdir.clone(), ..ModelConfig::default() };
+        let mut documents = planted_documents();
+        let Fit { mut model, w, .. } = fit(&documents, &config).unwrap();
+        let (vocab, idf, h) = (model.vocab.clone(), model.idf.clone(), model.h.clone());
+
+        // A new term in the documents of the topic over "topic0term0"
+        for doc in documents.iter_mut().filter(|doc| doc.iter().any(|token| token == "topic0term0")) {
+            doc.push("topic0new".to_string());
+        }
+        let doc_counts = vocabulary::document_frequencies(&documents);
+        let columns = model.grow_vocabulary(&doc_counts, documents.len(), &config).unwrap();
+        assert_eq!(columns, vocab.len()..vocab.len() + 1);
+        assert_eq!(model.vocab.get("topic0new"), Some(vocab.len()));
+        assert!(vocab.iter().all(|(term, column)| model.vocab.get(term) == Some(column)));
+        assert_eq!(model.h.slice(s![.., ..vocab.len()]), h);
+        assert!(model.h.column(vocab.len()).iter().all(|&x| x == 0.0));
+        assert_eq!(model.idf.slice(s![..vocab.len()]), idf);
+        assert_eq!(model.idf.len(), model.vocab.len());
+
+        model.fit_terms(&documents, &w, columns, 200, 1e-4, Some(1));
+        let old_term = vocab.get("topic0term0").unwrap();
+        let topic = |column: usize| model.h.column(column).iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(topic(vocab.len()), topic(old_term));
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn seed_words_are_normalized_like_documents() {
+        let workdir = Workdir::temp().unwrap();
+        let path = workdir.path("seeds.toml");
+        std::fs::write(&path, "[[topics]]\nwords = [\"Café\", \"coöperate\"]\n").unwrap();
+        let config = PreprocessConfig { normalize: NormalizeConfig { fold_accents: true, ..NormalizeConfig::default() }, ..PreprocessConfig::default() };
+        let documents = Preprocessor::new(&config).unwrap().process("cafe cooperate");
+        assert_eq!(load_seed_topics(&path, &config).unwrap(), std::slice::from_ref(&documents));
+        // Without folding the accented words don't match the documents' tokens
+        assert_ne!(load_seed_topics(&path, &PreprocessConfig::default()).unwrap(), [documents]);
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn seed_mask_skips_unknown_words_and_fails_without_any() {
+        let vocab = Vocabulary::build(vocabulary::document_frequencies(&[vec!["apple".to_string(), "pear".to_string()]]), 1, &[]);
+        let seeds = |topics: &[&[&str]]| -> Vec<Vec<String>> { topics.iter().map(|words| words.iter().map(|w| w.to_string()).collect()).collect() };
+
+        let mask = seed_mask(&seeds(&[&["pear", "plum"], &["apple"]]), 3, &vocab).unwrap();
+        assert_eq!(mask, ndarray::arr2(&[[0.0, 1.0], [1.0, 0.0], [0.0, 0.0]]));
+
+        let error = seed_mask(&seeds(&[&["apple"], &["plum", "fig"]]), 3, &vocab).unwrap_err();
+        assert!(error.to_string().contains("topic 1 are in the vocabulary (plum, fig)"), "{}", error);
+    }
+}
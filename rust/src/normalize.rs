@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Normalization of the text before tokenizing and of the tokens before stopword
+/// removal and stemming, see `Normalizer`.
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeConfig {
+    /// Unicode NFKC normalization, e.g. the ligature "ﬁ" to "fi" and full-width letters
+    /// to ASCII ones
+    pub nfkc: bool,
+    /// Strip diacritics and spell out letters such as "ß" and "æ", so "coöperate"
+    /// becomes "cooperate" instead of being split by the tokenizer
+    pub fold_accents: bool,
+    /// Word list misspelled tokens are folded into: one word per line, optionally
+    /// followed by a count ranking words at the same distance
+    pub spelling_words: Option<String>,
+    /// Most edits (insertions, deletions, substitutions, transpositions) between a token
+    /// and the word it is folded into
+    pub spelling_distance: usize,
+}
+
+/// Tokens shorter than this are never folded, as too many words are a single edit apart
+const MIN_FOLDED_LENGTH: usize = 4;
+
+/// Letters NFKD doesn't decompose into a base letter and combining marks.
+fn spell_out(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ø' => "o",
+        'Ø' => "O",
+        'ł' => "l",
+        'Ł' => "L",
+        'đ' => "d",
+        'Đ' => "D",
+        'þ' => "th",
+        'Þ' => "TH",
+        'ı' => "i",
+        _ => return None,
+    })
+}
+
+/// Words of a spelling list, bucketed by length so only words within the edit
+/// distance of a token's length are compared.
+struct SpellingWords {
+    by_length: HashMap<usize, Vec<(String, u64)>>,
+    max_distance: usize,
+    /// Fold of every token seen so far; `None` when it is kept as is
+    cache: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl SpellingWords {
+    fn load(path: &str, max_distance: usize, normalize: impl Fn(&str) -> String) -> Result<SpellingWords, Box<dyn Error>> {
+        let mut by_length: HashMap<usize, Vec<(String, u64)>> = HashMap::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let mut fields = line.split(|c: char| c.is_whitespace() || c == ',');
+            let Some(word) = fields.next().filter(|word| !word.is_empty()) else { continue };
+            let count = fields.find(|field| !field.is_empty()).and_then(|count| count.parse().ok()).unwrap_or(0);
+            let word = normalize(word).to_lowercase();
+            by_length.entry(word.chars().count()).or_default().push((word, count));
+        }
+        // Stable, so words of equal count keep the file's order
+        for words in by_length.values_mut() {
+            words.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        }
+        Ok(SpellingWords { by_length, max_distance, cache: Mutex::new(HashMap::new()) })
+    }
+
+    fn contains(&self, token: &str, length: usize) -> bool {
+        self.by_length.get(&length).is_some_and(|words| words.iter().any(|(word, _)| word == token))
+    }
+
+    /// The closest listed word within the edit distance, preferring higher counts.
+    fn closest(&self, token: &str) -> Option<String> {
+        let length = token.chars().count();
+        if length < MIN_FOLDED_LENGTH || self.contains(token, length) {
+            return None;
+        }
+        let mut best: Option<(usize, u64, &str)> = None;
+        for candidate_length in length.saturating_sub(self.max_distance)..=length + self.max_distance {
+            for (word, count) in self.by_length.get(&candidate_length).into_iter().flatten() {
+                let distance = strsim::osa_distance(token, word);
+                if distance <= self.max_distance && best.is_none_or(|(d, c, _)| distance < d || (distance == d && *count > c)) {
+                    best = Some((distance, *count, word));
+                }
+            }
+        }
+        best.map(|(_, _, word)| word.to_string())
+    }
+
+    fn fold(&self, token: String) -> String {
+        if let Some(folded) = self.cache.lock().unwrap().get(&token) {
+            return folded.clone().unwrap_or(token);
+        }
+        let folded = self.closest(&token);
+        self.cache.lock().unwrap().insert(token.clone(), folded.clone());
+        folded.unwrap_or(token)
+    }
+}
+
+/// Applies a `NormalizeConfig`: Unicode normalization and accent folding of the raw
+/// text, then folding of misspelled tokens into the closest word of a word list.
+#[derive(Default)]
+pub struct Normalizer {
+    nfkc: bool,
+    fold_accents: bool,
+    spelling: Option<SpellingWords>,
+}
+
+impl Normalizer {
+    pub fn new(config: &NormalizeConfig) -> Result<Normalizer, Box<dyn Error>> {
+        let mut normalizer = Normalizer { nfkc: config.nfkc, fold_accents: config.fold_accents, spelling: None };
+        if let Some(path) = &config.spelling_words {
+            let spelling = SpellingWords::load(path, config.spelling_distance, |word| normalizer.text(word).into_owned())
+                .map_err(|e| format!("Spelling word list {}: {}", path, e))?;
+            normalizer.spelling = Some(spelling);
+        }
+        Ok(normalizer)
+    }
+
+    /// Normalizes text before it is tokenized.
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = if self.nfkc { Cow::Owned(text.nfkc().collect()) } else { Cow::Borrowed(text) };
+        if self.fold_accents {
+            text = text
+                .nfkd()
+                .filter(|&c| !is_combining_mark(c))
+                .fold(String::with_capacity(text.len()), |mut folded, c| {
+                    match spell_out(c) {
+                        Some(letters) => folded.push_str(letters),
+                        None => folded.push(c),
+                    }
+                    folded
+                })
+                .into();
+        }
+        text
+    }
+
+    /// Folds a token into the closest listed word, if any is close enough.
+    pub fn token(&self, token: String) -> String {
+        match &self.spelling {
+            Some(spelling) => spelling.fold(token),
+            None => token,
+        }
+    }
+}
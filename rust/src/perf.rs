@@ -0,0 +1,53 @@
+use crate::HardwareCounts;
+use perf_event::events::Hardware;
+use perf_event::{Builder, Counter};
+use std::io;
+
+/// `ESRCH`, returned by perf_event_open for a thread that no longer exists
+const ESRCH: i32 = 3;
+
+/// Hardware counters of every thread of the process. perf events observe a single
+/// thread, so a counter is opened on each thread alive at `start` (e.g. the rayon pool)
+/// and inherited by the threads they spawn while it runs.
+pub struct PerfCounters {
+    threads: Vec<[Counter; 3]>,
+}
+
+impl PerfCounters {
+    /// Opens and enables the counters. Fails when perf events are unavailable, e.g. not
+    /// permitted by `kernel.perf_event_paranoid` or inside a container.
+    pub fn start() -> io::Result<PerfCounters> {
+        let mut threads = Vec::new();
+        for entry in std::fs::read_dir("/proc/self/task")? {
+            let Some(tid) = entry?.file_name().to_str().and_then(|name| name.parse::<i32>().ok()) else {
+                continue;
+            };
+            let counter = |kind: Hardware| Builder::new().observe_pid(tid).inherit(true).kind(kind).build();
+            let counters = match (counter(Hardware::INSTRUCTIONS), counter(Hardware::CACHE_MISSES), counter(Hardware::BRANCH_MISSES)) {
+                (Ok(instructions), Ok(cache_misses), Ok(branch_misses)) => [instructions, cache_misses, branch_misses],
+                // The thread exited since the directory was listed
+                (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) if e.raw_os_error() == Some(ESRCH) => continue,
+                (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return Err(e),
+            };
+            threads.push(counters);
+        }
+        for counter in threads.iter_mut().flatten() {
+            counter.enable()?;
+        }
+        Ok(PerfCounters { threads })
+    }
+
+    /// Stops the counters and sums them over the threads.
+    pub fn stop(mut self) -> io::Result<HardwareCounts> {
+        let mut counts = HardwareCounts::default();
+        for [instructions, cache_misses, branch_misses] in &mut self.threads {
+            for counter in [&mut *instructions, &mut *cache_misses, &mut *branch_misses] {
+                counter.disable()?;
+            }
+            counts.instructions += instructions.read()?;
+            counts.cache_misses += cache_misses.read()?;
+            counts.branch_misses += branch_misses.read()?;
+        }
+        Ok(counts)
+    }
+}
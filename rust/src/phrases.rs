@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+pub const PHRASES_FILE: &str = "phrases.txt";
+
+/// Separator joining the parts of a merged phrase, e.g. "new_york".
+const JOINER: &str = "_";
+
+#[derive(Debug, Clone)]
+pub struct PhraseConfig {
+    /// Pairs seen fewer times than this are never merged
+    pub min_count: usize,
+    /// Minimum collocation score for a pair to be merged
+    pub threshold: f32,
+}
+
+impl Default for PhraseConfig {
+    fn default() -> Self {
+        PhraseConfig {
+            min_count: 5,
+            threshold: 10.0,
+        }
+    }
+}
+
+/// Token pairs that occur together often enough to be treated as one term.
+#[derive(Debug, Clone, Default)]
+pub struct Phrases {
+    pairs: HashSet<(String, String)>,
+}
+
+impl Phrases {
+    /// Scores every adjacent pair with gensim's original `Phrases` scorer:
+    /// `(count(a b) - min_count) * vocabulary / (count(a) * count(b))`, where
+    /// `vocabulary` is the number of distinct tokens, keeping the pairs scoring above
+    /// the threshold.
+    pub fn learn(documents: &[Vec<String>], config: &PhraseConfig) -> Phrases {
+        let mut unigrams: HashMap<&str, usize> = HashMap::new();
+        let mut bigrams: HashMap<(&str, &str), usize> = HashMap::new();
+        for doc in documents {
+            for token in doc {
+                *unigrams.entry(token).or_insert(0) += 1;
+            }
+            for pair in doc.windows(2) {
+                *bigrams.entry((&pair[0], &pair[1])).or_insert(0) += 1;
+            }
+        }
+
+        let vocabulary = unigrams.len();
+        let pairs = bigrams.into_iter()
+            .filter(|&(_, count)| count >= config.min_count)
+            .filter(|&((a, b), count)| {
+                let score = (count - config.min_count) as f32 * vocabulary as f32
+                    / (unigrams[a] * unigrams[b]) as f32;
+                score > config.threshold
+            })
+            .map(|((a, b), _)| (a.to_string(), b.to_string()))
+            .collect();
+        Phrases { pairs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Merges known pairs left to right, so each token joins at most one phrase.
+    pub fn apply(&self, tokens: Vec<String>) -> Vec<String> {
+        if self.pairs.is_empty() {
+            return tokens;
+        }
+        let mut merged = Vec::with_capacity(tokens.len());
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(token) = iter.next() {
+            match iter.peek() {
+                Some(next) if self.pairs.contains(&(token.clone(), next.clone())) => {
+                    merged.push(format!("{}{}{}", token, JOINER, next));
+                    iter.next();
+                }
+                _ => merged.push(token),
+            }
+        }
+        merged
+    }
+
+    /// Writes one pair per line, space separated.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut pairs: Vec<_> = self.pairs.iter().collect();
+        pairs.sort();
+        std::fs::write(path, pairs.iter().map(|(a, b)| format!("{} {}\n", a, b)).collect::<String>())?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Phrases, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut pairs = HashSet::new();
+        for line in reader.lines() {
+            let line = line?;
+            if let Some((a, b)) = line.split_once(' ') {
+                pairs.insert((a.to_string(), b.to_string()));
+            }
+        }
+        Ok(Phrases { pairs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workdir::Workdir;
+
+    fn tokens(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn phrases(pairs: &[(&str, &str)]) -> Phrases {
+        Phrases { pairs: pairs.iter().map(|&(a, b)| (a.to_string(), b.to_string())).collect() }
+    }
+
+    #[test]
+    fn learn_merges_collocations_only() {
+        // "new york" always occurs together; "york and" is as frequent a pair, but
+        // "and" is too common on its own for it to score; the filler words occur once
+        let documents: Vec<Vec<String>> = (0..10)
+            .map(|doc| {
+                let filler: Vec<String> = (0..30).map(|word| format!("w{}_{}", doc, word)).collect();
+                tokens(&format!("new york and {} and and", filler.join(" ")))
+            })
+            .collect();
+        let learned = Phrases::learn(&documents, &PhraseConfig::default());
+        assert_eq!(learned.len(), 1);
+        assert_eq!(learned.apply(tokens("new york and")), tokens("new_york and"));
+    }
+
+    #[test]
+    fn apply_merges_left_to_right() {
+        let phrases = phrases(&[("a", "b"), ("b", "c")]);
+        assert_eq!(phrases.apply(tokens("a b c")), tokens("a_b c"));
+        assert_eq!(phrases.apply(tokens("b c a b b")), tokens("b_c a_b b"));
+        assert_eq!(Phrases::default().apply(tokens("a b")), tokens("a b"));
+    }
+
+    #[test]
+    fn save_and_load_keep_pairs() {
+        let phrases = phrases(&[("new", "york"), ("ice", "cream")]);
+        let workdir = Workdir::temp().unwrap();
+        let path = workdir.path(PHRASES_FILE);
+        phrases.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ice cream\nnew york\n");
+        assert_eq!(Phrases::load(&path).unwrap().pairs, phrases.pairs);
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+}
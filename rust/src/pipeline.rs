@@ -0,0 +1,224 @@
+use crate::modeling::{self, ModelConfig, ModelSummary};
+use crate::preprocessing::{self, PreprocessConfig, PreprocessingSummary, Preprocessor};
+use crate::timer::StepTimer;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::marker::PhantomData;
+
+/// Structured result of a stage, reported to the `Instrument` for exporters.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum StepOutput {
+    Preprocessing(PreprocessingSummary),
+    Modeling(ModelSummary),
+}
+
+/// One step of a pipeline, turning its input into the next step's input. Steps that
+/// exchange files through the workdir (such as `Preprocess` and `Model`) pass on a
+/// summary of what they wrote.
+pub trait Stage {
+    type Input;
+    type Output;
+
+    /// Name the stage is measured and reported under
+    fn name(&self) -> &str;
+
+    /// Runs the stage, timing its sub-stages on `timer`.
+    fn run(&mut self, input: Self::Input, timer: &mut StepTimer) -> Result<Self::Output, Box<dyn Error>>;
+
+    /// The structured form of an output reported to the `Instrument`, if the stage has one.
+    fn output(&self, _output: &Self::Output) -> Option<StepOutput> {
+        None
+    }
+}
+
+/// Measures the stages of a pipeline as they run, e.g. the benchmark's `measure_step`.
+pub trait Instrument {
+    /// Runs `stage`, which must be called exactly once.
+    fn measure(&mut self, name: &str, stage: &mut dyn FnMut() -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>>;
+
+    /// Receives the sub-stages a stage timed, once it finished.
+    fn substeps(&mut self, _name: &str, _timer: &StepTimer) {}
+
+    /// Receives a stage's `StepOutput`, once it finished.
+    fn output(&mut self, _name: &str, _output: StepOutput) {}
+}
+
+/// Times each stage as a sub-stage named after it.
+impl Instrument for StepTimer {
+    fn measure(&mut self, name: &str, stage: &mut dyn FnMut() -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        self.time(name, stage)
+    }
+}
+
+type RunFn<'a, I, O> = Box<dyn FnMut(I, &mut dyn Instrument) -> Result<O, Box<dyn Error>> + 'a>;
+
+/// Chains stages whose outputs match the next stage's input, see `Pipeline`.
+pub struct PipelineBuilder<'a, I, O> {
+    names: Vec<String>,
+    run: RunFn<'a, I, O>,
+}
+
+impl<'a, I: 'a> PipelineBuilder<'a, I, I> {
+    /// An empty pipeline, passing its input through.
+    pub fn new() -> Self {
+        PipelineBuilder { names: Vec::new(), run: Box::new(|input, _| Ok(input)) }
+    }
+}
+
+impl<'a, I: 'a> Default for PipelineBuilder<'a, I, I> {
+    fn default() -> Self {
+        PipelineBuilder::new()
+    }
+}
+
+impl<'a, I: 'a, O: 'a> PipelineBuilder<'a, I, O> {
+    /// Appends `stage`, run on the output of the stages before it.
+    pub fn stage<S>(self, mut stage: S) -> PipelineBuilder<'a, I, S::Output>
+    where
+        S: Stage<Input = O> + 'a,
+        S::Output: 'a,
+    {
+        let PipelineBuilder { mut names, run: mut previous } = self;
+        names.push(stage.name().to_string());
+        let run = move |input: I, instrument: &mut dyn Instrument| {
+            let mut input = Some(previous(input, instrument)?);
+            let mut output = None;
+            let mut timer = StepTimer::new();
+            let name = stage.name().to_string();
+            instrument.measure(&name, &mut || {
+                let input = input.take().ok_or_else(|| format!("Stage {} ran more than once", name))?;
+                output = Some(stage.run(input, &mut timer)?);
+                Ok(())
+            })?;
+            instrument.substeps(&name, &timer);
+            let output = output.ok_or_else(|| format!("Stage {} was not run", name))?;
+            if let Some(step_output) = stage.output(&output) {
+                instrument.output(&name, step_output);
+            }
+            Ok(output)
+        };
+        PipelineBuilder { names, run: Box::new(run) }
+    }
+
+    pub fn build(self) -> Pipeline<'a, I, O> {
+        Pipeline { names: self.names, run: self.run, _input: PhantomData }
+    }
+}
+
+/// Stages run in order, each measured by the `Instrument` the pipeline is run with.
+pub struct Pipeline<'a, I, O> {
+    names: Vec<String>,
+    run: RunFn<'a, I, O>,
+    _input: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> Pipeline<'_, I, O> {
+    /// Names of the stages, in the order they run.
+    pub fn stages(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn run(&mut self, input: I, instrument: &mut dyn Instrument) -> Result<O, Box<dyn Error>> {
+        (self.run)(input, instrument)
+    }
+}
+
+/// A stage from a closure.
+pub struct FnStage<F, I, O> {
+    name: String,
+    run: F,
+    _types: PhantomData<fn(I) -> O>,
+}
+
+/// Wraps `run` as a stage called `name`.
+pub fn from_fn<F, I, O>(name: &str, run: F) -> FnStage<F, I, O>
+where
+    F: FnMut(I, &mut StepTimer) -> Result<O, Box<dyn Error>>,
+{
+    FnStage { name: name.to_string(), run, _types: PhantomData }
+}
+
+impl<F, I, O> Stage for FnStage<F, I, O>
+where
+    F: FnMut(I, &mut StepTimer) -> Result<O, Box<dyn Error>>,
+{
+    type Input = I;
+    type Output = O;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, input: I, timer: &mut StepTimer) -> Result<O, Box<dyn Error>> {
+        (self.run)(input, timer)
+    }
+}
+
+/// Tokenizes the documents at the input path into the workdir, see `preprocessing::run`.
+pub struct Preprocess<'a> {
+    config: &'a PreprocessConfig,
+    preprocessor: Option<Preprocessor>,
+}
+
+impl<'a> Preprocess<'a> {
+    pub fn new(config: &'a PreprocessConfig) -> Preprocess<'a> {
+        Preprocess { config, preprocessor: None }
+    }
+
+    /// Tokenizes with `preprocessor` (e.g. a custom tokenizer) instead of one built from the config.
+    pub fn with_preprocessor(config: &'a PreprocessConfig, preprocessor: Preprocessor) -> Preprocess<'a> {
+        Preprocess { config, preprocessor: Some(preprocessor) }
+    }
+}
+
+impl Stage for Preprocess<'_> {
+    type Input = String;
+    type Output = PreprocessingSummary;
+
+    fn name(&self) -> &str {
+        "preprocessing"
+    }
+
+    fn run(&mut self, path: String, _timer: &mut StepTimer) -> Result<PreprocessingSummary, Box<dyn Error>> {
+        match &self.preprocessor {
+            Some(preprocessor) => preprocessing::run(&path, preprocessor, self.config),
+            None => preprocessing::start(&path, self.config),
+        }
+    }
+
+    fn output(&self, summary: &PreprocessingSummary) -> Option<StepOutput> {
+        Some(StepOutput::Preprocessing(summary.clone()))
+    }
+}
+
+/// Fits a model on the workdir's tokens and writes its outputs, see `modeling::start`.
+pub struct Model<'a> {
+    config: &'a ModelConfig,
+}
+
+impl<'a> Model<'a> {
+    pub fn new(config: &'a ModelConfig) -> Model<'a> {
+        Model { config }
+    }
+}
+
+impl Stage for Model<'_> {
+    type Input = PreprocessingSummary;
+    type Output = ModelSummary;
+
+    fn name(&self) -> &str {
+        "modeling"
+    }
+
+    fn run(&mut self, preprocessed: PreprocessingSummary, timer: &mut StepTimer) -> Result<ModelSummary, Box<dyn Error>> {
+        if preprocessed.documents == 0 {
+            return Err("Preprocessing found no documents to model".into());
+        }
+        modeling::start(self.config, timer)
+    }
+
+    fn output(&self, summary: &ModelSummary) -> Option<StepOutput> {
+        Some(StepOutput::Modeling(summary.clone()))
+    }
+}
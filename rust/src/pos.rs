@@ -0,0 +1,62 @@
+use crate::tokenizer::{RegexTokenizer, Tokenizer};
+use std::error::Error;
+
+/// Default nlprule tokenizer binary with the English tagger.
+pub const POS_MODEL_FILE: &str = "en_tokenizer.bin";
+
+#[derive(Debug, Clone)]
+pub struct PosConfig {
+    /// nlprule tokenizer binary, e.g. `en_tokenizer.bin`
+    pub model_path: String,
+    /// Penn Treebank tag prefixes to keep; "NN" keeps NN, NNS, NNP and NNPS
+    pub tags: Vec<String>,
+}
+
+impl Default for PosConfig {
+    fn default() -> Self {
+        PosConfig {
+            model_path: POS_MODEL_FILE.to_string(),
+            tags: vec!["NN".to_string(), "JJ".to_string()],
+        }
+    }
+}
+
+/// Tags the text and keeps only the words whose part of speech is whitelisted,
+/// then cleans the survivors with the regular tokenizer.
+pub struct PosTokenizer {
+    tagger: nlprule::Tokenizer,
+    tags: Vec<String>,
+    inner: RegexTokenizer,
+}
+
+impl PosTokenizer {
+    pub fn new(config: &PosConfig, inner: RegexTokenizer) -> Result<PosTokenizer, Box<dyn Error>> {
+        Ok(PosTokenizer {
+            tagger: nlprule::Tokenizer::new(&config.model_path)?,
+            tags: config.tags.clone(),
+            inner,
+        })
+    }
+
+    fn keep(&self, token: &nlprule::types::Token) -> bool {
+        token.word().tags().iter().any(|data| {
+            let pos = data.pos().as_str();
+            self.tags.iter().any(|tag| pos.starts_with(tag.as_str()))
+        })
+    }
+}
+
+impl Tokenizer for PosTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let content_words: Vec<String> = self.tagger.pipe(text)
+            .flat_map(|sentence| {
+                sentence.tokens()
+                    .iter()
+                    .filter(|token| self.keep(token))
+                    .map(|token| token.word().text().as_str().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.inner.tokenize(&content_words.join(" "))
+    }
+}
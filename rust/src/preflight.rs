@@ -0,0 +1,94 @@
+use crate::{sample_path, DATASETS, ITERATIONS, METRICS_ROOT, SAMPLES};
+use preproccess::modeling::ModelConfig;
+use preproccess::preprocessing::{self, PreprocessConfig, Preprocessor, STOPWORDS_FILE};
+use preproccess::progress;
+use preproccess::readers;
+use std::error::Error;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Document count and total bytes of one dataset.
+fn dataset_size(input: &str) -> Result<(usize, u64), Box<dyn Error>> {
+    let path = Path::new(input);
+    if readers::is_corpus_file(path) {
+        let rows = std::io::BufReader::new(preproccess::compression::open(path)?).lines().count();
+        return Ok((rows.saturating_sub(1), path.metadata()?.len()));
+    }
+    let files = preprocessing::input_files(input)?;
+    let mut bytes = 0;
+    for file in &files {
+        bytes += file.metadata().map_err(|e| format!("{} (listed in {}): {}", file.display(), input, e))?.len();
+    }
+    Ok((files.len(), bytes))
+}
+
+/// Whether a file can be created in `dir`, creating the directory if needed.
+fn check_writable(dir: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(".preflight");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(probe)?;
+    Ok(())
+}
+
+/// Checks everything the benchmark will read or write and prints the planned run
+/// matrix without running it. Fails listing every problem found.
+pub fn run(preprocess_config: &PreprocessConfig, config: &ModelConfig, jobs: usize, k_values: Option<&[usize]>) -> Result<(), Box<dyn Error>> {
+    let mut problems = Vec::new();
+    let k_values = k_values.unwrap_or(std::slice::from_ref(&config.k));
+    let runs = k_values.len() * ITERATIONS * DATASETS;
+
+    progress!("Planned runs: {} topic count(s) {:?} x {} iterations x {} datasets per sample size, {} parallel job(s)",
+        k_values.len(), k_values, ITERATIONS, DATASETS, jobs);
+    for sample in SAMPLES {
+        let mut documents = Vec::new();
+        let mut bytes = 0;
+        for dataset in 1..=DATASETS {
+            let input = sample_path(sample, dataset);
+            if !Path::new(&input).exists() {
+                problems.push(format!("Missing dataset {}", input));
+                continue;
+            }
+            match dataset_size(&input) {
+                Ok((count, size)) => {
+                    documents.push(count);
+                    bytes += size;
+                }
+                Err(e) => problems.push(format!("Unreadable dataset {}: {}", input, e)),
+            }
+        }
+        let (min, max) = (documents.iter().min().copied().unwrap_or(0), documents.iter().max().copied().unwrap_or(0));
+        progress!("  N={}: {} of {} datasets found, {}-{} documents each, {:.1} MB total, {} runs",
+            sample, documents.len(), DATASETS, min, max, bytes as f64 / (1024.0 * 1024.0), runs);
+    }
+    progress!("Total: {} runs", SAMPLES.len() * runs);
+
+    if Path::new(STOPWORDS_FILE).exists() {
+        progress!("Project stopwords: {}", STOPWORDS_FILE);
+    } else {
+        progress!("Project stopwords: {} not found, using the built-in list only", STOPWORDS_FILE);
+    }
+    // Loads the stopword files and part-of-speech model, validating the settings
+    if let Err(e) = Preprocessor::new(preprocess_config) {
+        problems.push(format!("Invalid preprocessing settings: {}", e));
+    }
+    if let Some(vocab) = &config.vocab_path {
+        let state = if Path::new(vocab).exists() { "reused" } else { "built from the first dataset" };
+        progress!("Vocabulary: {} ({})", vocab, state);
+    }
+
+    for dir in [Path::new(METRICS_ROOT), config.workdir.root()] {
+        if let Err(e) = check_writable(dir) {
+            problems.push(format!("Cannot write to {}: {}", dir.display(), e));
+        }
+    }
+
+    if problems.is_empty() {
+        progress!("Preflight checks passed");
+        return Ok(());
+    }
+    for problem in &problems {
+        progress!("  {}", problem);
+    }
+    Err(format!("Preflight found {} problem(s)", problems.len()).into())
+}
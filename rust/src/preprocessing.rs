@@ -1,12 +1,29 @@
-use csv::Writer;
+use crate::compression::{self, Compression};
+use crate::console;
+use crate::dates::DocumentDates;
+#[cfg(feature = "arrow")]
+use crate::feather;
+use crate::filters::{self, LengthFilter, RegexFilter, StopwordFilter, TokenFilter};
+use crate::modeling;
+use crate::normalize::{NormalizeConfig, Normalizer};
+#[cfg(feature = "pos")]
+use crate::pos::{PosConfig, PosTokenizer};
+use crate::phrases::{PhraseConfig, Phrases, PHRASES_FILE};
+use crate::readers;
+#[cfg(feature = "remote")]
+use crate::remote;
+use crate::workdir::{Workdir, ENCODINGS_FILE, FILES_FILE, TOKENS_FILE};
+use crate::tokenizer::{CharNgramTokenizer, CharNgrams, RegexTokenizer, Tokenizer, TokenizerConfig};
+use clap::ValueEnum;
+use csv::{Writer, WriterBuilder};
 use regex::Regex;
 use serde::ser;
 use serde_json;
 use std::collections::HashSet;
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use stemmer::Stemmer;
 use walkdir::WalkDir;   
 
@@ -20,96 +37,601 @@ struct TextData {
 struct FileData {
     index: u32,
     file_path: String,
+    tokens_before_filtering: usize,
+    tokens_after_filtering: usize,
+    bytes: u64,
+    language: String,
+    /// YYYY-MM-DD, see `DocumentDates`
+    date: String,
 }
 
-fn load_stopwords(filepath: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+/// Types of the `FileData` columns in files.arrow.
+#[cfg(feature = "arrow")]
+const FILE_COLUMNS: [feather::Column; 7] = {
+    use feather::Column::{Int, Text};
+    [Int, Text, Int, Int, Int, Text, Text]
+};
+
+impl FileData {
+    fn new(index: u32, doc: ProcessedDoc) -> FileData {
+        FileData {
+            index,
+            file_path: doc.id,
+            tokens_before_filtering: doc.tokens_before_filtering,
+            tokens_after_filtering: doc.tokens.len(),
+            bytes: doc.bytes,
+            language: doc.language,
+            date: doc.date,
+        }
+    }
+}
+
+/// A document tokenized by `Preprocessor::stream`, with what files.csv records about it.
+#[derive(Debug, Clone)]
+pub struct ProcessedDoc {
+    /// File path, or the row id of a document from a corpus file
+    pub id: String,
+    pub tokens: Vec<String>,
+    /// Tokens before stopword removal
+    pub tokens_before_filtering: usize,
+    /// Size of the file, or of the row's text
+    pub bytes: u64,
+    /// ISO 639-3 code, empty when the text is too short or mixed to tell
+    pub language: String,
+    /// YYYY-MM-DD, see `DocumentDates`
+    pub date: String,
+    /// Encoding of a file that was not UTF-8 and was converted
+    pub encoding: Option<&'static str>,
+}
+
+/// Documents yielded one at a time by `Preprocessor::stream`.
+pub type ProcessedDocs<'a> = Box<dyn Iterator<Item = Result<ProcessedDoc, Box<dyn Error>>> + 'a>;
+
+/// What a preprocessing run wrote to the workdir.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct PreprocessingSummary {
+    pub documents: usize,
+    /// Tokens before stopword removal, over all documents
+    pub tokens_before_filtering: usize,
+    /// Tokens written to tokens.csv, over all documents
+    pub tokens: usize,
+    /// Documents left without any tokens
+    pub empty_documents: usize,
+    /// Non-UTF-8 files converted, listed in encodings.csv
+    pub converted_files: usize,
+    /// Phrases learned and merged, when phrase merging is enabled
+    pub phrases: Option<usize>,
+}
+
+/// Project stopword list, merged into the stopwords when present.
+pub const STOPWORDS_FILE: &str = "../stopwords.txt";
+
+/// English stopword list compiled into the binary so it works without any files.
+const DEFAULT_STOPWORDS: &str = include_str!("stopwords_en.txt");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StemmerKind {
+    /// The original Porter algorithm (English only)
+    Porter,
+    /// The Snowball stemmer for the configured language
+    #[default]
+    Snowball,
+    /// Keep surface forms
+    None,
+}
+
+/// When stopwords are filtered relative to stemming.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StopwordStage {
+    /// Drop tokens listed as stopwords, then stem the rest
+    #[default]
+    Before,
+    /// Stem first, then drop tokens whose stem is a stopword or the stem of one, so
+    /// inflected forms ("having") and stopwords given as stems also match
+    After,
+    /// Filter both before and after stemming
+    Both,
+}
+
+impl StemmerKind {
+    /// The libstemmer algorithm name, or `None` when stemming is off.
+    pub fn algorithm(self, language: &str) -> Result<Option<&'static str>, String> {
+        let name = match self {
+            StemmerKind::Porter => "porter",
+            StemmerKind::Snowball => language,
+            StemmerKind::None => return Ok(None),
+        };
+        let algorithms = Stemmer::algorithms();
+        match algorithms.iter().find(|algorithm| **algorithm == name) {
+            Some(algorithm) => Ok(Some(*algorithm)),
+            None => Err(format!("Unknown stemmer '{}', available: {}", name, algorithms.join(", "))),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PreprocessConfig {
+    /// Column (CSV) or field (JSON Lines) holding the text in corpus files
+    pub text_column: String,
+    /// Column or field identifying each row; the row number is used when unset
+    pub id_column: Option<String>,
+    /// Codec for tokens.csv
+    pub compression: Compression,
+    /// Where tokens.csv, files.csv and the other outputs are written
+    pub workdir: Workdir,
+    pub tokenizer: TokenizerConfig,
+    /// Unicode, accent and spelling normalization ahead of tokenizing and stemming
+    pub normalize: NormalizeConfig,
+    /// Vectorize by the character n-grams of the words instead of the words, without
+    /// stopword removal or stemming
+    pub char_ngrams: Option<CharNgrams>,
+    pub stemmer: StemmerKind,
+    /// Snowball language, e.g. "english" or "french"
+    pub stem_language: String,
+    /// Start from the built-in English stopword list
+    pub default_stopwords: bool,
+    /// Stopword files merged on top of the defaults and `STOPWORDS_FILE`
+    pub stopword_files: Vec<String>,
+    /// Stopwords given directly, e.g. on the command line
+    pub extra_stopwords: Vec<String>,
+    /// Whether stopwords are filtered before or after stemming
+    pub stopword_stage: StopwordStage,
+    /// Drop tokens shorter than this many characters, before stemming
+    pub min_token_length: Option<usize>,
+    /// Drop tokens longer than this many characters, before stemming
+    pub max_token_length: Option<usize>,
+    /// Drop tokens these match, before stemming
+    pub token_exclude_patterns: Vec<Regex>,
+    /// Merge collocations into single tokens after tokenizing the corpus
+    pub phrases: Option<PhraseConfig>,
+    /// Metadata CSV of document dates recorded in files.csv; files otherwise get
+    /// their modification date
+    pub dates: Option<String>,
+    /// Keep only words with whitelisted part-of-speech tags
+    #[cfg(feature = "pos")]
+    pub pos: Option<PosConfig>,
+    /// Also write tokens and files as Arrow IPC files
+    #[cfg(feature = "arrow")]
+    pub arrow: bool,
+    /// Where `s3://` and `https://` inputs are downloaded and reused from
+    #[cfg(feature = "remote")]
+    pub remote_cache: String,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        PreprocessConfig {
+            text_column: "text".to_string(),
+            id_column: None,
+            compression: Compression::None,
+            workdir: Workdir::default(),
+            tokenizer: TokenizerConfig::default(),
+            normalize: NormalizeConfig::default(),
+            char_ngrams: None,
+            stemmer: StemmerKind::Snowball,
+            stem_language: "english".to_string(),
+            default_stopwords: true,
+            stopword_files: Vec::new(),
+            extra_stopwords: Vec::new(),
+            stopword_stage: StopwordStage::Before,
+            min_token_length: None,
+            max_token_length: None,
+            token_exclude_patterns: Vec::new(),
+            phrases: None,
+            dates: None,
+            #[cfg(feature = "pos")]
+            pos: None,
+            #[cfg(feature = "arrow")]
+            arrow: false,
+            #[cfg(feature = "remote")]
+            remote_cache: remote::DEFAULT_CACHE_DIR.to_string(),
+        }
+    }
+}
+
+pub fn load_stopwords(filepath: &str) -> Result<HashSet<String>, Box<dyn Error>> {
     let file = File::open(filepath)?;
     let reader = BufReader::new(file);
     let stopwords: HashSet<String> = reader.lines()
         .filter_map(Result::ok)
+        .map(|word| word.trim().to_lowercase())
+        .filter(|word| !word.is_empty())
         .collect();
     Ok(stopwords)
 }
 
-fn preprocess_text(text: &str, stopwords: &HashSet<String>) -> Vec<String> {
-    // Remove special characters and numbers
-    let re = Regex::new(r"[^a-zA-Z\s]").unwrap();
-    let cleaned = re.replace_all(text, " ").to_lowercase();
+/// Merges the configured stopword sources: the built-in list, `STOPWORDS_FILE` if it
+/// exists, each extra file (which must exist) and the words given directly.
+pub fn collect_stopwords(config: &PreprocessConfig) -> Result<HashSet<String>, Box<dyn Error>> {
+    let mut stopwords = HashSet::new();
+    if config.default_stopwords {
+        stopwords.extend(DEFAULT_STOPWORDS.lines().map(str::to_string));
+    }
+    if Path::new(STOPWORDS_FILE).exists() {
+        stopwords.extend(load_stopwords(STOPWORDS_FILE)?);
+    }
+    for path in &config.stopword_files {
+        stopwords.extend(load_stopwords(path).map_err(|e| format!("Failed to read stopwords from {}: {}", path, e))?);
+    }
+    stopwords.extend(config.extra_stopwords.iter().map(|word| word.trim().to_lowercase()));
+    Ok(stopwords)
+}
 
-    // Tokenize and filter empty strings
-    let tokens: Vec<String> = cleaned.split_whitespace()
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty() && !stopwords.contains(s))
-        .collect();
+/// Turns raw text into the stemmed tokens the model is fit on: tokenize, drop
+/// stopwords, run the token filters, stem, and merge learned phrases.
+pub struct Preprocessor {
+    tokenizer: Box<dyn Tokenizer>,
+    /// Applied to the text before tokenizing and to each token before stopword removal
+    normalizer: Normalizer,
+    stopwords: StopwordFilter,
+    /// The stopwords and their stems, matched against stemmed tokens
+    stemmed_stopwords: StopwordFilter,
+    stopword_stage: StopwordStage,
+    /// Applied in order after stopword removal and before stemming, see `with_filter`
+    filters: Vec<Box<dyn TokenFilter>>,
+    stemmer: Option<&'static str>,
+    phrases: Phrases,
+    dates: DocumentDates,
+    /// Columns of the text and the id of each row when streaming a corpus file
+    text_column: String,
+    id_column: Option<String>,
+}
 
-    // Lemmatization (using stemming as a simple approximation)
-    let mut stemmer = Stemmer::new("english").unwrap();
-    let tokens = tokens.iter()
-        .map(|word| stemmer.stem(word).to_string())
-        .collect();
+impl Preprocessor {
+    /// Builds the configured tokenizer and collects the stopwords, plus the phrases
+    /// learned by the last preprocessing run when phrase merging is enabled.
+    pub fn new(config: &PreprocessConfig) -> Result<Preprocessor, Box<dyn Error>> {
+        let tokenizer: Box<dyn Tokenizer> = Box::new(RegexTokenizer::new(&config.tokenizer)?);
+        #[cfg(feature = "pos")]
+        let tokenizer: Box<dyn Tokenizer> = match &config.pos {
+            Some(pos) => Box::new(PosTokenizer::new(pos, RegexTokenizer::new(&config.tokenizer)?)?),
+            None => tokenizer,
+        };
+        let normalizer = Normalizer::new(&config.normalize)?;
+        let mut preprocessor = match config.char_ngrams {
+            Some(lengths) => Preprocessor::with_tokenizer(Box::new(CharNgramTokenizer::new(tokenizer, lengths)), HashSet::new()).with_stemmer(None),
+            None => {
+                // Normalized like the text, so e.g. accented stopwords still match
+                let stopwords = collect_stopwords(config)?.iter().map(|word| normalizer.text(word).into_owned()).collect();
+                let stemmer = config.stemmer.algorithm(&config.stem_language)?;
+                let mut preprocessor = Preprocessor::with_tokenizer(tokenizer, stopwords).with_stemmer(stemmer).with_stopword_stage(config.stopword_stage);
+                if config.min_token_length.is_some() || config.max_token_length.is_some() {
+                    preprocessor = preprocessor.with_filter(LengthFilter { min: config.min_token_length.unwrap_or(0), max: config.max_token_length });
+                }
+                for pattern in &config.token_exclude_patterns {
+                    preprocessor = preprocessor.with_filter(RegexFilter(pattern.clone()));
+                }
+                preprocessor
+            }
+        };
+        preprocessor.normalizer = normalizer;
+        let phrases_file = config.workdir.path(PHRASES_FILE);
+        if config.phrases.is_some() && Path::new(&phrases_file).exists() {
+            preprocessor.phrases = Phrases::load(&phrases_file)?;
+        }
+        if let Some(dates) = &config.dates {
+            preprocessor.dates = DocumentDates::load(dates)?;
+        }
+        Ok(preprocessor.with_columns(&config.text_column, config.id_column.as_deref()))
+    }
+
+    /// A preprocessor with a custom tokenizer and the default English stemmer.
+    pub fn with_tokenizer(tokenizer: Box<dyn Tokenizer>, stopwords: HashSet<String>) -> Preprocessor {
+        Preprocessor {
+            tokenizer,
+            normalizer: Normalizer::default(),
+            stemmed_stopwords: StopwordFilter::default(),
+            stopwords: StopwordFilter::new(stopwords),
+            stopword_stage: StopwordStage::Before,
+            filters: Vec::new(),
+            stemmer: None,
+            phrases: Phrases::default(),
+            dates: DocumentDates::default(),
+            text_column: "text".to_string(),
+            id_column: None,
+        }
+        .with_stemmer(Some("english"))
+    }
+
+    /// Replaces the columns `stream` reads from CSV and JSON Lines corpus files.
+    pub fn with_columns(mut self, text_column: &str, id_column: Option<&str>) -> Preprocessor {
+        self.text_column = text_column.to_string();
+        self.id_column = id_column.map(str::to_string);
+        self
+    }
+
+    /// Replaces the stemming algorithm; `None` keeps surface forms.
+    pub fn with_stemmer(mut self, algorithm: Option<&'static str>) -> Preprocessor {
+        self.stemmer = algorithm;
+        self.stemmed_stopwords = StopwordFilter::new(self.stopwords.words().cloned().chain(self.stem(self.stopwords.words().cloned())).collect());
+        self
+    }
+
+    /// Replaces the normalization applied to the text before tokenizing.
+    pub fn with_normalizer(mut self, normalizer: Normalizer) -> Preprocessor {
+        self.normalizer = normalizer;
+        self
+    }
 
-    tokens
+    /// Replaces when stopwords are filtered relative to stemming.
+    pub fn with_stopword_stage(mut self, stage: StopwordStage) -> Preprocessor {
+        self.stopword_stage = stage;
+        self
+    }
+
+    /// Adds a filter run on each token after those added before it, following
+    /// stopword removal (when before stemming) and ahead of stemming.
+    pub fn with_filter(mut self, filter: impl TokenFilter + 'static) -> Preprocessor {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Stems `words` with the configured algorithm, if any.
+    fn stem(&self, words: impl Iterator<Item = String>) -> Vec<String> {
+        match self.stemmer {
+            Some(algorithm) => {
+                let mut stemmer = Stemmer::new(algorithm).unwrap();
+                words.map(|word| stemmer.stem(&word).to_string()).collect()
+            }
+            None => words.collect(),
+        }
+    }
+
+    pub fn process(&self, text: &str) -> Vec<String> {
+        self.phrases.apply(self.process_counted(text).0)
+    }
+
+    /// Like `process` without phrase merging, also returning the number of tokens
+    /// before stopword removal.
+    fn process_counted(&self, text: &str) -> (Vec<String>, usize) {
+        let raw_tokens = self.tokenizer.tokenize(&self.normalizer.text(text));
+        let raw_count = raw_tokens.len();
+        let stopwords = (self.stopword_stage != StopwordStage::After).then_some(&self.stopwords as &dyn TokenFilter);
+        let chain: Vec<&dyn TokenFilter> = stopwords.into_iter().chain(self.filters.iter().map(AsRef::as_ref)).collect();
+        let tokens = raw_tokens.into_iter()
+            .map(|token| self.normalizer.token(token))
+            .filter_map(|token| filters::apply(chain.iter().copied(), token));
+
+        // Lemmatization (using stemming as a simple approximation)
+        let mut tokens = self.stem(tokens);
+        if self.stopword_stage != StopwordStage::Before {
+            tokens.retain(|token| self.stemmed_stopwords.filter(token).is_some());
+        }
+
+        (tokens, raw_count)
+    }
+
+    /// Tokenizes the documents at `path` (a directory, a sample index file, or a
+    /// CSV/JSON Lines corpus file) one at a time as they are read, instead of writing
+    /// tokens.csv, so callers can hand them to a sink of their own while memory stays
+    /// flat however large the corpus. Learned phrases are merged as in `process`.
+    pub fn stream(&self, path: &str) -> Result<impl Iterator<Item = Result<ProcessedDoc, Box<dyn Error>>> + '_, Box<dyn Error>> {
+        self.documents(path, &self.text_column, self.id_column.as_deref(), true)
+    }
+
+    /// Like `stream` with the given corpus file columns, merging phrases only when `merge_phrases`.
+    fn documents<'a>(&'a self, path: &str, text_column: &str, id_column: Option<&str>, merge_phrases: bool) -> Result<ProcessedDocs<'a>, Box<dyn Error>> {
+        if readers::is_corpus_file(Path::new(path)) {
+            let rows = readers::corpus_rows(Path::new(path), text_column, id_column)?;
+            return Ok(Box::new(rows.map(move |row| {
+                let (id, content) = row?;
+                let date = self.dates.of_row(&id);
+                Ok(self.process_document(id, &content, content.len() as u64, date, merge_phrases))
+            })));
+        }
+        Ok(Box::new(walk_input_files(path)?.map(move |path| self.process_file(&path?, merge_phrases))))
+    }
+
+    /// Reads and tokenizes the document file at `path`.
+    fn process_file(&self, path: &Path, merge_phrases: bool) -> Result<ProcessedDoc, Box<dyn Error>> {
+        let (content, encoding) = readers::read_document(path)?;
+        let bytes = std::fs::metadata(path)?.len();
+        let doc = self.process_document(path.to_string_lossy().into_owned(), &content, bytes, self.dates.of_file(path), merge_phrases);
+        Ok(ProcessedDoc { encoding, ..doc })
+    }
+
+    fn process_document(&self, id: String, content: &str, bytes: u64, date: String, merge_phrases: bool) -> ProcessedDoc {
+        let (tokens, tokens_before_filtering) = self.process_counted(content);
+        ProcessedDoc {
+            id,
+            tokens: if merge_phrases { self.phrases.apply(tokens) } else { tokens },
+            tokens_before_filtering,
+            bytes,
+            language: whatlang::detect(content)
+                .filter(|info| info.is_reliable())
+                .map(|info| info.lang().code().to_string())
+                .unwrap_or_default(),
+            date,
+            encoding: None,
+        }
+    }
 }
 
-fn process_files(input_path: &str, output_path: &str, files_csv: &str, stopwords: &HashSet<String>) -> Result<(), Box<dyn Error>> {
-    let mut text_writer = Writer::from_path(output_path)?;
-    let mut file_writer = Writer::from_path(files_csv)?;
-    let mut index: u32 = 0;
-    println!("Processing files in {}...", input_path);
-    for entry in WalkDir::new(input_path)
+/// Lists the documents to process: every supported document under a directory, or the
+/// paths listed one per line in an index file written by `bootstrap --index`.
+pub fn input_files(input_path: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    Ok(walk_input_files(input_path)?.collect::<io::Result<_>>()?)
+}
+
+/// Like `input_files`, yielding the paths while the directory or index file is read
+/// rather than collecting them, for inputs too large to list up front.
+pub fn walk_input_files(input_path: &str) -> Result<Box<dyn Iterator<Item = io::Result<PathBuf>>>, Box<dyn Error>> {
+    let path = Path::new(input_path);
+    if path.is_file() {
+        let reader = BufReader::new(File::open(path)?);
+        return Ok(Box::new(reader.lines().filter_map(|line| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            line => Some(line.map(|line| PathBuf::from(line.trim()))),
+        })));
+    }
+
+    Ok(Box::new(WalkDir::new(input_path)
         .follow_links(true)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "txt") {
-            let content = std::fs::read_to_string(path)?;
-            let tokens = preprocess_text(&content, stopwords);
-
-            //let tokens_str = format!("[{}]", tokens.join(", ")); // Manually format tokens as a string
-            let tokens_str = serde_json::to_string(&tokens)?; // Use serde_json to format tokens as a string
-                                                              //let lemmatized_str = get_words_from_string(&tokens_str, "./lemmas.csv", "Vec");
-
-            //println!(lemmatized_str);
-            let text_data = TextData {
-                index,
-                tokens: tokens_str,
-            };
-
-            let file_data = FileData {
-                index,
-                file_path: path.to_string_lossy().into_owned(),
-            };
-
-            text_writer.serialize(&text_data)?;
-            file_writer.serialize(&file_data)?;
-
-            index += 1;
+        .map(|e| e.into_path())
+        .filter(|path| readers::is_document(path))
+        .map(Ok)))
+}
+
+fn process_files(input_path: &str, output_path: &str, files_csv: &str, encodings_csv: &str, preprocessor: &Preprocessor, config: &PreprocessConfig) -> Result<PreprocessingSummary, Box<dyn Error>> {
+    let mut text_writer = Writer::from_writer(compression::create(output_path)?);
+    let mut file_writer = Writer::from_path(files_csv)?;
+    let mut encoding_writer = Writer::from_path(encodings_csv)?;
+    encoding_writer.write_record(["file_path", "encoding"])?;
+    let mut converted = 0;
+    let mut summary = PreprocessingSummary::default();
+
+    if readers::is_corpus_file(Path::new(input_path)) {
+        // One document per row, keyed by its id in place of a file path
+        progress!("Processing rows in {}...", input_path);
+    } else {
+        progress!("Processing files in {}...", input_path);
+    }
+    // Phrases are learned over the whole corpus afterwards, see `merge_phrases`
+    for (index, doc) in (0u32..).zip(preprocessor.documents(input_path, &config.text_column, config.id_column.as_deref(), false)?) {
+        let doc = doc?;
+        if let Some(encoding) = doc.encoding {
+            encoding_writer.write_record([doc.id.as_str(), encoding])?;
+            converted += 1;
         }
+        summary.documents += 1;
+        summary.tokens_before_filtering += doc.tokens_before_filtering;
+        summary.tokens += doc.tokens.len();
+        summary.empty_documents += usize::from(doc.tokens.is_empty());
+
+        text_writer.serialize(&TextData {
+            index,
+            tokens: serde_json::to_string(&doc.tokens)?,
+        })?;
+        file_writer.serialize(FileData::new(index, doc))?;
     }
 
-    text_writer.flush()?;
+    compression::finish_csv(text_writer)?;
     file_writer.flush()?;
-    Ok(())
+    encoding_writer.flush()?;
+    if converted > 0 {
+        progress!("Converted {} non-UTF-8 files, listed in {}", converted, encodings_csv);
+    }
+    Ok(PreprocessingSummary { converted_files: converted, ..summary })
 }
 
-pub fn start(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    let tokens_csv = "tokens.csv";
-    let files_csv = "files.csv";
-    let stopwords_file = "../stopwords.txt";
+/// Reads the document paths from a files CSV, ordered by document index.
+pub fn load_file_paths(files_csv: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_path(files_csv)?;
+    let mut paths = Vec::new();
+    for result in rdr.records() {
+        let record = result?;
+        paths.push(record.get(1).unwrap_or_default().to_string());
+    }
+    Ok(paths)
+}
 
-    if Path::new(tokens_csv).exists() {
-        std::fs::remove_file(tokens_csv)?;
+/// Reads the document dates from a files CSV, ordered by document index. Documents
+/// without a date, or files.csv written before dates were recorded, give empty strings.
+pub fn load_file_dates(files_csv: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_path(files_csv)?;
+    let date_idx = rdr.headers()?.iter().position(|h| h == "date");
+    let mut dates = Vec::new();
+    for result in rdr.records() {
+        let record = result?;
+        dates.push(date_idx.and_then(|idx| record.get(idx)).unwrap_or_default().to_string());
     }
-    if Path::new(files_csv).exists() {
-        std::fs::remove_file(files_csv)?;
+    Ok(dates)
+}
+
+/// Tokenizes `paths` and appends them to existing tokens/files CSVs, numbering
+/// documents from `first_index`. Files that can't be read are reported and skipped.
+/// Returns the tokens of each appended document.
+pub fn append_files(paths: &[PathBuf], first_index: u32, output_path: &str, files_csv: &str, preprocessor: &Preprocessor) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    let mut text_writer = WriterBuilder::new().has_headers(false).from_writer(compression::append(output_path)?);
+    let mut file_writer = WriterBuilder::new().has_headers(false).from_writer(OpenOptions::new().append(true).open(files_csv)?);
+    let mut documents = Vec::new();
+
+    for path in paths {
+        let doc = match preprocessor.process_file(path, true) {
+            Ok(doc) => doc,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let index = first_index + documents.len() as u32;
+        text_writer.serialize(&TextData {
+            index,
+            tokens: serde_json::to_string(&doc.tokens)?,
+        })?;
+        documents.push(doc.tokens.clone());
+        file_writer.serialize(FileData::new(index, doc))?;
     }
 
+    compression::finish_csv(text_writer)?;
+    file_writer.flush()?;
+    Ok(documents)
+}
 
-    let stopwords = load_stopwords(stopwords_file)?;
-    process_files(path, tokens_csv, files_csv, &stopwords)?;
-    println!("Preprocessing completed for path: {}", path);
+/// Learns collocations over the whole of tokens.csv, rewrites it with them merged
+/// and saves them to `PHRASES_FILE` for documents processed later. Returns the
+/// number of phrases learned.
+fn merge_phrases(tokens_csv: &str, phrases_file: &str, config: &PhraseConfig) -> Result<usize, Box<dyn Error>> {
+    let documents = modeling::load_documents(tokens_csv)?;
+    let phrases = Phrases::learn(&documents, config);
+    phrases.save(phrases_file)?;
 
-    // Return an empty Vec<String> to match the expected type
-    Ok(Vec::new())
+    let mut text_writer = Writer::from_writer(compression::create(tokens_csv)?);
+    for (index, tokens) in (0u32..).zip(documents) {
+        text_writer.serialize(&TextData {
+            index,
+            tokens: serde_json::to_string(&phrases.apply(tokens))?,
+        })?;
+    }
+    compression::finish_csv(text_writer)?;
+    progress!("Merged {} phrases, listed in {}", phrases.len(), phrases_file);
+    Ok(phrases.len())
+}
+
+/// Tokenizes the documents at `path` (a directory, a sample index file, or a
+/// CSV/JSON Lines corpus file) into tokens.csv and files.csv. With the `remote`
+/// feature, `path` may also be an `s3://` or `https://` location, downloaded to
+/// the configured cache first.
+pub fn start(path: &str, config: &PreprocessConfig) -> Result<PreprocessingSummary, Box<dyn Error>> {
+    run(path, &Preprocessor::new(config)?, config)
+}
+
+/// Like `start`, with a caller-supplied preprocessor (e.g. a custom tokenizer).
+pub fn run(path: &str, preprocessor: &Preprocessor, config: &PreprocessConfig) -> Result<PreprocessingSummary, Box<dyn Error>> {
+    let workdir = &config.workdir;
+    let tokens_csv = workdir.path(&config.compression.path(TOKENS_FILE));
+    let files_csv = &workdir.path(FILES_FILE);
+    let encodings_csv = &workdir.path(ENCODINGS_FILE);
+
+    #[cfg(feature = "remote")]
+    let local = remote::localize(path, &config.remote_cache)?;
+    #[cfg(feature = "remote")]
+    let path = local.as_str();
+
+    compression::remove_variants(&workdir.path(TOKENS_FILE))?;
+    if Path::new(files_csv).exists() {
+        std::fs::remove_file(files_csv)?;
+    }
+
+    let mut summary = process_files(path, &tokens_csv, files_csv, encodings_csv, preprocessor, config)?;
+    if let Some(phrase_config) = &config.phrases {
+        summary.phrases = Some(merge_phrases(&tokens_csv, &workdir.path(PHRASES_FILE), phrase_config)?);
+    }
+    #[cfg(feature = "arrow")]
+    if config.arrow {
+        feather::write_tokens(&modeling::load_documents(&tokens_csv)?, &workdir.path(feather::TOKENS_ARROW))?;
+        feather::convert_csv(files_csv, &FILE_COLUMNS, &workdir.path(feather::FILES_ARROW))?;
+    }
+    console::artifact("tokens", &tokens_csv);
+    console::artifact("files", files_csv);
+    console::metric("documents", summary.documents);
+    console::metric("tokens", summary.tokens);
+    console::metric("empty_documents", summary.empty_documents);
+    progress!("Preprocessing completed for path: {}", path);
+    progress!("{} documents, {} of {} tokens kept, {} documents without tokens",
+        summary.documents, summary.tokens, summary.tokens_before_filtering, summary.empty_documents);
+    Ok(summary)
 }
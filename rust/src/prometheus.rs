@@ -0,0 +1,139 @@
+use crate::modeling::{NmfModel, ReconstructionError};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::{Pid, ProcessesToUpdate, System};
+
+/// Path the metrics are served on.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The last model fitted or loaded.
+#[derive(Clone, Copy)]
+struct FitState {
+    topics: usize,
+    terms: usize,
+    /// Documents and duration of the fit, unknown for a model loaded from disk
+    documents: Option<usize>,
+    duration: Option<Duration>,
+    error: Option<ReconstructionError>,
+}
+
+/// Counters and gauges of a long-running `serve` or `watch` process, scraped from
+/// `METRICS_PATH` in the Prometheus text format.
+pub struct PipelineMetrics {
+    started: SystemTime,
+    /// Documents preprocessed and projected onto the topics
+    documents: AtomicU64,
+    /// Inference requests answered by `serve`
+    requests: AtomicU64,
+    /// Models fitted, including refits
+    fits: AtomicU64,
+    fit: Mutex<Option<FitState>>,
+}
+
+impl Default for PipelineMetrics {
+    fn default() -> Self {
+        PipelineMetrics {
+            started: SystemTime::now(),
+            documents: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            fits: AtomicU64::new(0),
+            fit: Mutex::new(None),
+        }
+    }
+}
+
+impl PipelineMetrics {
+    pub fn add_documents(&self, count: usize) {
+        self.documents.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a model loaded from disk as the current one.
+    pub fn record_model(&self, model: &NmfModel) {
+        *self.fit.lock().unwrap() = Some(FitState { topics: model.h.nrows(), terms: model.vocab.len(), documents: None, duration: None, error: model.fit });
+    }
+
+    /// Records a model fitted on `documents` in `duration` as the current one.
+    pub fn record_fit(&self, model: &NmfModel, documents: usize, duration: Duration) {
+        self.fits.fetch_add(1, Ordering::Relaxed);
+        *self.fit.lock().unwrap() = Some(FitState { topics: model.h.nrows(), terms: model.vocab.len(), documents: Some(documents), duration: Some(duration), error: model.fit });
+    }
+
+    /// The metrics in the Prometheus text exposition format, with the process's
+    /// memory read at the time of the call.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+        };
+
+        metric("nmf_documents_processed_total", "counter", "Documents preprocessed and projected onto the topics.", self.documents.load(Ordering::Relaxed) as f64);
+        metric("nmf_requests_total", "counter", "Inference requests answered.", self.requests.load(Ordering::Relaxed) as f64);
+        metric("nmf_fits_total", "counter", "Models fitted, including refits.", self.fits.load(Ordering::Relaxed) as f64);
+        if let Some(fit) = *self.fit.lock().unwrap() {
+            metric("nmf_model_topics", "gauge", "Topics of the current model.", fit.topics as f64);
+            metric("nmf_model_terms", "gauge", "Vocabulary terms of the current model.", fit.terms as f64);
+            if let Some(documents) = fit.documents {
+                metric("nmf_model_documents", "gauge", "Documents the current model was fitted on.", documents as f64);
+            }
+            if let Some(duration) = fit.duration {
+                metric("nmf_fit_duration_seconds", "gauge", "Duration of the last fit.", duration.as_secs_f64());
+            }
+            if let Some(error) = fit.error {
+                metric("nmf_reconstruction_error", "gauge", "Frobenius norm of the current model's residual.", error.frobenius_error as f64);
+                metric("nmf_relative_error", "gauge", "Squared residual of the current model relative to the data.", error.relative_error as f64);
+                metric("nmf_explained_variance", "gauge", "Share of the data the current model reproduces.", error.explained_variance as f64);
+                metric("nmf_iterations", "gauge", "Iterations the current model's factorization ran for.", error.iterations as f64);
+            }
+        }
+
+        let pid = Pid::from(std::process::id() as usize);
+        let mut system = System::new();
+        system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+        if let Some(process) = system.process(pid) {
+            metric("process_resident_memory_bytes", "gauge", "Resident memory size in bytes.", process.memory() as f64);
+            metric("process_virtual_memory_bytes", "gauge", "Virtual memory size in bytes.", process.virtual_memory() as f64);
+        }
+        let started = self.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        metric("process_start_time_seconds", "gauge", "Start time of the process since the Unix epoch in seconds.", started.as_secs_f64());
+        out
+    }
+}
+
+async fn scrape(State(metrics): State<Arc<PipelineMetrics>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.render())
+}
+
+/// A router serving `metrics` on `METRICS_PATH`, to merge into another or serve alone.
+pub fn router<S: Clone + Send + Sync + 'static>(metrics: Arc<PipelineMetrics>) -> Router<S> {
+    Router::new().route(METRICS_PATH, get(scrape)).with_state(metrics)
+}
+
+/// Serves `metrics` on `addr` from a background thread for the rest of the process.
+pub fn spawn(metrics: Arc<PipelineMetrics>, addr: &str) -> Result<(), Box<dyn Error>> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build()?;
+    let listener = runtime.block_on(tokio::net::TcpListener::bind(addr))?;
+    progress!("Serving metrics on http://{}{}", addr, METRICS_PATH);
+    std::thread::spawn(move || {
+        runtime.block_on(async {
+            if let Err(e) = axum::serve(listener, router(metrics)).await {
+                eprintln!("Metrics endpoint failed: {}", e);
+            }
+        })
+    });
+    Ok(())
+}
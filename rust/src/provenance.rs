@@ -0,0 +1,104 @@
+use crate::{logical_cores, sample_path, DATASETS, MIB};
+use preproccess::preprocessing;
+use preproccess::readers;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::Path;
+use sysinfo::System;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of a file's contents.
+pub fn file_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Document count and content hash of a dataset. A directory or index file hashes
+/// the names and contents of its documents in sorted order, so the hash doesn't
+/// depend on the directory walk order.
+fn dataset_hash(input: &str) -> Result<(usize, String), Box<dyn Error>> {
+    let path = Path::new(input);
+    if readers::is_corpus_file(path) {
+        return Ok((1, file_hash(path)?));
+    }
+    let mut files = preprocessing::input_files(input)?;
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in &files {
+        let name = file.strip_prefix(path).unwrap_or(file);
+        hasher.update(name.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(file_hash(file).map_err(|e| format!("{}: {}", file.display(), e))?.as_bytes());
+    }
+    Ok((files.len(), hex(&hasher.finalize())))
+}
+
+/// Path, file count and content hash of every benchmark dataset of the given sizes.
+pub fn datasets(samples: &[usize]) -> serde_json::Value {
+    let mut entries = Vec::new();
+    for &sample in samples {
+        for dataset in 1..=DATASETS {
+            let path = sample_path(sample, dataset);
+            let entry = match dataset_hash(&path) {
+                Ok((files, sha256)) => serde_json::json!({"sample": sample, "dataset": dataset, "path": path, "files": files, "sha256": sha256}),
+                Err(e) => serde_json::json!({"sample": sample, "dataset": dataset, "path": path, "error": e.to_string()}),
+            };
+            entries.push(entry);
+        }
+    }
+    serde_json::Value::Array(entries)
+}
+
+/// Hashes of further input files (stopword lists, vocabulary, dates), `null` for
+/// those that don't exist (yet).
+pub fn files<'a>(paths: impl IntoIterator<Item = &'a str>) -> serde_json::Value {
+    paths
+        .into_iter()
+        .map(|path| (path.to_string(), file_hash(Path::new(path)).ok().into()))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Crate version, the git commit it was built from and the enabled features.
+pub fn build() -> serde_json::Value {
+    let features: Vec<&str> = [
+        ("arrow", cfg!(feature = "arrow")),
+        ("perf", cfg!(feature = "perf")),
+        ("pos", cfg!(feature = "pos")),
+        ("remote", cfg!(feature = "remote")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("tui", cfg!(feature = "tui")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    serde_json::json!({
+        "crate": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": option_env!("GIT_COMMIT"),
+        "git_dirty": option_env!("GIT_DIRTY").map(|dirty| dirty == "true"),
+        "profile": if cfg!(debug_assertions) { "debug" } else { "release" },
+        "features": features,
+    })
+}
+
+/// Host name, operating system, CPU model and core counts, and total memory.
+pub fn host() -> serde_json::Value {
+    let mut system = System::new();
+    system.refresh_cpu_all();
+    system.refresh_memory();
+    serde_json::json!({
+        "hostname": System::host_name(),
+        "os": System::long_os_version(),
+        "kernel": System::kernel_version(),
+        "arch": System::cpu_arch(),
+        "cpu": system.cpus().first().map(|cpu| cpu.brand().trim().to_string()),
+        "physical_cores": system.physical_core_count(),
+        "logical_cores": logical_cores(),
+        "memory_mib": system.total_memory() as f64 / MIB,
+    })
+}
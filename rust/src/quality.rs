@@ -0,0 +1,88 @@
+use crate::validate::top_terms;
+use crate::vocabulary::Vocabulary;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Per-run topic quality written by the benchmark
+pub const TOPIC_QUALITY_FILE: &str = "topic_quality.csv";
+/// Top words per topic the quality metrics are computed over
+pub const QUALITY_TOP_WORDS: usize = 10;
+/// Overlap above which two topics are reported as near duplicates
+pub const REDUNDANT_OVERLAP: f32 = 0.5;
+
+/// Diversity and redundancy of a model's topics, judged by their top words.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicQuality {
+    /// Share of unique words among the top words of all topics: 1 when no two topics
+    /// share a top word, 1/k when all topics have the same ones
+    pub diversity: f32,
+    /// Mean Jaccard overlap of the top words over all pairs of topics
+    pub mean_overlap: f32,
+    /// Overlap of the most similar pair of topics
+    pub max_overlap: f32,
+    /// The most similar pair of topics, if there are at least two
+    pub most_similar: Option<(usize, usize)>,
+}
+
+/// Computes topic diversity and pairwise overlap over each topic's `top` highest
+/// weighted words.
+pub fn topic_quality(h: &Array2<f32>, vocab: &Vocabulary, top: usize) -> TopicQuality {
+    let terms = vocab.terms();
+    let top_words: Vec<HashSet<&str>> = h.rows().into_iter().map(|row| top_terms(row, &terms, top)).collect();
+
+    let total: usize = top_words.iter().map(HashSet::len).sum();
+    let unique: HashSet<&str> = top_words.iter().flatten().copied().collect();
+    let diversity = if total == 0 { 0.0 } else { unique.len() as f32 / total as f32 };
+
+    let mut overlaps = Vec::new();
+    let mut most_similar = None;
+    let mut max_overlap = 0.0;
+    for a in 0..top_words.len() {
+        for b in a + 1..top_words.len() {
+            let overlap = top_words[a].intersection(&top_words[b]).count() as f32 / top_words[a].union(&top_words[b]).count().max(1) as f32;
+            if most_similar.is_none() || overlap > max_overlap {
+                max_overlap = overlap;
+                most_similar = Some((a, b));
+            }
+            overlaps.push(overlap);
+        }
+    }
+    let mean_overlap = if overlaps.is_empty() { 0.0 } else { overlaps.iter().sum::<f32>() / overlaps.len() as f32 };
+    TopicQuality { diversity, mean_overlap, max_overlap, most_similar }
+}
+
+/// Mean normalized pointwise mutual information of each topic's `top` word pairs,
+/// from their co-occurrence in `documents`: 1 when the words always appear together,
+/// 0 when independent, -1 when never together. Scoring on documents the model wasn't
+/// fit on rewards topics that generalize rather than memorize.
+pub fn npmi_coherence(h: &Array2<f32>, vocab: &Vocabulary, documents: &[Vec<String>], top: usize) -> f32 {
+    let terms = vocab.terms();
+    let top_words: Vec<Vec<&str>> = h.rows().into_iter().map(|row| top_terms(row, &terms, top).into_iter().collect()).collect();
+    let wanted: HashSet<&str> = top_words.iter().flatten().copied().collect();
+    // The wanted words of each document
+    let occurrences: Vec<HashSet<&str>> = documents
+        .iter()
+        .map(|doc| doc.iter().map(String::as_str).filter(|token| wanted.contains(token)).collect())
+        .collect();
+    let num_docs = documents.len().max(1) as f32;
+    let share = |words: &[&str]| occurrences.iter().filter(|doc| words.iter().all(|word| doc.contains(word))).count() as f32 / num_docs;
+
+    let mut scores = Vec::new();
+    for words in &top_words {
+        for (i, a) in words.iter().enumerate() {
+            for b in &words[i + 1..] {
+                let joint = share(&[a, b]);
+                let npmi = if joint == 0.0 {
+                    -1.0
+                } else if joint >= 1.0 {
+                    1.0
+                } else {
+                    (joint / (share(&[a]) * share(&[b]))).ln() / -joint.ln()
+                };
+                scores.push(npmi);
+            }
+        }
+    }
+    if scores.is_empty() { 0.0 } else { scores.iter().sum::<f32>() / scores.len() as f32 }
+}
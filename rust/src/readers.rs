@@ -0,0 +1,150 @@
+use crate::compression;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::error::Error;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+const DOCUMENT_EXTENSIONS: [&str; 2] = ["txt", "docx"];
+const CORPUS_EXTENSIONS: [&str; 3] = ["csv", "jsonl", "ndjson"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    compression::inner_extension(path).is_some_and(|ext| extensions.contains(&ext.as_str()))
+}
+
+/// Whether `path` is a file in one of the formats `read_document` understands,
+/// optionally gzip or zstd compressed.
+pub fn is_document(path: &Path) -> bool {
+    path.is_file() && has_extension(path, &DOCUMENT_EXTENSIONS)
+}
+
+/// Reads a document as UTF-8 text, decompressing .gz/.zst files. Plain text files not valid as UTF-8 are
+/// converted from their detected encoding; the source encoding name is
+/// returned when that happened.
+pub fn read_document(path: &Path) -> Result<(String, Option<&'static str>), Box<dyn Error>> {
+    if has_extension(path, &["docx"]) {
+        return Ok((read_docx(path)?, None));
+    }
+
+    let mut bytes = Vec::new();
+    compression::open(path)?.read_to_end(&mut bytes)?;
+
+    if let Some((encoding, _)) = Encoding::for_bom(&bytes) {
+        let (text, _, _) = encoding.decode(&bytes);
+        let converted = (encoding != UTF_8).then(|| encoding.name());
+        return Ok((text.into_owned(), converted));
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok((text, None)),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            let mut detector = EncodingDetector::new();
+            detector.feed(&bytes, true);
+            let encoding = detector.guess(None, true);
+            // Undecodable sequences become U+FFFD rather than aborting the run
+            let (text, _, _) = encoding.decode(&bytes);
+            Ok((text.into_owned(), Some(encoding.name())))
+        }
+    }
+}
+
+/// Extracts the body text of a .docx file from its `word/document.xml` part,
+/// one line per paragraph.
+fn read_docx(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    compression::open(path)?.read_to_end(&mut bytes)?;
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut xml = String::new();
+    archive.by_name("word/document.xml")?.read_to_string(&mut xml)?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text_run = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"w:t" => in_text_run = true,
+            Event::End(e) => match e.name().as_ref() {
+                b"w:t" => in_text_run = false,
+                b"w:p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Empty(e) if matches!(e.name().as_ref(), b"w:tab" | b"w:br") => text.push(' '),
+            Event::Text(t) if in_text_run => text.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Whether `path` is a single-file corpus holding one document per row.
+pub fn is_corpus_file(path: &Path) -> bool {
+    path.is_file() && has_extension(path, &CORPUS_EXTENSIONS)
+}
+
+/// A lazily read `(id, text)` row of a corpus file.
+pub type CorpusRows = Box<dyn Iterator<Item = Result<(String, String), Box<dyn Error>>>>;
+
+/// Reads `(id, text)` pairs from a CSV file with a header row or from a JSON
+/// Lines file. Ids come from `id_column`, or are the zero-based row number.
+pub fn read_corpus_rows(path: &Path, text_column: &str, id_column: Option<&str>) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    corpus_rows(path, text_column, id_column)?.collect()
+}
+
+/// Like `read_corpus_rows`, yielding the rows while the file is read rather than
+/// collecting them, for corpora too large to hold in memory.
+pub fn corpus_rows(path: &Path, text_column: &str, id_column: Option<&str>) -> Result<CorpusRows, Box<dyn Error>> {
+    if has_extension(path, &["csv"]) {
+        csv_rows(path, text_column, id_column)
+    } else {
+        jsonl_rows(path, text_column, id_column)
+    }
+}
+
+fn csv_rows(path: &Path, text_column: &str, id_column: Option<&str>) -> Result<CorpusRows, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_reader(compression::open(path)?);
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str| headers.iter()
+        .position(|h| h == name)
+        .ok_or_else(|| format!("Column '{}' not found in {}", name, path.display()));
+    let text_idx = column(text_column)?;
+    let id_idx = id_column.map(column).transpose()?;
+
+    Ok(Box::new(rdr.into_records().enumerate().map(move |(row, result)| {
+        let record = result?;
+        let id = match id_idx {
+            Some(idx) => record.get(idx).unwrap_or_default().to_string(),
+            None => row.to_string(),
+        };
+        Ok((id, record.get(text_idx).unwrap_or_default().to_string()))
+    })))
+}
+
+fn jsonl_rows(path: &Path, text_column: &str, id_column: Option<&str>) -> Result<CorpusRows, Box<dyn Error>> {
+    let reader = BufReader::new(compression::open(path)?);
+    let (path, text_column, id_column) = (path.to_path_buf(), text_column.to_string(), id_column.map(str::to_string));
+    Ok(Box::new(reader.lines().enumerate().filter_map(move |(row, line)| {
+        let row = (|| -> Result<Option<(String, String)>, Box<dyn Error>> {
+            let line = line?;
+            if line.trim().is_empty() {
+                return Ok(None);
+            }
+            let value: Value = serde_json::from_str(&line)?;
+            let text = value.get(&text_column)
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("Line {} of {} has no string field '{}'", row + 1, path.display(), text_column))?;
+            let id = match id_column.as_deref().and_then(|field| value.get(field)) {
+                Some(Value::String(id)) => id.clone(),
+                Some(other) => other.to_string(),
+                None => row.to_string(),
+            };
+            Ok(Some((id, text.to_string())))
+        })();
+        row.transpose()
+    })))
+}
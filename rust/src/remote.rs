@@ -0,0 +1,319 @@
+use crate::readers;
+use hmac::{Hmac, Mac};
+use quick_xml::events::Event;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Directory remote corpora are downloaded to when none is configured
+pub const DEFAULT_CACHE_DIR: &str = "remote_cache";
+
+const AWS_REGION: &str = "us-east-1";
+
+/// True for the `s3://`, `https://` and `http://` paths `localize` downloads.
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Resolves an input path to a local one, downloading remote inputs into `cache_dir`:
+/// an object or URL becomes a cached file, an `s3://bucket/prefix/` (ending in a slash)
+/// a cached directory of every object under the prefix, and an index file listing
+/// remote documents a copy listing their cached paths. Files already in the cache are
+/// reused, so a corpus is only downloaded once. Local paths are returned unchanged.
+pub fn localize(path: &str, cache_dir: &str) -> Result<String, Box<dyn Error>> {
+    let cache = Path::new(cache_dir);
+    let local = if let Some(location) = path.strip_prefix("s3://") {
+        let (bucket, key) = location.split_once('/').unwrap_or((location, ""));
+        let s3 = S3::from_env(bucket);
+        if key.is_empty() || key.ends_with('/') {
+            s3.sync_prefix(key, cache)?
+        } else {
+            s3.fetch(key, cache)?
+        }
+    } else if is_remote(path) {
+        fetch_url(path, cache)?
+    } else {
+        PathBuf::from(path)
+    };
+
+    if local.is_file() && !readers::is_corpus_file(&local) && !readers::is_document(&local) {
+        return localize_index(&local, cache);
+    }
+    Ok(local.to_string_lossy().into_owned())
+}
+
+/// Rewrites an index file whose lines include remote documents to list their cached
+/// copies instead.
+fn localize_index(index: &Path, cache: &Path) -> Result<String, Box<dyn Error>> {
+    let lines: Vec<String> = BufReader::new(File::open(index)?).lines().collect::<Result<_, _>>()?;
+    if !lines.iter().any(|line| is_remote(line.trim())) {
+        return Ok(index.to_string_lossy().into_owned());
+    }
+    let mut local = Vec::with_capacity(lines.len());
+    for line in lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        local.push(if is_remote(line) { localize(line, &cache.to_string_lossy())? } else { line.to_string() });
+    }
+    let name = index.file_name().map_or("index".into(), |name| name.to_string_lossy());
+    let path = cache.join(format!("{}.local", name));
+    fs::create_dir_all(cache)?;
+    fs::write(&path, local.join("\n") + "\n")?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Cache location of a URL: `<cache>/<host>/<path>`.
+fn url_cache_path(url: &str, cache: &Path) -> PathBuf {
+    let location = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let location = location.split(['?', '#']).next().unwrap_or(location);
+    let mut path = cache.to_path_buf();
+    path.extend(location.split('/').filter(|part| !part.is_empty() && *part != "." && *part != ".."));
+    path
+}
+
+fn fetch_url(url: &str, cache: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let path = url_cache_path(url, cache);
+    if !path.is_file() {
+        progress!("Downloading {}...", url);
+        download(ureq::get(url), url, &path)?;
+    }
+    Ok(path)
+}
+
+/// Streams a response body to `path`, writing a partial file first so an interrupted
+/// download is never mistaken for a cached one.
+fn download(request: ureq::Request, source: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let response = request.call().map_err(|e| describe(e, source))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut file = File::create(&partial)?;
+    io::copy(&mut response.into_reader(), &mut file)?;
+    file.flush()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn describe(error: ureq::Error, source: &str) -> Box<dyn Error> {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            format!("downloading {} failed with HTTP {}: {}", source, status, body.trim()).into()
+        }
+        other => format!("downloading {} failed: {}", source, other).into(),
+    }
+}
+
+/// An object listed under an S3 prefix.
+struct S3Object {
+    key: String,
+    size: u64,
+}
+
+/// An S3 bucket, reached at `AWS_ENDPOINT_URL` (path-style, e.g. for MinIO) or its
+/// virtual-hosted AWS endpoint in `AWS_REGION`. Requests are signed with AWS Signature
+/// Version 4 when `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` are set, and are
+/// anonymous otherwise, which suits public buckets.
+struct S3 {
+    bucket: String,
+    region: String,
+    endpoint: Option<String>,
+    credentials: Option<Credentials>,
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3 {
+    fn from_env(bucket: &str) -> S3 {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let credentials = match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key), Some(secret_key)) => Some(Credentials { access_key, secret_key, session_token: var("AWS_SESSION_TOKEN") }),
+            _ => None,
+        };
+        S3 {
+            bucket: bucket.to_string(),
+            region: var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")).unwrap_or_else(|| AWS_REGION.to_string()),
+            endpoint: var("AWS_ENDPOINT_URL").map(|endpoint| endpoint.trim_end_matches('/').to_string()),
+            credentials,
+        }
+    }
+
+    /// Base URL and the path prefix object keys are appended to.
+    fn base(&self) -> (String, String) {
+        match &self.endpoint {
+            Some(endpoint) => (endpoint.clone(), format!("/{}", self.bucket)),
+            None => (format!("https://{}.s3.{}.amazonaws.com", self.bucket, self.region), String::new()),
+        }
+    }
+
+    /// A GET request for `key` (empty for the bucket itself) with the given query.
+    fn get(&self, key: &str, query: &[(&str, &str)]) -> ureq::Request {
+        let (base, prefix) = self.base();
+        let path = format!("{}/{}", prefix, uri_encode(key, false));
+        let mut query: Vec<(String, String)> = query.iter().map(|(name, value)| (uri_encode(name, true), uri_encode(value, true))).collect();
+        query.sort();
+        let query = query.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&");
+        let url = if query.is_empty() { format!("{}{}", base, path) } else { format!("{}{}?{}", base, path, query) };
+
+        let request = ureq::get(&url);
+        match &self.credentials {
+            Some(credentials) => {
+                let host = base.split_once("://").map_or(base.as_str(), |(_, host)| host);
+                sign(request, credentials, &self.region, host, &path, &query)
+            }
+            None => request,
+        }
+    }
+
+    fn cache_path(&self, key: &str, cache: &Path) -> PathBuf {
+        let mut path = cache.join("s3").join(&self.bucket);
+        path.extend(key.split('/').filter(|part| !part.is_empty() && *part != "." && *part != ".."));
+        path
+    }
+
+    fn fetch(&self, key: &str, cache: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.cache_path(key, cache);
+        if !path.is_file() {
+            let source = format!("s3://{}/{}", self.bucket, key);
+            progress!("Downloading {}...", source);
+            download(self.get(key, &[]), &source, &path)?;
+        }
+        Ok(path)
+    }
+
+    /// Downloads every object under `prefix` not already cached with the same size, and
+    /// returns the cached directory.
+    fn sync_prefix(&self, prefix: &str, cache: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let objects = self.list(prefix)?;
+        let mut downloaded = 0;
+        for object in objects.iter().filter(|object| !object.key.ends_with('/')) {
+            let path = self.cache_path(&object.key, cache);
+            if fs::metadata(&path).is_ok_and(|meta| meta.len() == object.size) {
+                continue;
+            }
+            download(self.get(&object.key, &[]), &format!("s3://{}/{}", self.bucket, object.key), &path)?;
+            downloaded += 1;
+        }
+        progress!("s3://{}/{}: {} objects, {} downloaded to the cache", self.bucket, prefix, objects.len(), downloaded);
+        let dir = self.cache_path(prefix, cache);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Lists the objects under `prefix` with ListObjectsV2, following continuation tokens.
+    fn list(&self, prefix: &str) -> Result<Vec<S3Object>, Box<dyn Error>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let source = format!("s3://{}/{}", self.bucket, prefix);
+            let mut body = String::new();
+            self.get("", &query).call().map_err(|e| describe(e, &source))?.into_reader().read_to_string(&mut body)?;
+            let page = parse_listing(&body)?;
+            objects.extend(page.objects);
+            match page.next_token {
+                Some(next) if page.truncated => token = Some(next),
+                _ => return Ok(objects),
+            }
+        }
+    }
+}
+
+struct Listing {
+    objects: Vec<S3Object>,
+    truncated: bool,
+    next_token: Option<String>,
+}
+
+fn parse_listing(xml: &str) -> Result<Listing, Box<dyn Error>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut listing = Listing { objects: Vec::new(), truncated: false, next_token: None };
+    let mut element = String::new();
+    let mut key = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => element = String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            Event::End(end) => {
+                if end.local_name().as_ref() == b"Contents" {
+                    key.clear();
+                }
+                element.clear();
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                match element.as_str() {
+                    "Key" => key = text.into_owned(),
+                    "Size" => listing.objects.push(S3Object { key: key.clone(), size: text.trim().parse()? }),
+                    "IsTruncated" => listing.truncated = text.trim() == "true",
+                    "NextContinuationToken" => listing.next_token = Some(text.into_owned()),
+                    _ => {}
+                }
+            }
+            Event::Eof => return Ok(listing),
+            _ => {}
+        }
+    }
+}
+
+/// Percent-encodes as SigV4 requires: everything but unreserved characters, and `/`
+/// too unless encoding an object key path.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Adds AWS Signature Version 4 headers to a GET request without a body.
+fn sign(request: ureq::Request, credentials: &Credentials, region: &str, host: &str, path: &str, query: &str) -> ureq::Request {
+    let now = time::OffsetDateTime::now_utc();
+    let date = format!("{:04}{:02}{:02}", now.year(), now.month() as u8, now.day());
+    let timestamp = format!("{}T{:02}{:02}{:02}Z", date, now.hour(), now.minute(), now.second());
+    let payload = "UNSIGNED-PAYLOAD";
+
+    let mut headers = vec![("host", host.to_string()), ("x-amz-content-sha256", payload.to_string()), ("x-amz-date", timestamp.clone())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_request = format!("GET\n{}\n{}\n{}\n{}\n{}", path, query, canonical_headers, signed_headers, payload);
+
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+    let key = ["s3", "aws4_request"].iter().fold(
+        hmac(&hmac(format!("AWS4{}", credentials.secret_key).as_bytes(), &date), region),
+        |key, part| hmac(&key, part),
+    );
+    let signature = hex(&hmac(&key, &string_to_sign));
+
+    let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", credentials.access_key, scope, signed_headers, signature);
+    headers.into_iter()
+        .filter(|(name, _)| *name != "host")
+        .fold(request, |request, (name, value)| request.set(name, &value))
+        .set("Authorization", &authorization)
+}
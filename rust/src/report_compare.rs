@@ -0,0 +1,293 @@
+use crate::compression;
+use crate::console;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Columns a metrics file's values are read from, and how its step names and units map
+/// onto this crate's. The defaults are this crate's N{sample}_metrics.csv columns.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnMapping {
+    /// Sample size; taken from "N<size>" in the file name when the file has no such column
+    pub sample: String,
+    /// Bootstrap iteration; 0 when the file has no such column
+    pub iteration: String,
+    /// Dataset within the sample size; 0 when the file has no such column
+    pub dataset: String,
+    /// Topic count, only matched when both implementations record it
+    pub k: String,
+    pub step: String,
+    /// Duration of the step, in seconds after `time_scale`
+    pub time: String,
+    /// Memory of the step, in MiB after `memory_scale`; optional
+    pub memory: String,
+    /// Factor turning the time values into seconds, e.g. 0.001 for milliseconds
+    pub time_scale: f64,
+    /// Factor turning the memory values into MiB, e.g. 1 / 1048576 for bytes
+    pub memory_scale: f64,
+    /// Step names replaced by this crate's, e.g. "Preprocessing" = "preprocessing";
+    /// names are otherwise compared ignoring case
+    pub steps: HashMap<String, String>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            sample: "Sample".to_string(),
+            iteration: "Iteration".to_string(),
+            dataset: "Dataset".to_string(),
+            k: "K".to_string(),
+            step: "Step".to_string(),
+            time: "Time (s)".to_string(),
+            memory: "RSS Peak (MiB)".to_string(),
+            time_scale: 1.0,
+            memory_scale: 1.0,
+            steps: HashMap::new(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    pub fn load(path: &str) -> Result<ColumnMapping, Box<dyn Error>> {
+        toml::from_str(&std::fs::read_to_string(path)?).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+/// Where a step's measurements line up across the implementations.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct StepKey {
+    sample: usize,
+    k: Option<usize>,
+    iteration: usize,
+    dataset: usize,
+    step: String,
+}
+
+/// Mean time and memory of the rows sharing a `StepKey`.
+#[derive(Debug, Clone, Copy, Default)]
+struct Reading {
+    time: f64,
+    memory: Option<f64>,
+}
+
+/// Sample size from "N100" or "N_100" in a file or directory name.
+fn sample_from_name(path: &Path) -> Option<usize> {
+    let re = Regex::new(r"N_?(\d+)").unwrap();
+    path.components().rev().find_map(|part| re.captures(&part.as_os_str().to_string_lossy())?[1].parse().ok())
+}
+
+/// The metrics files at `path`: the file itself, or the files in the directory whose
+/// names `pattern` matches, sorted.
+fn metrics_files(path: &str, pattern: &Regex) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|file| pattern.is_match(&file.file_name().unwrap_or_default().to_string_lossy()))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("{} has no metrics files", path.display()).into());
+    }
+    Ok(files)
+}
+
+/// Reads the steps of the metrics files with `mapping`, averaging rows that share a
+/// key. Rows without a time, such as failed steps, are skipped.
+fn read_metrics(files: &[PathBuf], mapping: &ColumnMapping) -> Result<BTreeMap<StepKey, Reading>, Box<dyn Error>> {
+    let steps: HashMap<String, String> = mapping.steps.iter().map(|(from, to)| (from.to_lowercase(), to.to_lowercase())).collect();
+    let mut sums: BTreeMap<StepKey, (f64, f64, usize, usize)> = BTreeMap::new();
+    for file in files {
+        let mut reader = csv::Reader::from_reader(compression::open(file)?);
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|header| header == name);
+        let required = |name: &str| column(name).ok_or_else(|| format!("{} has no column '{}'", file.display(), name));
+        let (step_col, time_col) = (required(&mapping.step)?, required(&mapping.time)?);
+        let (sample_col, iteration_col, dataset_col, k_col, memory_col) =
+            (column(&mapping.sample), column(&mapping.iteration), column(&mapping.dataset), column(&mapping.k), column(&mapping.memory));
+        let file_sample = sample_from_name(file);
+        if sample_col.is_none() && file_sample.is_none() {
+            return Err(format!("{} has no column '{}' and no N<size> in its name", file.display(), mapping.sample).into());
+        }
+
+        for record in reader.records() {
+            let record = record?;
+            let field = |col: Option<usize>| col.and_then(|col| record.get(col)).map(str::trim).filter(|value| !value.is_empty());
+            let number = |col: Option<usize>| field(col).and_then(|value| value.parse::<f64>().ok());
+            let Some(time) = number(Some(time_col)) else { continue };
+            let step = field(Some(step_col)).unwrap_or_default().to_lowercase();
+            let key = StepKey {
+                sample: number(sample_col).map(|sample| sample as usize).or(file_sample).unwrap_or_default(),
+                k: number(k_col).map(|k| k as usize),
+                iteration: number(iteration_col).map_or(0, |iteration| iteration as usize),
+                dataset: number(dataset_col).map_or(0, |dataset| dataset as usize),
+                step: steps.get(&step).cloned().unwrap_or(step),
+            };
+            let sum = sums.entry(key).or_default();
+            sum.0 += time * mapping.time_scale;
+            sum.2 += 1;
+            if let Some(memory) = number(memory_col) {
+                sum.1 += memory * mapping.memory_scale;
+                sum.3 += 1;
+            }
+        }
+    }
+    Ok(sums
+        .into_iter()
+        .map(|(key, (time, memory, rows, memory_rows))| {
+            (key, Reading { time: time / rows as f64, memory: (memory_rows > 0).then(|| memory / memory_rows as f64) })
+        })
+        .collect())
+}
+
+/// Drops the topic counts from the keys, for when only one side records them.
+fn without_k(readings: BTreeMap<StepKey, Reading>) -> BTreeMap<StepKey, Reading> {
+    readings.into_iter().map(|(key, reading)| (StepKey { k: None, ..key }, reading)).collect()
+}
+
+fn geometric_mean(values: &[f64]) -> f64 {
+    (values.iter().map(|value| value.ln()).sum::<f64>() / values.len() as f64).exp()
+}
+
+/// Speedup and memory ratios of one step over the matched runs.
+#[derive(Default)]
+struct StepSummary {
+    pairs: usize,
+    rust_time: f64,
+    other_time: f64,
+    speedups: Vec<f64>,
+    rust_memory: Vec<f64>,
+    other_memory: Vec<f64>,
+}
+
+impl StepSummary {
+    fn add(&mut self, rust: Reading, other: Reading) {
+        self.pairs += 1;
+        self.rust_time += rust.time;
+        self.other_time += other.time;
+        if rust.time > 0.0 && other.time > 0.0 {
+            self.speedups.push(other.time / rust.time);
+        }
+        if let (Some(rust), Some(other)) = (rust.memory, other.memory) {
+            self.rust_memory.push(rust);
+            self.other_memory.push(other);
+        }
+    }
+
+    /// Ratio of the other implementation's total time to this crate's, above 1 when
+    /// this crate is faster.
+    fn speedup(&self) -> f64 {
+        self.other_time / self.rust_time
+    }
+
+    fn memory_ratio(&self) -> Option<f64> {
+        (!self.rust_memory.is_empty()).then(|| self.other_memory.iter().sum::<f64>() / self.rust_memory.iter().sum::<f64>())
+    }
+
+    fn row(&self, sample: &str, step: &str) -> Vec<String> {
+        let mean = |values: &[f64]| (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64);
+        let optional = |value: Option<f64>| value.map_or_else(String::new, |value| value.to_string());
+        vec![
+            sample.to_string(),
+            step.to_string(),
+            self.pairs.to_string(),
+            (self.rust_time / self.pairs as f64).to_string(),
+            (self.other_time / self.pairs as f64).to_string(),
+            self.speedup().to_string(),
+            optional((!self.speedups.is_empty()).then(|| geometric_mean(&self.speedups))),
+            optional(mean(&self.rust_memory)),
+            optional(mean(&self.other_memory)),
+            optional(self.memory_ratio()),
+        ]
+    }
+}
+
+/// Aligns the steps of this crate's metrics at `rust` (a run directory of
+/// N{sample}_metrics.csv files, or one such file) with those of another
+/// implementation at `other` (a CSV file or a directory of them), read with `mapping`,
+/// by sample size, topic count, iteration, dataset and step. Writes each matched
+/// step's times and memory side by side to `output`, and the speedup and memory ratio
+/// of each step per sample size and over all of them to `summary`. Ratios are the
+/// other implementation's over this crate's, so above 1 where this crate does better.
+pub fn run(rust: &str, other: &str, mapping: &ColumnMapping, label: &str, output: &str, summary: &str) -> Result<(), Box<dyn Error>> {
+    let rust_files = metrics_files(rust, &Regex::new(r"^N\d+_metrics\.csv(\.gz|\.zst)?$").unwrap())?;
+    let other_files = metrics_files(other, &Regex::new(r"\.csv(\.gz|\.zst)?$").unwrap())?;
+    let mut rust_steps = read_metrics(&rust_files, &ColumnMapping::default())?;
+    let mut other_steps = read_metrics(&other_files, mapping)?;
+    progress!("Read {} steps from {} Rust metrics files and {} from {} {} files",
+        rust_steps.len(), rust_files.len(), other_steps.len(), other_files.len(), label);
+    if other_steps.keys().all(|key| key.k.is_none()) || rust_steps.keys().all(|key| key.k.is_none()) {
+        rust_steps = without_k(rust_steps);
+        other_steps = without_k(other_steps);
+    }
+
+    let mut writer = csv::Writer::from_path(output)?;
+    writer.write_record([
+        "Sample".to_string(), "K".to_string(), "Iteration".to_string(), "Dataset".to_string(), "Step".to_string(),
+        "Rust Time (s)".to_string(), format!("{} Time (s)", label), "Speedup".to_string(),
+        "Rust Memory (MiB)".to_string(), format!("{} Memory (MiB)", label), "Memory Ratio".to_string(),
+    ])?;
+    let mut per_sample: BTreeMap<(usize, String), StepSummary> = BTreeMap::new();
+    let mut overall: BTreeMap<String, StepSummary> = BTreeMap::new();
+    for (key, rust) in &rust_steps {
+        let Some(&other) = other_steps.get(key) else { continue };
+        let optional = |value: Option<f64>| value.map_or_else(String::new, |value| value.to_string());
+        let memory_ratio = rust.memory.zip(other.memory).map(|(rust, other)| other / rust);
+        writer.write_record([
+            key.sample.to_string(),
+            optional(key.k.map(|k| k as f64)),
+            key.iteration.to_string(),
+            key.dataset.to_string(),
+            key.step.clone(),
+            rust.time.to_string(),
+            other.time.to_string(),
+            (other.time / rust.time).to_string(),
+            optional(rust.memory),
+            optional(other.memory),
+            optional(memory_ratio),
+        ])?;
+        per_sample.entry((key.sample, key.step.clone())).or_default().add(*rust, other);
+        overall.entry(key.step.clone()).or_default().add(*rust, other);
+    }
+    writer.flush()?;
+
+    let matched: usize = overall.values().map(|step| step.pairs).sum();
+    if matched == 0 {
+        return Err(format!("No steps matched between {} and {}; check the column mapping and step names", rust, other).into());
+    }
+    let unmatched_rust = rust_steps.len() - matched;
+    let unmatched_other = other_steps.len() - matched;
+    if unmatched_rust + unmatched_other > 0 {
+        progress!("{} Rust and {} {} steps have no counterpart and are left out", unmatched_rust, unmatched_other, label);
+    }
+
+    let mut summary_writer = csv::Writer::from_path(summary)?;
+    summary_writer.write_record([
+        "Sample".to_string(), "Step".to_string(), "Pairs".to_string(),
+        "Rust Time Mean (s)".to_string(), format!("{} Time Mean (s)", label), "Speedup".to_string(), "Speedup Geomean".to_string(),
+        "Rust Memory Mean (MiB)".to_string(), format!("{} Memory Mean (MiB)", label), "Memory Ratio".to_string(),
+    ])?;
+    for ((sample, step), summary) in &per_sample {
+        summary_writer.write_record(summary.row(&sample.to_string(), step))?;
+    }
+    for (step, summary) in &overall {
+        summary_writer.write_record(summary.row("all", step))?;
+    }
+    summary_writer.flush()?;
+
+    progress!("{} matched steps written to {}, summary to {}", matched, output, summary);
+    progress!("Speedup and memory ratio of {} over Rust, all sample sizes:", label);
+    for (step, summary) in &overall {
+        let memory = summary.memory_ratio().map_or("no memory".to_string(), |ratio| format!("{:.2}x memory", ratio));
+        progress!("  {}: {:.2}x time ({} pairs), {}", step, summary.speedup(), summary.pairs, memory);
+    }
+    console::artifact("comparison", output);
+    console::artifact("comparison_summary", summary);
+    console::metric("matched_steps", matched);
+    Ok(())
+}
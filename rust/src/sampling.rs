@@ -0,0 +1,195 @@
+use crate::dates::DocumentDates;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Metadata field files are stratified or grouped by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleField {
+    /// Top-level subdirectory of the file under the source directory
+    Subdir,
+    /// Year of the file's date: its `date` in the metadata CSV, otherwise its
+    /// modification time
+    Year,
+    /// A column of the metadata CSV
+    Column(String),
+}
+
+impl std::str::FromStr for SampleField {
+    type Err = String;
+
+    /// Parses "subdir", "year", or the name of a metadata column.
+    fn from_str(s: &str) -> Result<SampleField, String> {
+        Ok(match s {
+            "" => return Err("Empty sampling field".to_string()),
+            "subdir" => SampleField::Subdir,
+            "year" => SampleField::Year,
+            column => SampleField::Column(column.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for SampleField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SampleField::Subdir => write!(f, "subdir"),
+            SampleField::Year => write!(f, "year"),
+            SampleField::Column(column) => write!(f, "{}", column),
+        }
+    }
+}
+
+/// How the files of a sample are drawn.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Sampling {
+    /// Uniformly without replacement
+    #[default]
+    Simple,
+    /// Uniformly within each value of the field, in proportion to its share of the files
+    Stratified(SampleField),
+    /// Whole groups of files sharing a value of the field, in random order
+    Grouped(SampleField),
+}
+
+/// How bootstrap samples are drawn, see `Sampler`.
+#[derive(Debug, Clone, Default)]
+pub struct SamplingConfig {
+    pub sampling: Sampling,
+    /// CSV with a key column (file path or file name) followed by metadata columns,
+    /// e.g. `date` for sampling by year
+    pub metadata: Option<String>,
+}
+
+/// Values of a metadata CSV column, keyed like `DocumentDates` by the first column
+/// (file path or file name).
+fn load_column(path: &str, column: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let idx = rdr.headers()?
+        .iter()
+        .position(|h| h == column)
+        .ok_or_else(|| format!("{} has no '{}' column", path, column))?;
+    let mut by_key = HashMap::new();
+    for result in rdr.records() {
+        let record = result?;
+        by_key.insert(record.get(0).unwrap_or_default().to_string(), record.get(idx).unwrap_or_default().to_string());
+    }
+    Ok(by_key)
+}
+
+/// The value of `field` for each of `files`; empty when a file has none.
+fn field_values(files: &[PathBuf], source_dir: &Path, field: &SampleField, metadata: Option<&str>) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(match field {
+        SampleField::Subdir => files
+            .iter()
+            .map(|file| {
+                let relative = file.strip_prefix(source_dir).unwrap_or(file);
+                let mut components = relative.components();
+                match (components.next(), components.next()) {
+                    (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+                    _ => String::new(),
+                }
+            })
+            .collect(),
+        SampleField::Year => {
+            let dates = metadata.map(DocumentDates::load).transpose()?.unwrap_or_default();
+            files.iter().map(|file| dates.of_file(file).chars().take(4).collect()).collect()
+        }
+        SampleField::Column(column) => {
+            let path = metadata.ok_or_else(|| format!("Sampling by '{}' needs a metadata CSV", column))?;
+            let by_key = load_column(path, column)?;
+            files
+                .iter()
+                .map(|file| {
+                    let by_name = || file.file_name().and_then(|name| by_key.get(name.to_string_lossy().as_ref()));
+                    by_key.get(file.to_string_lossy().as_ref()).or_else(by_name).cloned().unwrap_or_default()
+                })
+                .collect()
+        }
+    })
+}
+
+/// Splits `size` over strata in proportion to their sizes, handing the rounding
+/// remainder to the strata with the largest fractional shares.
+fn allocate(strata: &[Vec<usize>], size: usize) -> Vec<usize> {
+    let total: usize = strata.iter().map(Vec::len).sum();
+    let shares: Vec<f64> = strata.iter().map(|files| size as f64 * files.len() as f64 / total.max(1) as f64).collect();
+    let mut counts: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..strata.len()).collect();
+    by_remainder.sort_by(|&a, &b| (shares[b] - shares[b].floor()).total_cmp(&(shares[a] - shares[a].floor())));
+    let missing = size - counts.iter().sum::<usize>();
+    for &stratum in by_remainder.iter().take(missing) {
+        counts[stratum] += 1;
+    }
+    counts
+}
+
+/// Draws samples of files according to a `Sampling`.
+pub struct Sampler {
+    sampling: Sampling,
+    /// Indices of the files sharing each value of the field, in value order; a single
+    /// stratum of all files for `Sampling::Simple`
+    strata: Vec<(String, Vec<usize>)>,
+}
+
+impl Sampler {
+    /// Groups `files` (found under `source_dir`) by the sampling field, read from the
+    /// metadata CSV where needed.
+    pub fn new(files: &[PathBuf], source_dir: &Path, config: &SamplingConfig) -> Result<Sampler, Box<dyn Error>> {
+        let sampling = config.sampling.clone();
+        let strata = match &sampling {
+            Sampling::Simple => vec![(String::new(), (0..files.len()).collect())],
+            Sampling::Stratified(field) | Sampling::Grouped(field) => {
+                let mut by_value: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+                for (idx, value) in field_values(files, source_dir, field, config.metadata.as_deref())?.into_iter().enumerate() {
+                    by_value.entry(value).or_default().push(idx);
+                }
+                by_value.into_iter().collect()
+            }
+        };
+        Ok(Sampler { sampling, strata })
+    }
+
+    /// One line describing the strata or groups, e.g. for the bootstrap log.
+    pub fn describe(&self) -> String {
+        let values = |strata: &[(String, Vec<usize>)]| {
+            strata.iter().take(10).map(|(value, files)| format!("{}={}", if value.is_empty() { "(none)" } else { value }, files.len())).collect::<Vec<_>>().join(", ")
+        };
+        match &self.sampling {
+            Sampling::Simple => "simple random sampling".to_string(),
+            Sampling::Stratified(field) => format!("stratified by {} over {} strata: {}", field, self.strata.len(), values(&self.strata)),
+            Sampling::Grouped(field) => format!("grouped by {} into {} groups: {}", field, self.strata.len(), values(&self.strata)),
+        }
+    }
+
+    /// Draws the indices of about `size` files. Stratified samples keep each
+    /// stratum's share of the files; grouped samples take whole groups until the next
+    /// would overshoot `size`, so they may come out smaller.
+    pub fn draw(&self, size: usize, rng: &mut StdRng) -> Vec<usize> {
+        match self.sampling {
+            Sampling::Simple | Sampling::Stratified(_) => {
+                let strata: Vec<Vec<usize>> = self.strata.iter().map(|(_, files)| files.clone()).collect();
+                allocate(&strata, size)
+                    .into_iter()
+                    .zip(&strata)
+                    .flat_map(|(count, files)| files.choose_multiple(rng, count).copied().collect::<Vec<_>>())
+                    .collect()
+            }
+            Sampling::Grouped(_) => {
+                let mut groups: Vec<&Vec<usize>> = self.strata.iter().map(|(_, files)| files).collect();
+                groups.shuffle(rng);
+                let mut sample = Vec::with_capacity(size);
+                for files in groups {
+                    if sample.len() + files.len() <= size {
+                        sample.extend_from_slice(files);
+                    }
+                    if sample.len() == size {
+                        break;
+                    }
+                }
+                sample
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::cluster::dominant_topic;
+use crate::compression::{self, Encoder};
+use crate::console;
+use crate::modeling::{normalize_rows, ModelConfig, NmfModel};
+use crate::preprocessing::{self, PreprocessConfig, Preprocessor};
+use crate::readers;
+use crate::shutdown;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Counts of a `score` run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScoreSummary {
+    pub documents: usize,
+    /// Documents with no term of the model's vocabulary, written without a topic
+    pub empty_documents: usize,
+    /// Files that could not be read, reported and skipped
+    pub failed: usize,
+}
+
+/// Projects a batch of documents onto the model's topics and writes one row per file.
+fn write_batch(batch: &mut Vec<(PathBuf, Vec<String>)>, model: &NmfModel, config: &ModelConfig, wtr: &mut csv::Writer<Encoder>, summary: &mut ScoreSummary) -> Result<(), Box<dyn Error>> {
+    let (paths, documents): (Vec<PathBuf>, Vec<Vec<String>>) = batch.drain(..).unzip();
+    let w = normalize_rows(model.transform(&documents, config.max_iter, config.tol, config.init_seed()));
+    for (path, row) in paths.iter().zip(w.rows()) {
+        let mut record = vec![path.to_string_lossy().into_owned()];
+        if row.sum() > 0.0 {
+            let (topic, probability) = dominant_topic(row);
+            record.push(topic.to_string());
+            record.push(format!("{:.6}", probability));
+        } else {
+            summary.empty_documents += 1;
+            record.extend([String::new(), String::new()]);
+        }
+        record.extend(row.iter().map(|share| format!("{:.6}", share)));
+        wtr.write_record(&record)?;
+    }
+    summary.documents += paths.len();
+    // Flushed per batch, so an interrupted run leaves every finished batch on disk
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Labels every document under `input` (a directory or an index file) with the topics
+/// of the saved model at `model_path`. Files are read, preprocessed and projected
+/// `batch_size` at a time while the directory is walked, so memory stays bounded
+/// however many there are. Writes each file's dominant topic, its share and the full
+/// topic distribution to `output`, compressed according to its extension.
+pub fn run(input: &str, model_path: &str, output: &str, batch_size: usize, preprocess_config: &PreprocessConfig, config: &ModelConfig) -> Result<ScoreSummary, Box<dyn Error>> {
+    let model = NmfModel::load(model_path)?;
+    let preprocessor = Preprocessor::new(preprocess_config)?;
+    let mut wtr = csv::Writer::from_writer(compression::create(output)?);
+    let mut header = vec!["file".to_string(), "dominant_topic".to_string(), "probability".to_string()];
+    header.extend((0..model.h.nrows()).map(|topic| format!("topic_{}", topic)));
+    wtr.write_record(&header)?;
+
+    let started = Instant::now();
+    let mut summary = ScoreSummary::default();
+    let mut batch = Vec::with_capacity(batch_size);
+    for path in preprocessing::walk_input_files(input)? {
+        if shutdown::requested() {
+            break;
+        }
+        let path = path?;
+        match readers::read_document(&path) {
+            Ok((text, _)) => batch.push((path, preprocessor.process(&text))),
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                summary.failed += 1;
+            }
+        }
+        if batch.len() >= batch_size.max(1) {
+            write_batch(&mut batch, &model, config, &mut wtr, &mut summary)?;
+            let rate = summary.documents as f64 / started.elapsed().as_secs_f64().max(1e-9);
+            progress!("Scored {} documents ({:.0} per second)", summary.documents, rate);
+        }
+    }
+    if !batch.is_empty() {
+        write_batch(&mut batch, &model, config, &mut wtr, &mut summary)?;
+    }
+    compression::finish_csv(wtr)?;
+
+    if shutdown::requested() {
+        progress!("Interrupted after {} documents", summary.documents);
+    }
+    progress!("Scored {} documents in {:.1}s, written to {}", summary.documents, started.elapsed().as_secs_f64(), output);
+    console::artifact("scores", output);
+    console::metric("documents", summary.documents);
+    console::metric("empty_documents", summary.empty_documents);
+    console::metric("failed", summary.failed);
+    if summary.empty_documents > 0 {
+        progress!("{} documents have no vocabulary terms and no dominant topic", summary.empty_documents);
+    }
+    if summary.failed > 0 {
+        progress!("{} files could not be read", summary.failed);
+    }
+    Ok(summary)
+}
@@ -0,0 +1,105 @@
+use crate::modeling::{ModelConfig, NmfModel, TopWords, Topics};
+use crate::preprocessing::{PreprocessConfig, Preprocessor};
+use crate::prometheus::{self, PipelineMetrics, METRICS_PATH};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Arc;
+
+struct AppState {
+    model: NmfModel,
+    preprocessor: Preprocessor,
+    max_iter: usize,
+    tol: f32,
+    seed: Option<u64>,
+    top_words: TopWords,
+    metrics: Arc<PipelineMetrics>,
+}
+
+#[derive(Deserialize)]
+struct TextRequest {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct TokensResponse {
+    tokens: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct DistributionResponse {
+    tokens: Vec<String>,
+    distribution: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct TopicsResponse {
+    topics: Topics,
+}
+
+/// Runs CPU-bound `work` on the blocking thread pool, so a long NMF projection doesn't
+/// hold up the async workers serving other connections, `/metrics` included.
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T, StatusCode> {
+    tokio::task::spawn_blocking(work).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn preprocess(State(state): State<Arc<AppState>>, Json(request): Json<TextRequest>) -> Result<Json<TokensResponse>, StatusCode> {
+    state.metrics.add_request();
+    let tokens = blocking(move || state.preprocessor.process(&request.text)).await?;
+    Ok(Json(TokensResponse { tokens }))
+}
+
+async fn topics(State(state): State<Arc<AppState>>, Json(request): Json<TextRequest>) -> Result<Json<DistributionResponse>, StatusCode> {
+    state.metrics.add_request();
+    let response = blocking(move || {
+        let tokens = state.preprocessor.process(&request.text);
+        let w = state.model.transform(std::slice::from_ref(&tokens), state.max_iter, state.tol, state.seed);
+        state.metrics.add_documents(1);
+        DistributionResponse {
+            tokens,
+            distribution: w.row(0).to_vec(),
+        }
+    })
+    .await?;
+    Ok(Json(response))
+}
+
+async fn model_topics(State(state): State<Arc<AppState>>) -> Json<TopicsResponse> {
+    state.metrics.add_request();
+    Json(TopicsResponse { topics: state.model.topics(state.top_words) })
+}
+
+/// Serves topic inference for a saved model over HTTP until the process is stopped,
+/// with Prometheus metrics on `METRICS_PATH`.
+pub fn run(model_path: &str, addr: &str, preprocess_config: &PreprocessConfig, config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let model = NmfModel::load(model_path)?;
+    let metrics = Arc::new(PipelineMetrics::default());
+    metrics.record_model(&model);
+    let state = Arc::new(AppState {
+        model,
+        preprocessor: Preprocessor::new(preprocess_config)?,
+        max_iter: config.max_iter,
+        tol: config.tol,
+        seed: config.init_seed(),
+        top_words: config.top_words,
+        metrics: metrics.clone(),
+    });
+
+    let app = Router::new()
+        .route("/preprocess", post(preprocess))
+        .route("/topics", post(topics))
+        .route("/model/topics", get(model_topics))
+        .with_state(state)
+        .merge(prometheus::router(metrics));
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        progress!("Serving {} on http://{}, metrics on {}", model_path, addr, METRICS_PATH);
+        axum::serve(listener, app).await?;
+        Ok(())
+    })
+}
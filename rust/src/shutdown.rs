@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Handles Ctrl-C in stages: the first press lets the current dataset finish, the
+/// second also cuts the running NMF fit short, and the third exits immediately.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| match INTERRUPTS.fetch_add(1, Ordering::SeqCst) {
+        0 => eprintln!("\nInterrupted: finishing the current dataset (Ctrl-C again to stop the NMF fit)"),
+        1 => eprintln!("\nInterrupted: stopping the NMF fit (Ctrl-C again to exit immediately)"),
+        _ => std::process::exit(130),
+    })
+}
+
+/// Whether the run should stop once the current dataset is done.
+pub fn requested() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) >= 1
+}
+
+/// Whether in-progress work should be abandoned.
+pub fn aborted() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) >= 2
+}
@@ -0,0 +1,54 @@
+use crate::console;
+use crate::modeling::{self, ModelConfig, NmfModel};
+use crate::preprocessing::{self, PreprocessConfig, Preprocessor};
+use crate::readers;
+use crate::workdir::{DISTRIBUTIONS_FILE, FILES_FILE};
+use ndarray::{Array1, ArrayView1};
+use std::error::Error;
+use std::path::Path;
+
+pub(crate) fn cosine_similarity(a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32 {
+    let norm = a.dot(&a).sqrt() * b.dot(&b).sqrt();
+    if norm == 0.0 { 0.0 } else { a.dot(&b) / norm }
+}
+
+/// Prints the `top` documents whose topic vectors are most similar to the query,
+/// which is either an indexed document or an external text file projected with the saved model.
+pub fn run(doc: Option<usize>, file: Option<&str>, top: usize, model_path: &str, preprocess_config: &PreprocessConfig, config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let w = modeling::load_topic_distributions(&config.workdir.path(&config.compression.path(DISTRIBUTIONS_FILE)))?;
+    let paths = preprocessing::load_file_paths(&config.workdir.path(FILES_FILE))?;
+
+    let query: Array1<f32> = match (doc, file) {
+        (Some(index), _) => {
+            if index >= w.nrows() {
+                return Err(format!("Document {} out of range, corpus has {} documents", index, w.nrows()).into());
+            }
+            w.row(index).to_owned()
+        }
+        (None, Some(path)) => {
+            let model = NmfModel::load(model_path)?;
+            let preprocessor = Preprocessor::new(preprocess_config)?;
+            let (text, _) = readers::read_document(Path::new(path))?;
+            let tokens = preprocessor.process(&text);
+            model.transform(&[tokens], config.max_iter, config.tol, config.init_seed()).row(0).to_owned()
+        }
+        (None, None) => return Err("Either a document index or a file is required".into()),
+    };
+
+    let mut scores: Vec<(usize, f32)> = w.rows()
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| Some(*idx) != doc)
+        .map(|(idx, row)| (idx, cosine_similarity(query.view(), row)))
+        .collect();
+    scores.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut similar = Vec::new();
+    for (idx, score) in scores.iter().take(top) {
+        let path = paths.get(*idx).map_or("?", String::as_str);
+        progress!("{:.4}  {}  {}", score, idx, path);
+        similar.push(serde_json::json!({ "score": score, "document": idx, "path": path }));
+    }
+    console::metric("similar", similar);
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use crate::{MetricsSink, StepRecord};
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::path::Path;
+
+/// Database in the run directory shared by all sample sizes.
+pub const RESULTS_DB: &str = "results.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id TEXT PRIMARY KEY,
+    created TEXT NOT NULL,
+    manifest TEXT
+);
+CREATE TABLE IF NOT EXISTS steps (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT NOT NULL REFERENCES runs(id),
+    sample INTEGER NOT NULL,
+    k INTEGER NOT NULL,
+    iteration INTEGER NOT NULL,
+    dataset INTEGER NOT NULL,
+    step TEXT NOT NULL,
+    time_s REAL NOT NULL,
+    rss_before_mib REAL,
+    rss_after_mib REAL,
+    rss_peak_mib REAL,
+    virtual_before_mib REAL,
+    virtual_after_mib REAL,
+    virtual_peak_mib REAL,
+    cpu_usage REAL,
+    cpu_seconds REAL,
+    heldout_error REAL,
+    nmf_iterations INTEGER,
+    time_per_iteration_s REAL,
+    h_update_s REAL,
+    w_update_s REAL,
+    error_s REAL,
+    final_error REAL,
+    frobenius_error REAL,
+    relative_error REAL,
+    explained_variance REAL,
+    read_bytes INTEGER,
+    write_bytes INTEGER,
+    read_ops INTEGER,
+    write_ops INTEGER,
+    instructions INTEGER,
+    cache_misses INTEGER,
+    branch_misses INTEGER
+);
+CREATE TABLE IF NOT EXISTS topics (
+    run_id TEXT NOT NULL REFERENCES runs(id),
+    step_id INTEGER NOT NULL REFERENCES steps(id),
+    topic INTEGER NOT NULL,
+    words TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS doc_topics (
+    run_id TEXT NOT NULL REFERENCES runs(id),
+    step_id INTEGER NOT NULL REFERENCES steps(id),
+    document INTEGER NOT NULL,
+    topic INTEGER NOT NULL,
+    weight REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS steps_run ON steps(run_id, sample, k, step);
+";
+
+/// Writes the metrics, topics and dominant topic of every document to `RESULTS_DB`,
+/// keyed by the run id so results of several runs can be merged into one database.
+pub struct SqliteSink {
+    connection: Connection,
+    run_id: String,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database of `run_dir` and registers the run.
+    pub fn open(run_dir: &Path) -> Result<SqliteSink, Box<dyn Error>> {
+        let connection = Connection::open(run_dir.join(RESULTS_DB))?;
+        connection.execute_batch(SCHEMA)?;
+        let run_id = run_dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let manifest = std::fs::read_to_string(run_dir.join("manifest.json")).ok();
+        connection.execute(
+            "INSERT OR IGNORE INTO runs (id, created, manifest) VALUES (?1, datetime('now'), ?2)",
+            params![run_id, manifest],
+        )?;
+        Ok(SqliteSink { connection, run_id })
+    }
+}
+
+impl MetricsSink for SqliteSink {
+    fn record(&mut self, record: &StepRecord) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.transaction()?;
+        let metrics = record.metrics;
+        let timings = record.model().map(|summary| &summary.timings);
+        let fit = record.model().and_then(|summary| summary.fit);
+        transaction.execute(
+            "INSERT INTO steps (run_id, sample, k, iteration, dataset, step, time_s,
+                rss_before_mib, rss_after_mib, rss_peak_mib, virtual_before_mib, virtual_after_mib, virtual_peak_mib,
+                cpu_usage, cpu_seconds, heldout_error,
+                nmf_iterations, time_per_iteration_s, h_update_s, w_update_s, error_s, final_error,
+                frobenius_error, relative_error, explained_variance,
+                read_bytes, write_bytes, read_ops, write_ops, instructions, cache_misses, branch_misses)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23,
+                ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)",
+            params![
+                self.run_id,
+                record.sample,
+                record.k,
+                record.iteration,
+                record.dataset,
+                record.step,
+                metrics.elapsed.as_secs_f64(),
+                metrics.memory.rss_before_mib,
+                metrics.memory.rss_after_mib,
+                metrics.memory.rss_peak_mib,
+                metrics.memory.virtual_before_mib,
+                metrics.memory.virtual_after_mib,
+                metrics.memory.virtual_peak_mib,
+                metrics.cpu_usage,
+                metrics.cpu_seconds,
+                record.model().and_then(|summary| summary.heldout_error),
+                timings.map(|t| t.iterations),
+                timings.map(|t| t.per_iteration().as_secs_f64()),
+                timings.map(|t| t.h_update.as_secs_f64()),
+                timings.map(|t| t.w_update.as_secs_f64()),
+                timings.map(|t| t.error.as_secs_f64()),
+                timings.and_then(|t| t.errors.last().copied()),
+                fit.map(|f| f.frobenius_error),
+                fit.map(|f| f.relative_error),
+                fit.map(|f| f.explained_variance),
+                metrics.io.read_bytes,
+                metrics.io.write_bytes,
+                metrics.io.read_ops,
+                metrics.io.write_ops,
+                metrics.hardware.map(|h| h.instructions),
+                metrics.hardware.map(|h| h.cache_misses),
+                metrics.hardware.map(|h| h.branch_misses),
+            ],
+        )?;
+        let step_id = transaction.last_insert_rowid();
+
+        if let Some(summary) = record.model() {
+            let mut insert = transaction.prepare("INSERT INTO topics (run_id, step_id, topic, words) VALUES (?1, ?2, ?3, ?4)")?;
+            for topic in &summary.topics {
+                insert.execute(params![self.run_id, step_id, topic.index, topic.terms().join(" ")])?;
+            }
+        }
+        if !record.assignments.is_empty() {
+            let mut insert = transaction.prepare("INSERT INTO doc_topics (run_id, step_id, document, topic, weight) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (document, &(topic, weight)) in record.assignments.iter().enumerate() {
+                insert.execute(params![self.run_id, step_id, document, topic, weight])?;
+            }
+        }
+        for (name, elapsed) in record.substeps {
+            transaction.execute(
+                "INSERT INTO steps (run_id, sample, k, iteration, dataset, step, time_s) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![self.run_id, record.sample, record.k, record.iteration, record.dataset, format!("{}/{}", record.step, name), elapsed.as_secs_f64()],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
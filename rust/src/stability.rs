@@ -0,0 +1,126 @@
+use crate::console;
+use crate::modeling::{NmfModel, TopWords};
+use crate::validate::{align_vocabularies, match_topics, mean, pearson, top_terms};
+use clap::ValueEnum;
+use ndarray::Array2;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// How topics of two models are compared when aligning them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Similarity {
+    /// Pearson correlation of the topic-word weights over both vocabularies
+    #[default]
+    Correlation,
+    /// Jaccard index of the topics' top words
+    Jaccard,
+}
+
+/// How consistently one topic of the reference model reappears across runs.
+#[derive(Debug, serde::Serialize)]
+pub struct TopicStability {
+    pub topic: usize,
+    pub top_words: String,
+    /// Runs in which the topic was matched
+    pub runs: usize,
+    pub mean_similarity: f32,
+    pub std_similarity: f32,
+    /// Lowest similarity over the runs; empty when the topic was never matched
+    pub min_similarity: Option<f32>,
+}
+
+/// Saved models among `inputs`: model files, or every .json file in a directory.
+fn model_files(inputs: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Similarity of every topic of `reference` (rows) to every topic of `model` (columns).
+fn similarities(reference: &NmfModel, model: &NmfModel, similarity: Similarity, top: usize) -> Array2<f32> {
+    let reference_terms = reference.vocab.terms();
+    let terms = model.vocab.terms();
+    let (union_terms, h_reference, h) = align_vocabularies(&reference.h, &reference_terms, &model.h, &terms);
+
+    let mut scores = Array2::<f32>::zeros((h_reference.nrows(), h.nrows()));
+    for ((i, j), score) in scores.indexed_iter_mut() {
+        *score = match similarity {
+            Similarity::Correlation => pearson(h_reference.row(i), h.row(j)),
+            Similarity::Jaccard => {
+                let a = top_terms(h_reference.row(i), &union_terms, top);
+                let b = top_terms(h.row(j), &union_terms, top);
+                a.intersection(&b).count() as f32 / a.union(&b).count().max(1) as f32
+            }
+        };
+    }
+    scores
+}
+
+/// Aligns the topics of models fit on bootstrap datasets with those of the first model
+/// (Hungarian matching) and writes how stable each of its topics is to `output`, listing
+/// the `top_words` of the reference topics.
+pub fn run(inputs: &[String], similarity: Similarity, top: usize, top_words: TopWords, output: &str) -> Result<(), Box<dyn Error>> {
+    let files = model_files(inputs)?;
+    if files.len() < 2 {
+        return Err(format!("Topic stability needs at least two models, found {}", files.len()).into());
+    }
+    let models = files
+        .iter()
+        .map(|file| NmfModel::load(&file.to_string_lossy()).map_err(|e| format!("{}: {}", file.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let reference = &models[0];
+    progress!("Aligning {} models with {}", models.len() - 1, files[0].display());
+
+    let mut scores: Vec<Vec<f32>> = vec![Vec::new(); reference.h.nrows()];
+    for model in &models[1..] {
+        let similarities = similarities(reference, model, similarity, top);
+        for (topic, matched) in match_topics(&similarities)?.into_iter().enumerate() {
+            if let Some(matched) = matched {
+                scores[topic].push(similarities[[topic, matched]]);
+            }
+        }
+    }
+
+    let topics = reference.topics(top_words);
+    let mut results = Vec::new();
+    for (topic, values) in scores.iter().enumerate() {
+        let average = mean(values.iter().copied());
+        let variance = mean(values.iter().map(|x| (x - average).powi(2)));
+        results.push(TopicStability {
+            topic,
+            top_words: topics.get(topic).map_or(String::new(), |t| t.terms().join(" ")),
+            runs: values.len(),
+            mean_similarity: average,
+            std_similarity: variance.sqrt(),
+            min_similarity: values.iter().copied().reduce(f32::min),
+        });
+    }
+
+    let mut wtr = csv::Writer::from_path(output)?;
+    for result in &results {
+        wtr.serialize(result)?;
+    }
+    wtr.flush()?;
+
+    for result in &results {
+        progress!("Topic {}: stability {:.3} ± {:.3} over {} runs ({})",
+            result.topic, result.mean_similarity, result.std_similarity, result.runs, result.top_words);
+    }
+    progress!("Mean topic stability: {:.3}", mean(results.iter().map(|r| r.mean_similarity)));
+    progress!("Per-topic stability written to {}", output);
+    console::artifact("stability", output);
+    console::metric("mean_stability", mean(results.iter().map(|r| r.mean_similarity)));
+    Ok(())
+}
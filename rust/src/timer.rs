@@ -0,0 +1,29 @@
+use std::time::{Duration, Instant};
+
+/// Records the wall time of the named sub-stages of a pipeline step, in the order
+/// they first ran. Stages timed more than once accumulate.
+#[derive(Debug, Default)]
+pub struct StepTimer {
+    stages: Vec<(String, Duration)>,
+}
+
+impl StepTimer {
+    pub fn new() -> StepTimer {
+        StepTimer::default()
+    }
+
+    pub fn time<T>(&mut self, name: &str, stage: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = stage();
+        let elapsed = started.elapsed();
+        match self.stages.iter_mut().find(|(stage, _)| stage == name) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((name.to_string(), elapsed)),
+        }
+        result
+    }
+
+    pub fn stages(&self) -> &[(String, Duration)] {
+        &self.stages
+    }
+}
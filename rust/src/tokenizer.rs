@@ -0,0 +1,195 @@
+use clap::ValueEnum;
+use regex::Regex;
+
+/// Splits raw text into lowercase tokens ahead of stopword removal and stemming.
+///
+/// Implement this to plug a custom tokenizer into a `Preprocessor`.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// How hyphens inside words are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HyphenMode {
+    /// Split at hyphens: "state-of-the-art" becomes four tokens
+    #[default]
+    Split,
+    /// Keep hyphenated words as one token
+    Keep,
+    /// Drop the hyphens and join the parts: "e-mail" becomes "email"
+    Join,
+}
+
+/// What happens to purely numeric tokens when digits are kept. Alphanumeric codes
+/// such as "covid19" are always kept as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NumberMode {
+    /// Keep numbers as tokens
+    #[default]
+    Keep,
+    /// Drop numbers, keeping only tokens containing letters
+    Drop,
+    /// Map years (1500-2099) to <year> and other numbers to <num>
+    Normalize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TokenizerConfig {
+    /// Regex whose matches (in the lowercased text) are the tokens; overrides `strip_pattern`
+    pub token_pattern: Option<String>,
+    /// Regex of characters replaced by whitespace before splitting; defaults to
+    /// everything except letters (and digits/hyphens when kept)
+    pub strip_pattern: Option<String>,
+    pub keep_digits: bool,
+    pub numbers: NumberMode,
+    pub hyphens: HyphenMode,
+    pub strip_urls: bool,
+    pub strip_emails: bool,
+}
+
+/// The built-in tokenizer, configured by a `TokenizerConfig` and compiled once.
+pub struct RegexTokenizer {
+    token_re: Option<Regex>,
+    strip_re: Regex,
+    url_re: Option<Regex>,
+    email_re: Option<Regex>,
+    numbers: NumberMode,
+    hyphens: HyphenMode,
+}
+
+impl RegexTokenizer {
+    pub fn new(config: &TokenizerConfig) -> Result<RegexTokenizer, regex::Error> {
+        let strip_pattern = match &config.strip_pattern {
+            Some(pattern) => pattern.clone(),
+            None => {
+                let digits = if config.keep_digits { "0-9" } else { "" };
+                let hyphen = if config.hyphens == HyphenMode::Split { "" } else { "\\-" };
+                format!(r"[^a-zA-Z{}{}\s]", digits, hyphen)
+            }
+        };
+
+        Ok(RegexTokenizer {
+            token_re: config.token_pattern.as_deref().map(Regex::new).transpose()?,
+            strip_re: Regex::new(&strip_pattern)?,
+            url_re: config.strip_urls.then(|| Regex::new(r"(?i)\b(?:https?://|www\.)\S+")).transpose()?,
+            email_re: config.strip_emails.then(|| Regex::new(r"\b[\w.+-]+@[\w-]+\.[\w.-]+\b")).transpose()?,
+            numbers: config.numbers,
+            hyphens: config.hyphens,
+        })
+    }
+
+    fn apply_hyphen_mode(&self, token: &str) -> String {
+        match self.hyphens {
+            HyphenMode::Split => token.to_string(),
+            HyphenMode::Keep => token.trim_matches('-').to_string(),
+            HyphenMode::Join => token.replace('-', ""),
+        }
+    }
+
+    /// Applies the number mode; `None` drops the token.
+    fn apply_number_mode(&self, token: String) -> Option<String> {
+        if self.numbers == NumberMode::Keep || !token.bytes().all(|b| b.is_ascii_digit()) {
+            return Some(token);
+        }
+        match self.numbers {
+            NumberMode::Drop => None,
+            _ if is_year(&token) => Some("<year>".to_string()),
+            _ => Some("<num>".to_string()),
+        }
+    }
+}
+
+impl Default for RegexTokenizer {
+    fn default() -> Self {
+        RegexTokenizer::new(&TokenizerConfig::default()).expect("default tokenizer patterns are valid")
+    }
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut text = text.to_string();
+        // URLs and emails go first so their pieces don't survive as tokens
+        for re in self.url_re.iter().chain(&self.email_re) {
+            text = re.replace_all(&text, " ").into_owned();
+        }
+
+        let tokens: Vec<String> = match &self.token_re {
+            Some(re) => re.find_iter(&text.to_lowercase()).map(|m| m.as_str().to_string()).collect(),
+            None => {
+                // Remove special characters and numbers
+                let cleaned = self.strip_re.replace_all(&text, " ").to_lowercase();
+                cleaned.split_whitespace().map(str::to_string).collect()
+            }
+        };
+
+        tokens.iter()
+            .map(|token| self.apply_hyphen_mode(token))
+            .filter(|token| !token.is_empty())
+            .filter_map(|token| self.apply_number_mode(token))
+            .collect()
+    }
+}
+
+fn is_year(token: &str) -> bool {
+    token.len() == 4 && token.parse::<u32>().is_ok_and(|year| (1500..2100).contains(&year))
+}
+
+/// Marks the start and end of a word in its character n-grams.
+pub const WORD_BOUNDARY: char = '_';
+
+/// Range of character n-gram lengths, e.g. 3 to 5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharNgrams {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl std::str::FromStr for CharNgrams {
+    type Err = String;
+
+    /// Parses "3-5", or "4" for a single length.
+    fn from_str(s: &str) -> Result<CharNgrams, String> {
+        let parse = |n: &str| n.trim().parse::<usize>().map_err(|e| format!("Invalid n-gram length '{}': {}", n, e));
+        let (min, max) = match s.split_once('-') {
+            Some((min, max)) => (parse(min)?, parse(max)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if min == 0 || min > max {
+            return Err(format!("Invalid n-gram range '{}': lengths must be at least 1 and ascending", s));
+        }
+        Ok(CharNgrams { min, max })
+    }
+}
+
+/// Turns the words of an inner tokenizer into their character n-grams. Each word is
+/// padded with `WORD_BOUNDARY` and n-grams don't cross words; a padded word shorter
+/// than an n-gram length is kept whole.
+pub struct CharNgramTokenizer {
+    inner: Box<dyn Tokenizer>,
+    lengths: CharNgrams,
+}
+
+impl CharNgramTokenizer {
+    pub fn new(inner: Box<dyn Tokenizer>, lengths: CharNgrams) -> CharNgramTokenizer {
+        CharNgramTokenizer { inner, lengths }
+    }
+}
+
+impl Tokenizer for CharNgramTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut ngrams = Vec::new();
+        for word in self.inner.tokenize(text) {
+            let padded: Vec<char> = std::iter::once(WORD_BOUNDARY).chain(word.chars()).chain(std::iter::once(WORD_BOUNDARY)).collect();
+            for n in self.lengths.min..=self.lengths.max {
+                if padded.len() < n {
+                    if n == self.lengths.min {
+                        ngrams.push(padded.iter().collect());
+                    }
+                    break;
+                }
+                ngrams.extend(padded.windows(n).map(|window| window.iter().collect::<String>()));
+            }
+        }
+        ngrams
+    }
+}
@@ -0,0 +1,246 @@
+use crate::console;
+use crate::modeling::{self, ModelConfig, Solver};
+use crate::pipeline::Instrument;
+use crate::preprocessing::{self, PreprocessConfig};
+use crate::quality::{self, QUALITY_TOP_WORDS};
+use crate::workdir::TOKENS_FILE;
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Every configuration `tune` tried with its cross-validated scores
+pub const TUNE_RESULTS_FILE: &str = "tune_results.csv";
+
+/// Model hyperparameters read with `--config` and written by `tune`. Fields left out
+/// keep their defaults, and command line options given explicitly take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_df: Option<usize>,
+    /// Terms in more than this share of the documents are treated as stopwords
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_df: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lambda: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solver: Option<Solver>,
+}
+
+impl PipelineFile {
+    pub fn load(path: &str) -> Result<PipelineFile, Box<dyn Error>> {
+        toml::from_str(&std::fs::read_to_string(path)?).map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    /// Writes the file with a leading `comment` line.
+    pub fn save(&self, path: &str, comment: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, format!("# {}\n{}", comment, toml::to_string(self)?))?;
+        Ok(())
+    }
+}
+
+/// Values `tune` tries for each hyperparameter.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub k: Vec<usize>,
+    pub min_df: Vec<usize>,
+    /// `None` keeps every term however common
+    pub max_df: Vec<Option<f32>>,
+    pub lambda: Vec<f32>,
+    pub solver: Vec<Solver>,
+}
+
+impl SearchSpace {
+    /// Every combination of the values.
+    fn grid(&self) -> Vec<Candidate> {
+        let mut grid = Vec::new();
+        for &k in &self.k {
+            for &min_df in &self.min_df {
+                for &max_df in &self.max_df {
+                    for &lambda in &self.lambda {
+                        for &solver in &self.solver {
+                            grid.push(Candidate { k, min_df, max_df, lambda, solver });
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+}
+
+/// Score the best configuration is chosen by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TuneMetric {
+    /// Lowest relative reconstruction error of the held-out documents
+    #[default]
+    Error,
+    /// Highest NPMI coherence of the top words on the held-out documents
+    Coherence,
+}
+
+/// How `tune` searches, see `run`.
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    pub space: SearchSpace,
+    pub folds: usize,
+    /// Try this many random combinations instead of the whole grid
+    pub trials: Option<usize>,
+    pub metric: TuneMetric,
+    /// Where the best configuration is written as a `PipelineFile`
+    pub output: String,
+}
+
+/// One combination of hyperparameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub k: usize,
+    pub min_df: usize,
+    pub max_df: Option<f32>,
+    pub lambda: f32,
+    pub solver: Solver,
+}
+
+impl Candidate {
+    fn config(&self, base: &ModelConfig) -> ModelConfig {
+        ModelConfig {
+            k: self.k,
+            min_df: self.min_df,
+            auto_stopwords: self.max_df,
+            lambda: self.lambda,
+            solver: self.solver,
+            // A shared vocabulary would hide the document frequency cut-offs
+            vocab_path: None,
+            ..base.clone()
+        }
+    }
+
+    fn pipeline_file(&self) -> PipelineFile {
+        PipelineFile {
+            k: Some(self.k),
+            min_df: Some(self.min_df),
+            max_df: self.max_df,
+            lambda: Some(self.lambda),
+            solver: Some(self.solver),
+        }
+    }
+}
+
+impl std::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max_df = self.max_df.map_or("-".to_string(), |max_df| max_df.to_string());
+        let solver = self.solver.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string());
+        write!(f, "k={} min_df={} max_df={} lambda={} solver={}", self.k, self.min_df, max_df, self.lambda, solver)
+    }
+}
+
+/// Cross-validated scores of a `Candidate`, averaged over the folds.
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub candidate: Candidate,
+    pub heldout_error: f32,
+    pub coherence: f32,
+    /// Fitting and scoring all folds
+    pub elapsed: Duration,
+}
+
+impl Trial {
+    /// Whether this trial scores better than `other` on `metric`.
+    fn beats(&self, other: &Trial, metric: TuneMetric) -> bool {
+        match metric {
+            TuneMetric::Error => self.heldout_error < other.heldout_error,
+            TuneMetric::Coherence => self.coherence > other.coherence,
+        }
+    }
+}
+
+/// Documents a cross-validation fold is fit and scored on.
+struct Fold {
+    train: Vec<Vec<String>>,
+    test: Vec<Vec<String>>,
+}
+
+/// Preprocesses `input` once, then scores each configuration of the search space by
+/// `options.folds`-fold cross-validation: fit on all folds but one, and measure the
+/// reconstruction error and coherence of the held-out fold. The preprocessing and each
+/// configuration are measured as stages on `instrument`, so the benchmark machinery
+/// logs their cost. Writes every trial to `TUNE_RESULTS_FILE` in the workdir and the
+/// best configuration to `options.output`, and returns the trials in the order tried.
+pub fn run(input: &str, options: &TuneOptions, preprocess_config: &PreprocessConfig, config: &ModelConfig, instrument: &mut dyn Instrument) -> Result<Vec<Trial>, Box<dyn Error>> {
+    if options.folds < 2 {
+        return Err("Cross-validation needs at least 2 folds".into());
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut candidates = options.space.grid();
+    if candidates.is_empty() {
+        return Err("The search space is empty".into());
+    }
+    if let Some(trials) = options.trials.filter(|&trials| trials < candidates.len()) {
+        candidates = candidates.choose_multiple(&mut rng, trials).copied().collect();
+    }
+
+    instrument.measure("preprocessing", &mut || preprocessing::start(input, preprocess_config).map(|_| ()))?;
+    let documents = modeling::load_documents(&config.workdir.path(&config.compression.path(TOKENS_FILE)))?;
+    if documents.len() < options.folds {
+        return Err(format!("{} documents cannot be split into {} folds", documents.len(), options.folds).into());
+    }
+    let mut order: Vec<usize> = (0..documents.len()).collect();
+    order.shuffle(&mut rng);
+    // Every `folds`-th shuffled document is held out of a fold
+    let folds: Vec<Fold> = (0..options.folds)
+        .map(|fold| {
+            let documents_of = |held_out: bool| {
+                order.iter().enumerate().filter(|(position, _)| (position % options.folds == fold) == held_out).map(|(_, &idx)| documents[idx].clone()).collect()
+            };
+            Fold { train: documents_of(false), test: documents_of(true) }
+        })
+        .collect();
+    progress!("Tuning {} configurations with {}-fold cross-validation over {} documents", candidates.len(), options.folds, documents.len());
+
+    let mut trials = Vec::with_capacity(candidates.len());
+    for (idx, candidate) in candidates.into_iter().enumerate() {
+        let trial_config = candidate.config(config);
+        let started = Instant::now();
+        let mut scores = Vec::with_capacity(folds.len());
+        instrument.measure(&format!("trial {}", idx), &mut || {
+            for Fold { train, test } in &folds {
+                let fit = modeling::fit(train, &trial_config)?;
+                let error = fit.model.heldout_error(test, &trial_config);
+                let coherence = quality::npmi_coherence(&fit.model.h, &fit.model.vocab, test, QUALITY_TOP_WORDS);
+                scores.push((error, coherence));
+            }
+            Ok(())
+        })?;
+        let mean = |score: fn(&(f32, f32)) -> f32| scores.iter().map(score).sum::<f32>() / scores.len() as f32;
+        let trial = Trial { candidate, heldout_error: mean(|s| s.0), coherence: mean(|s| s.1), elapsed: started.elapsed() };
+        progress!("Trial {}: {}, held-out error {:.4}, coherence {:.3}", idx, candidate, trial.heldout_error, trial.coherence);
+        trials.push(trial);
+    }
+
+    let results_csv = config.workdir.path(TUNE_RESULTS_FILE);
+    let mut wtr = csv::Writer::from_path(&results_csv)?;
+    wtr.write_record(["Trial", "K", "Min DF", "Max DF", "Lambda", "Solver", "Held-out Error", "Coherence", "Time (s)"])?;
+    for (idx, trial) in trials.iter().enumerate() {
+        let Candidate { k, min_df, max_df, lambda, solver } = trial.candidate;
+        wtr.serialize((idx, k, min_df, max_df, lambda, solver, trial.heldout_error, trial.coherence, trial.elapsed.as_secs_f64()))?;
+    }
+    wtr.flush()?;
+
+    let best = trials.iter().reduce(|best, trial| if trial.beats(best, options.metric) { trial } else { best }).ok_or("No configuration was tried")?;
+    let metric = match options.metric {
+        TuneMetric::Error => format!("held-out error {:.4}", best.heldout_error),
+        TuneMetric::Coherence => format!("coherence {:.3}", best.coherence),
+    };
+    let comment = format!("Best of {} configurations by {}-fold cross-validated {}", trials.len(), options.folds, metric);
+    best.candidate.pipeline_file().save(&options.output, &comment)?;
+    progress!("Best configuration: {} ({}), written to {}; all trials in {}", best.candidate, metric, options.output, results_csv);
+    console::artifact("pipeline_config", &options.output);
+    console::artifact("tune_results", &results_csv);
+    Ok(trials)
+}
@@ -0,0 +1,228 @@
+use crate::console;
+use crate::modeling::{self, NmfModel};
+use ndarray::{Array2, ArrayView1};
+use pathfinding::prelude::{kuhn_munkres, Matrix};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Correlations are scaled to integers for the assignment solver.
+const WEIGHT_SCALE: f32 = 1e6;
+
+/// Alignment of one of our topics with its matched reference topic.
+#[derive(Debug, serde::Serialize)]
+pub struct TopicMatch {
+    pub topic: usize,
+    pub reference_topic: usize,
+    /// Pearson correlation of the topic-word weights over the union of both vocabularies
+    pub word_correlation: f32,
+    /// Share of the top words the two topics have in common
+    pub top_word_overlap: f32,
+    /// Pearson correlation of the document-topic columns, when a reference W is given
+    pub document_correlation: Option<f32>,
+}
+
+pub(crate) fn pearson(a: ArrayView1<f32>, b: ArrayView1<f32>) -> f32 {
+    let (mean_a, mean_b) = (a.mean().unwrap_or(0.0), b.mean().unwrap_or(0.0));
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (&x, &y) in a.iter().zip(b.iter()) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    let norm = (var_a * var_b).sqrt();
+    if norm == 0.0 { 0.0 } else { cov / norm }
+}
+
+pub(crate) fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), x| (sum + x, count + 1));
+    if count == 0 { 0.0 } else { sum / count as f32 }
+}
+
+pub(crate) fn read_npy(path: &str) -> Result<Array2<f32>, Box<dyn Error>> {
+    let bytes = std::fs::read(path)?;
+    let npy = npyz::NpyFile::new(&bytes[..])?;
+    let shape = npy.shape().to_vec();
+    let fortran = npy.order() == npyz::Order::Fortran;
+    let values: Vec<f32> = match npy.into_vec::<f64>() {
+        Ok(values) => values.into_iter().map(|x| x as f32).collect(),
+        Err(_) => npyz::NpyFile::new(&bytes[..])?.into_vec::<f32>()?,
+    };
+    let (rows, cols) = match shape[..] {
+        [rows, cols] => (rows as usize, cols as usize),
+        _ => return Err(format!("{} is not a 2-D array (shape {:?})", path, shape).into()),
+    };
+    if fortran {
+        Ok(Array2::from_shape_vec((cols, rows), values)?.reversed_axes().as_standard_layout().to_owned())
+    } else {
+        Ok(Array2::from_shape_vec((rows, cols), values)?)
+    }
+}
+
+/// Reads a matrix written by `pandas.DataFrame.to_csv()`: a header row and a leading
+/// index column. Returns the header (minus the index column) with the values.
+fn read_csv_matrix(path: &str) -> Result<(Vec<String>, Array2<f32>), Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let header: Vec<String> = rdr.headers()?.iter().skip(1).map(str::to_string).collect();
+    let mut values = Vec::new();
+    let mut rows = 0;
+    for result in rdr.records() {
+        let record = result?;
+        for field in record.iter().skip(1) {
+            values.push(field.parse::<f32>()?);
+        }
+        rows += 1;
+    }
+    Ok((header.clone(), Array2::from_shape_vec((rows, header.len()), values)?))
+}
+
+/// A matrix with the column names its file carried, if any.
+type NamedMatrix = (Option<Vec<String>>, Array2<f32>);
+
+fn read_matrix(path: &str) -> Result<NamedMatrix, Box<dyn Error>> {
+    if Path::new(path).extension().is_some_and(|ext| ext == "npy") {
+        Ok((None, read_npy(path)?))
+    } else {
+        let (header, matrix) = read_csv_matrix(path)?;
+        Ok((Some(header), matrix))
+    }
+}
+
+fn read_terms(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(reader.lines().collect::<Result<_, _>>()?)
+}
+
+/// Lays two topic-word matrices out over the union of their vocabularies, returning
+/// the union's terms in column order with the realigned matrices.
+pub(crate) fn align_vocabularies<'a>(ours: &Array2<f32>, our_terms: &[&'a str], reference: &Array2<f32>, reference_terms: &[&'a str]) -> (Vec<&'a str>, Array2<f32>, Array2<f32>) {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut union = Vec::new();
+    for term in our_terms.iter().chain(reference_terms).copied() {
+        if !index.contains_key(term) {
+            index.insert(term, union.len());
+            union.push(term);
+        }
+    }
+
+    let mut aligned_ours = Array2::<f32>::zeros((ours.nrows(), index.len()));
+    for (col, term) in our_terms.iter().enumerate() {
+        aligned_ours.column_mut(index[term]).assign(&ours.column(col));
+    }
+    let mut aligned_reference = Array2::<f32>::zeros((reference.nrows(), index.len()));
+    for (col, term) in reference_terms.iter().enumerate() {
+        aligned_reference.column_mut(index[term]).assign(&reference.column(col));
+    }
+    (union, aligned_ours, aligned_reference)
+}
+
+/// Pairs each of our topics with a distinct reference topic maximizing total word
+/// correlation (Hungarian algorithm). Returns `None` for topics left unmatched when
+/// we have more topics than the reference.
+pub(crate) fn match_topics(correlations: &Array2<f32>) -> Result<Vec<Option<usize>>, Box<dyn Error>> {
+    let weight = |c: f32| (c * WEIGHT_SCALE) as i64;
+    let (ours, reference) = correlations.dim();
+    if ours <= reference {
+        let rows: Vec<Vec<i64>> = correlations.rows().into_iter().map(|row| row.iter().map(|&c| weight(c)).collect()).collect();
+        let (_, assignment) = kuhn_munkres(&Matrix::from_rows(rows)?);
+        Ok(assignment.into_iter().map(Some).collect())
+    } else {
+        let rows: Vec<Vec<i64>> = correlations.columns().into_iter().map(|col| col.iter().map(|&c| weight(c)).collect()).collect();
+        let (_, assignment) = kuhn_munkres(&Matrix::from_rows(rows)?);
+        let mut matches = vec![None; ours];
+        for (reference_topic, topic) in assignment.into_iter().enumerate() {
+            matches[topic] = Some(reference_topic);
+        }
+        Ok(matches)
+    }
+}
+
+pub(crate) fn top_terms<'a>(weights: ArrayView1<f32>, terms: &[&'a str], top: usize) -> HashSet<&'a str> {
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.sort_unstable_by(|&a, &b| weights[b].total_cmp(&weights[a]));
+    order.into_iter().take(top).map(|idx| terms[idx]).collect()
+}
+
+/// Compares the saved model (and optionally its document-topic distributions) with a
+/// reference factorization of the same dataset, e.g. from scikit-learn, writing
+/// per-topic alignment to `output` and printing the averages.
+pub fn run(model_path: &str, distributions_csv: &str, reference_h: &str, reference_w: Option<&str>, reference_terms: Option<&str>, top: usize, output: &str) -> Result<(), Box<dyn Error>> {
+    let model = NmfModel::load(model_path)?;
+    let our_terms = model.vocab.terms();
+
+    let (header, h_reference) = read_matrix(reference_h)?;
+    let terms = match (reference_terms, header) {
+        (Some(path), _) => read_terms(path)?,
+        (None, Some(header)) => header,
+        (None, None) => return Err("A .npy topic-word matrix needs --reference-terms".into()),
+    };
+    if terms.len() != h_reference.ncols() {
+        return Err(format!("{} terms given for a topic-word matrix with {} columns", terms.len(), h_reference.ncols()).into());
+    }
+
+    let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+    let (union_terms, h_ours, h_reference) = align_vocabularies(&model.h, &our_terms, &h_reference, &terms);
+
+    let mut correlations = Array2::<f32>::zeros((h_ours.nrows(), h_reference.nrows()));
+    for ((i, j), c) in correlations.indexed_iter_mut() {
+        *c = pearson(h_ours.row(i), h_reference.row(j));
+    }
+    let matches = match_topics(&correlations)?;
+
+    let w = match reference_w {
+        Some(path) => {
+            let w_ours = modeling::load_topic_distributions(distributions_csv)?;
+            let (_, w_reference) = read_matrix(path)?;
+            if w_ours.nrows() != w_reference.nrows() {
+                return Err(format!("{} documents in {} but {} in {}", w_ours.nrows(), distributions_csv, w_reference.nrows(), path).into());
+            }
+            Some((w_ours, w_reference))
+        }
+        None => None,
+    };
+
+    let mut results = Vec::new();
+    for (topic, reference_topic) in matches.into_iter().enumerate() {
+        let Some(reference_topic) = reference_topic else {
+            progress!("Topic {} has no reference counterpart", topic);
+            continue;
+        };
+        let ours_top = top_terms(h_ours.row(topic), &union_terms, top);
+        let reference_top = top_terms(h_reference.row(reference_topic), &union_terms, top);
+        results.push(TopicMatch {
+            topic,
+            reference_topic,
+            word_correlation: correlations[[topic, reference_topic]],
+            top_word_overlap: ours_top.intersection(&reference_top).count() as f32 / top.max(1) as f32,
+            document_correlation: w.as_ref().map(|(w_ours, w_reference)| {
+                pearson(w_ours.column(topic), w_reference.column(reference_topic))
+            }),
+        });
+    }
+
+    let mut wtr = csv::Writer::from_path(output)?;
+    for result in &results {
+        wtr.serialize(result)?;
+    }
+    wtr.flush()?;
+
+    for result in &results {
+        progress!("Topic {} ~ reference {}: word r = {:.3}, top-{} overlap = {:.2}{}",
+            result.topic, result.reference_topic, result.word_correlation, top, result.top_word_overlap,
+            result.document_correlation.map_or(String::new(), |r| format!(", document r = {:.3}", r)));
+    }
+    progress!("Mean word correlation: {:.3}", mean(results.iter().map(|r| r.word_correlation)));
+    progress!("Mean top-{} overlap: {:.3}", top, mean(results.iter().map(|r| r.top_word_overlap)));
+    if w.is_some() {
+        progress!("Mean document correlation: {:.3}", mean(results.iter().filter_map(|r| r.document_correlation)));
+    }
+    progress!("Per-topic alignment written to {}", output);
+    console::artifact("validation", output);
+    console::metric("mean_word_correlation", mean(results.iter().map(|r| r.word_correlation)));
+    console::metric("mean_top_word_overlap", mean(results.iter().map(|r| r.top_word_overlap)));
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// Mapping from terms to their column in the document-term matrix.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Vocabulary {
+    #[serde(serialize_with = "serialize_sorted")]
+    index: HashMap<String, usize>,
+}
+
+/// Writes the terms in sorted order, so saved models are byte-for-byte reproducible.
+fn serialize_sorted<S: Serializer>(index: &HashMap<String, usize>, serializer: S) -> Result<S::Ok, S::Error> {
+    index.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl Vocabulary {
+    /// Keeps the terms occurring in at least `min_df` documents, except `excluded` ones.
+    pub fn build(doc_counts: HashMap<String, usize>, min_df: usize, excluded: &[String]) -> Vocabulary {
+        let mut index = HashMap::new();
+        let mut next_idx = 0;
+        // Columns in term order, not the map's, so the same corpus always gets the same matrix
+        let mut doc_counts: Vec<(String, usize)> = doc_counts.into_iter().collect();
+        doc_counts.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for (token, count) in doc_counts {
+            if count >= min_df && !excluded.contains(&token) {
+                index.insert(token, next_idx);
+                next_idx += 1;
+            }
+        }
+        Vocabulary { index }
+    }
+
+    /// Adds the terms of `doc_counts` occurring in at least `min_df` documents that
+    /// aren't in the vocabulary yet, except `excluded` ones, as `build` would. They take
+    /// the columns after the existing ones in term order, so known terms keep their
+    /// columns and models over them stay valid. Returns the added terms in column order.
+    pub fn update(&mut self, doc_counts: &HashMap<String, usize>, min_df: usize, excluded: &[String]) -> Vec<String> {
+        let mut added: Vec<String> = doc_counts
+            .iter()
+            .filter(|&(token, &count)| count >= min_df && !self.contains(token) && !excluded.contains(token))
+            .map(|(token, _)| token.clone())
+            .collect();
+        added.sort_unstable();
+        for token in &added {
+            self.index.insert(token.clone(), self.index.len());
+        }
+        added
+    }
+
+    pub fn get(&self, token: &str) -> Option<usize> {
+        self.index.get(token).copied()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.index.contains_key(token)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.index.iter().map(|(token, &idx)| (token.as_str(), idx))
+    }
+
+    /// Terms ordered by column index.
+    pub fn terms(&self) -> Vec<&str> {
+        let mut terms = vec![""; self.len()];
+        for (token, idx) in self.iter() {
+            terms[idx] = token;
+        }
+        terms
+    }
+
+    /// Writes one term per line, in column order.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for term in self.terms() {
+            writeln!(writer, "{}", term)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a vocabulary written by `save`; line `i` becomes column `i`.
+    pub fn load(path: &str) -> Result<Vocabulary> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut index = HashMap::new();
+        for (idx, line) in reader.lines().enumerate() {
+            index.insert(line?, idx);
+        }
+        Ok(Vocabulary { index })
+    }
+}
+
+/// Rules dropping junk terms that only show once the whole corpus is counted, applied
+/// to the vocabulary terms (after stemming) before the `min_df` cut-off.
+#[derive(Debug, Clone, Default)]
+pub struct VocabularyFilter {
+    /// Terms matching any of these anywhere are dropped, e.g. `^[a-z]{1,2}$`
+    pub exclude_patterns: Vec<Regex>,
+    /// Drop terms with a character repeated this many times in a row, e.g. 3 for "aaah"
+    pub max_repeat: Option<usize>,
+    /// Terms always dropped
+    pub blocklist: HashSet<String>,
+    /// Only these terms are kept when given
+    pub allowlist: Option<HashSet<String>>,
+}
+
+impl VocabularyFilter {
+    /// Reads a block- or allowlist: one term per line, blank lines and `#` comments skipped.
+    pub fn load_list(path: &str) -> Result<HashSet<String>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut terms = HashSet::new();
+        for line in reader.lines() {
+            let line = line?;
+            let term = line.trim();
+            if !term.is_empty() && !term.starts_with('#') {
+                terms.insert(term.to_string());
+            }
+        }
+        Ok(terms)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exclude_patterns.is_empty() && self.max_repeat.is_none() && self.blocklist.is_empty() && self.allowlist.is_none()
+    }
+
+    pub fn keeps(&self, term: &str) -> bool {
+        self.allowlist.as_ref().is_none_or(|allowed| allowed.contains(term))
+            && !self.blocklist.contains(term)
+            && self.max_repeat.is_none_or(|max| longest_run(term) < max)
+            && !self.exclude_patterns.iter().any(|pattern| pattern.is_match(term))
+    }
+}
+
+/// Length of the longest run of one repeated character in `term`.
+fn longest_run(term: &str) -> usize {
+    let mut chars = term.chars();
+    let Some(mut previous) = chars.next() else { return 0 };
+    let (mut run, mut longest) = (1, 1);
+    for c in chars {
+        run = if c == previous { run + 1 } else { 1 };
+        longest = longest.max(run);
+        previous = c;
+    }
+    longest
+}
+
+pub fn document_frequencies(documents: &[Vec<String>]) -> HashMap<String, usize> {
+    let mut doc_counts = HashMap::new();
+    add_document_frequencies(&mut doc_counts, documents);
+    doc_counts
+}
+
+/// Counts `documents` into the document frequencies of a growing corpus.
+pub fn add_document_frequencies(doc_counts: &mut HashMap<String, usize>, documents: &[Vec<String>]) {
+    for doc in documents {
+        let unique_tokens: HashSet<_> = doc.iter().collect();
+        for token in unique_tokens {
+            *doc_counts.entry(token.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Terms occurring in more than `max_df_ratio` of the documents, sorted.
+pub fn frequent_terms(doc_counts: &HashMap<String, usize>, num_docs: usize, max_df_ratio: f32) -> Vec<String> {
+    let limit = max_df_ratio * num_docs as f32;
+    let mut terms: Vec<String> = doc_counts.iter()
+        .filter(|&(_, &count)| count as f32 > limit)
+        .map(|(token, _)| token.clone())
+        .collect();
+    terms.sort();
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workdir::Workdir;
+
+    fn tokens(docs: &[&str]) -> Vec<Vec<String>> {
+        docs.iter().map(|doc| doc.split_whitespace().map(str::to_string).collect()).collect()
+    }
+
+    #[test]
+    fn document_frequencies_count_each_document_once() {
+        let doc_counts = document_frequencies(&tokens(&["pear pear apple", "pear fig", "kiwi"]));
+        assert_eq!(doc_counts["pear"], 2);
+        assert_eq!(doc_counts["apple"], 1);
+        assert_eq!(doc_counts.len(), 4);
+    }
+
+    #[test]
+    fn build_keeps_terms_above_min_df_in_term_order() {
+        let doc_counts = document_frequencies(&tokens(&["pear apple kiwi fig", "pear apple kiwi", "pear kiwi"]));
+        let vocab = Vocabulary::build(doc_counts, 2, &["kiwi".to_string()]);
+        assert_eq!(vocab.terms(), ["apple", "pear"]);
+        assert_eq!(vocab.get("pear"), Some(1));
+        assert!(!vocab.contains("fig"));
+        assert!(!vocab.contains("kiwi"));
+    }
+
+    #[test]
+    fn frequent_terms_are_above_the_ratio() {
+        let doc_counts = document_frequencies(&tokens(&["the pear", "the apple", "the fig pear"]));
+        assert_eq!(frequent_terms(&doc_counts, 3, 0.5), ["pear", "the"]);
+        assert_eq!(frequent_terms(&doc_counts, 3, 0.9), ["the"]);
+    }
+
+    #[test]
+    fn save_and_load_keep_columns() {
+        let doc_counts = document_frequencies(&tokens(&["pear apple kiwi", "fig"]));
+        let vocab = Vocabulary::build(doc_counts, 1, &[]);
+        let workdir = Workdir::temp().unwrap();
+        let path = workdir.path("vocabulary.txt");
+        vocab.save(&path).unwrap();
+        let loaded = Vocabulary::load(&path).unwrap();
+        assert_eq!(loaded.terms(), vocab.terms());
+        std::fs::remove_dir_all(workdir.root()).unwrap();
+    }
+
+    #[test]
+    fn update_appends_new_terms_after_existing_columns() {
+        let mut vocab = Vocabulary::build(document_frequencies(&tokens(&["pear apple", "pear apple"])), 2, &[]);
+        let grown = document_frequencies(&tokens(&["pear apple cherry kiwi fig", "pear banana kiwi cherry", "banana kiwi"]));
+        let added = vocab.update(&grown, 2, &["kiwi".to_string()]);
+        assert_eq!(added, ["banana", "cherry"]);
+        // "apple" is now under min_df, but known terms are never dropped
+        assert_eq!(vocab.terms(), ["apple", "pear", "banana", "cherry"]);
+        assert!(vocab.update(&grown, 2, &["kiwi".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::lineage::{self, LINEAGE_FILE};
+use crate::modeling::{self, ModelConfig};
+use crate::preprocessing::{self, PreprocessConfig, Preprocessor};
+use crate::prometheus::{self, PipelineMetrics};
+use crate::readers;
+use crate::vocabulary;
+use crate::workdir::{FILES_FILE, TOKENS_FILE};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// New files are processed once they have gone this long without an event, so
+// documents that are still being written are not read half-finished.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// The grown vocabulary in the workdir, which refits keep the columns of.
+pub const VOCABULARY_FILE: &str = "watch_vocabulary.txt";
+
+/// Fits a model on the documents already in `input_dir`, then keeps watching
+/// the directory and appends the topic distributions of newly added documents.
+/// With `refit_every`, the model is refit on all documents, warm-started from its
+/// topics, each time that many have been added, and the new topics' lineage from
+/// the old ones is appended to `LINEAGE_FILE`. With `grow_vocabulary`, terms of the
+/// new documents that reach `min_df` join the model's vocabulary as they arrive,
+/// their topic weights fit to the new documents, and refits keep the grown
+/// vocabulary's columns instead of rebuilding it. With `metrics_addr`, Prometheus
+/// metrics are served there while watching.
+pub fn run(input_dir: &str, refit_every: Option<usize>, grow_vocabulary: bool, metrics_addr: Option<&str>, preprocess_config: &PreprocessConfig, config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let metrics = Arc::new(PipelineMetrics::default());
+    if let Some(addr) = metrics_addr {
+        prometheus::spawn(metrics.clone(), addr)?;
+    }
+    let summary = preprocessing::start(input_dir, preprocess_config)?;
+    metrics.add_documents(summary.documents);
+    let tokens_csv = preprocess_config.workdir.path(&preprocess_config.compression.path(TOKENS_FILE));
+    let files_csv = preprocess_config.workdir.path(FILES_FILE);
+    let documents = modeling::load_documents(&tokens_csv)?;
+    let started = Instant::now();
+    let modeling::Fit { mut model, w, .. } = modeling::fit(&documents, config)?;
+    metrics.record_fit(&model, documents.len(), started.elapsed());
+    let model_json = config.workdir.path(modeling::MODEL_FILE);
+    modeling::save_document_topics(&w, config)?;
+    model.save(&model_json)?;
+
+    progress!("Initial model fitted on {} documents:", documents.len());
+    for topic in &model.topics(config.top_words) {
+        progress!("  {}", topic);
+    }
+
+    let mut seen: HashSet<PathBuf> = preprocessing::input_files(input_dir)?
+        .iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .collect();
+    let mut next_index = documents.len();
+    let mut added_since_fit = 0;
+    let mut doc_counts: HashMap<String, usize> = if grow_vocabulary { vocabulary::document_frequencies(&documents) } else { HashMap::new() };
+    let vocabulary_file = config.workdir.path(VOCABULARY_FILE);
+    let preprocessor = Preprocessor::new(preprocess_config)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(Path::new(input_dir), RecursiveMode::Recursive)?;
+    progress!("Watching {} for new documents...", input_dir);
+
+    // Outputs written into the watched directory when a workdir lies inside it
+    let workdirs: Vec<PathBuf> = [preprocess_config.workdir.root(), config.workdir.root()]
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .collect();
+    // Files with events, and when the last one arrived
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        let timeout = pending.values().min().map_or(SETTLE_TIME, |last| SETTLE_TIME.saturating_sub(last.elapsed()));
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    let now = Instant::now();
+                    for path in event.paths.iter().filter_map(|path| path.canonicalize().ok()) {
+                        if !workdirs.iter().any(|workdir| path.starts_with(workdir)) {
+                            pending.insert(path, now);
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Settled files are processed even while events for others keep arriving
+        let mut settled = Vec::new();
+        pending.retain(|path, last| {
+            let settling = last.elapsed() < SETTLE_TIME;
+            if !settling {
+                settled.push(path.clone());
+            }
+            settling
+        });
+        let mut new_files: Vec<PathBuf> = settled
+            .into_iter()
+            .filter(|path| readers::is_document(path))
+            .filter(|path| seen.insert(path.clone()))
+            .collect();
+        if new_files.is_empty() {
+            continue;
+        }
+        new_files.sort();
+
+        let new_documents = preprocessing::append_files(&new_files, next_index as u32, &tokens_csv, &files_csv, &preprocessor)?;
+        if new_documents.is_empty() {
+            continue;
+        }
+        next_index += new_documents.len();
+        let added = if grow_vocabulary {
+            vocabulary::add_document_frequencies(&mut doc_counts, &new_documents);
+            model.grow_vocabulary(&doc_counts, next_index, config)?
+        } else {
+            0..0
+        };
+        let w = model.transform(&new_documents, config.max_iter, config.tol, config.init_seed());
+        modeling::append_document_topics(&w, next_index - new_documents.len(), config)?;
+        metrics.add_documents(new_documents.len());
+        if !added.is_empty() {
+            progress!("Added {} terms to the vocabulary, now {}", added.len(), model.vocab.len());
+            model.fit_terms(&new_documents, &w, added, config.max_iter, config.tol, config.init_seed());
+            model.save(&model_json)?;
+        }
+
+        added_since_fit += new_documents.len();
+        progress!("Processed {} new document(s), {} total", new_documents.len(), next_index);
+
+        if refit_every.is_some_and(|every| added_since_fit >= every) {
+            let documents = modeling::load_documents(&tokens_csv)?;
+            let mut refit_config = ModelConfig { warm_start: Some(model_json.clone()), ..config.clone() };
+            if grow_vocabulary {
+                model.vocab.save(&vocabulary_file)?;
+                refit_config.vocab_path = Some(vocabulary_file.clone());
+            }
+            let started = Instant::now();
+            let modeling::Fit { model: refit, w, .. } = modeling::fit(&documents, &refit_config)?;
+            metrics.record_fit(&refit, documents.len(), started.elapsed());
+            progress!("Refit the model on {} documents", documents.len());
+            lineage::record(&model, &refit, &config.workdir.path(LINEAGE_FILE), config.top_words)?;
+            modeling::save_document_topics(&w, config)?;
+            refit.save(&model_json)?;
+            model = refit;
+            added_since_fit = 0;
+        }
+    }
+
+    Ok(())
+}
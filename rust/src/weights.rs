@@ -0,0 +1,45 @@
+use ndarray::Array1;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Weight of each document in the factorization's objective, from a CSV with a key
+/// column (file path, file name or corpus row id) followed by a `weight` column.
+/// Documents it doesn't list weigh 1.
+#[derive(Debug, Default)]
+pub struct DocumentWeights {
+    by_key: HashMap<String, f32>,
+}
+
+impl DocumentWeights {
+    pub fn load(path: &str) -> Result<DocumentWeights, Box<dyn Error>> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let weight_idx = rdr.headers()?
+            .iter()
+            .position(|h| h == "weight")
+            .ok_or_else(|| format!("{} has no 'weight' column", path))?;
+        let mut by_key = HashMap::new();
+        for (row, result) in rdr.records().enumerate() {
+            let record = result?;
+            let value = record.get(weight_idx).unwrap_or_default();
+            let weight = value.trim().parse::<f32>().ok()
+                .filter(|weight| weight.is_finite() && *weight >= 0.0)
+                .ok_or_else(|| format!("Row {} of {}: '{}' is not a non-negative weight", row + 1, path, value))?;
+            by_key.insert(record.get(0).unwrap_or_default().to_string(), weight);
+        }
+        Ok(DocumentWeights { by_key })
+    }
+
+    /// Weight of a document by its files.csv path or id, or by its file name.
+    pub fn of(&self, key: &str) -> Option<f32> {
+        let by_name = || Path::new(key).file_name().and_then(|name| self.by_key.get(name.to_string_lossy().as_ref()));
+        self.by_key.get(key).or_else(by_name).copied()
+    }
+
+    /// Weights of the documents listed in files.csv order, and how many the file lists.
+    pub fn for_documents(&self, keys: &[String]) -> (Array1<f32>, usize) {
+        let weights: Vec<Option<f32>> = keys.iter().map(|key| self.of(key)).collect();
+        let listed = weights.iter().filter(|weight| weight.is_some()).count();
+        (weights.into_iter().map(|weight| weight.unwrap_or(1.0)).collect(), listed)
+    }
+}
@@ -0,0 +1,79 @@
+use crate::cluster;
+use crate::console;
+use crate::modeling::{self, nmf, FitResult, ModelConfig, NmfOptions};
+use crate::workdir::TOKENS_FILE;
+use csv::Writer;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+/// Dominant cluster of each term, with its share of the term's memberships
+pub const WORD_CLUSTERS_FILE: &str = "word_clusters.csv";
+/// Every term's normalized membership in every cluster
+pub const WORD_MEMBERSHIPS_FILE: &str = "word_memberships.csv";
+/// One lexicon per cluster: its terms one per line, strongest first
+pub const LEXICONS_DIR: &str = "lexicons";
+
+/// Factorizes the transposed TF-IDF matrix of the workdir's tokens, Vᵀ ≈ WH, so the
+/// rows of W are the terms' memberships in `config.k` word clusters and H spreads the
+/// clusters over the documents. Writes each term's dominant cluster to
+/// `WORD_CLUSTERS_FILE`, all memberships to `WORD_MEMBERSHIPS_FILE` and a word list
+/// per cluster under `LEXICONS_DIR`.
+pub fn run(config: &ModelConfig) -> Result<(), Box<dyn Error>> {
+    let workdir = &config.workdir;
+    let documents = modeling::load_documents(&workdir.path(&config.compression.path(TOKENS_FILE)))?;
+    let (vocab, _, tfidf) = modeling::vectorize(&documents, config)?;
+    if vocab.is_empty() {
+        return Err("The vocabulary is empty, no words to cluster".into());
+    }
+    let FitResult { w, error, .. } = nmf(&tfidf.t().to_owned(), NmfOptions { background: None, ..NmfOptions::new(config) });
+    progress!("Clustered {} words into {} clusters, {:.1}% of variance explained", vocab.len(), config.k, error.explained_variance * 100.0);
+
+    // Terms of each cluster as (term, membership share, weight), strongest first
+    let terms = vocab.terms();
+    let mut clusters: Vec<Vec<(&str, f32, f32)>> = vec![Vec::new(); config.k];
+    for (term, row) in terms.iter().zip(w.rows()) {
+        let (cluster, share) = cluster::dominant_topic(row);
+        clusters[cluster].push((term, share, row[cluster]));
+    }
+    for members in &mut clusters {
+        members.sort_by(|a, b| b.2.total_cmp(&a.2));
+    }
+
+    let mut wtr = Writer::from_path(workdir.path(WORD_CLUSTERS_FILE))?;
+    wtr.write_record(["Term", "Cluster", "Membership", "Weight"])?;
+    for (cluster, members) in clusters.iter().enumerate() {
+        for &(term, share, weight) in members {
+            wtr.serialize((term, cluster, share, weight))?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(workdir.path(WORD_MEMBERSHIPS_FILE))?;
+    let mut header = vec!["Term".to_string()];
+    header.extend((0..config.k).map(|cluster| format!("cluster_{}", cluster)));
+    wtr.write_record(&header)?;
+    for (term, row) in terms.iter().zip(w.rows()) {
+        let total = row.sum();
+        let mut record = vec![term.to_string()];
+        record.extend(row.iter().map(|&weight| format!("{:.6}", if total > 0.0 { weight / total } else { 0.0 })));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+
+    let lexicons_dir = workdir.path(LEXICONS_DIR);
+    std::fs::create_dir_all(&lexicons_dir)?;
+    for (cluster, members) in clusters.iter().enumerate() {
+        let mut file = std::fs::File::create(Path::new(&lexicons_dir).join(format!("cluster_{}.txt", cluster)))?;
+        for &(term, _, _) in members {
+            writeln!(file, "{}", term)?;
+        }
+        let top: Vec<&str> = members.iter().take(config.top_words.count).map(|&(term, _, _)| term).collect();
+        progress!("  Cluster {} ({} words): {}", cluster, members.len(), top.join(" "));
+    }
+    progress!("Word clusters written to {}, lexicons to {}", workdir.path(WORD_CLUSTERS_FILE), lexicons_dir);
+    console::artifact("word_clusters", workdir.path(WORD_CLUSTERS_FILE));
+    console::artifact("word_memberships", workdir.path(WORD_MEMBERSHIPS_FILE));
+    console::artifact("lexicons", &lexicons_dir);
+    Ok(())
+}
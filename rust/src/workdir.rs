@@ -0,0 +1,47 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const TOKENS_FILE: &str = "tokens.csv";
+pub const FILES_FILE: &str = "files.csv";
+pub const ENCODINGS_FILE: &str = "converted_files.csv";
+pub const DISTRIBUTIONS_FILE: &str = "document_topic_distributions.csv";
+pub const CLUSTERS_DIR: &str = "clusters";
+pub const TOPIC_EXAMPLES_DIR: &str = "topic_examples";
+
+/// Directory a run writes its intermediate and output files to, so concurrent runs
+/// and datasets don't overwrite each other. Defaults to the current directory.
+#[derive(Debug, Clone)]
+pub struct Workdir {
+    root: PathBuf,
+}
+
+impl Workdir {
+    /// Uses `root`, creating it if needed.
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Workdir> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Workdir { root })
+    }
+
+    /// Creates a fresh directory under the system temp directory.
+    pub fn temp() -> io::Result<Workdir> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        Workdir::new(std::env::temp_dir().join(format!("nmf-pipeline-{}-{}", std::process::id(), nanos)))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of `name` inside the working directory.
+    pub fn path(&self, name: &str) -> String {
+        self.root.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Default for Workdir {
+    fn default() -> Self {
+        Workdir { root: PathBuf::from(".") }
+    }
+}
@@ -0,0 +1,77 @@
+//! End to end on a seeded synthetic corpus: preprocess it, fit a model and check with
+//! `validate` that the fit finds the topics the corpus was generated from.
+
+use preproccess::generate::{self, SyntheticConfig};
+use preproccess::modeling::{self, ModelConfig};
+use preproccess::preprocessing::{self, PreprocessConfig};
+use preproccess::validate;
+use preproccess::workdir::{Workdir, DISTRIBUTIONS_FILE, TOKENS_FILE};
+use std::path::Path;
+
+const TOPICS: usize = 3;
+
+/// A row of the validation CSV.
+#[derive(serde::Deserialize)]
+struct TopicMatch {
+    topic: usize,
+    reference_topic: usize,
+    word_correlation: f32,
+    top_word_overlap: f32,
+    document_correlation: Option<f32>,
+}
+
+fn corpus_config(seed: u64) -> SyntheticConfig {
+    SyntheticConfig { documents: 200, topics: TOPICS, vocabulary: 150, mean_length: 60.0, seed, ..SyntheticConfig::default() }
+}
+
+#[test]
+fn same_seed_generates_same_corpus() {
+    let workdir = Workdir::temp().unwrap();
+    let (first, second) = (workdir.path("first"), workdir.path("second"));
+    generate::run(&first, &corpus_config(7)).unwrap();
+    generate::run(&second, &corpus_config(7)).unwrap();
+
+    for file in [generate::TRUE_TOPIC_WORDS_FILE, generate::TRUE_DOCUMENT_TOPICS_FILE, "documents/doc_000.txt", "documents/doc_199.txt"] {
+        let read = |dir: &str| std::fs::read_to_string(Path::new(dir).join(file)).unwrap();
+        assert_eq!(read(&first), read(&second), "{} differs between runs with the same seed", file);
+    }
+    std::fs::remove_dir_all(workdir.root()).unwrap();
+}
+
+#[test]
+fn fit_recovers_planted_topics() {
+    let workdir = Workdir::temp().unwrap();
+    let corpus = workdir.path("corpus");
+    generate::run(&corpus, &corpus_config(7)).unwrap();
+
+    let preprocess_config = PreprocessConfig { workdir: workdir.clone(), ..PreprocessConfig::default() };
+    let documents_dir = Path::new(&corpus).join(generate::DOCUMENTS_DIR);
+    let summary = preprocessing::start(&documents_dir.to_string_lossy(), &preprocess_config).unwrap();
+    assert_eq!(summary.documents, 200);
+
+    let config = ModelConfig { k: TOPICS, deterministic: true, workdir: workdir.clone(), ..ModelConfig::default() };
+    let documents = modeling::load_documents(&workdir.path(TOKENS_FILE)).unwrap();
+    let fit = modeling::fit(&documents, &config).unwrap();
+    let model_path = workdir.path(modeling::MODEL_FILE);
+    fit.model.save(&model_path).unwrap();
+    modeling::save_document_topics(&fit.w, &config).unwrap();
+
+    let truth = |file: &str| Path::new(&corpus).join(file).to_string_lossy().into_owned();
+    let output = workdir.path("validation.csv");
+    validate::run(&model_path, &workdir.path(DISTRIBUTIONS_FILE), &truth(generate::TRUE_TOPIC_WORDS_FILE),
+        Some(&truth(generate::TRUE_DOCUMENT_TOPICS_FILE)), None, 10, &output).unwrap();
+
+    let mut rdr = csv::Reader::from_path(&output).unwrap();
+    let matches: Vec<TopicMatch> = rdr.deserialize().collect::<Result<_, _>>().unwrap();
+    assert_eq!(matches.len(), TOPICS);
+    let mut reference_topics: Vec<usize> = matches.iter().map(|m| m.reference_topic).collect();
+    reference_topics.sort_unstable();
+    assert_eq!(reference_topics, (0..TOPICS).collect::<Vec<_>>());
+    for m in &matches {
+        assert!(m.word_correlation > 0.8, "topic {} matches planted topic {} with word r = {}", m.topic, m.reference_topic, m.word_correlation);
+        assert!(m.top_word_overlap >= 0.7, "topic {} shares {} of its top words with planted topic {}", m.topic, m.top_word_overlap, m.reference_topic);
+        let document_correlation = m.document_correlation.unwrap();
+        assert!(document_correlation > 0.8, "topic {} matches planted topic {} with document r = {}", m.topic, m.reference_topic, document_correlation);
+    }
+    std::fs::remove_dir_all(workdir.root()).unwrap();
+}